@@ -72,3 +72,64 @@ pub struct CampaignDefs {
     #[field("CAMPAIGN_DEF", start)]
     pub campaign_defs: Vec<CampaignDef>,
 }
+
+#[cfg(test)]
+mod tests {
+    use shadow_company_tools::config::{Config, ConfigReader, ParseConfigError};
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let data = r#"
+            CAMPAIGN_DEF
+                BASENAME            training
+                TITLE               "Training Camp"
+                PLAYTEST_FUNDS      5000
+                MULTIPLAYER_FUNDS   1000 2000 3000
+                EMITTER_CONFIG      rain rain.txt
+                PRE_ACTION          SET_VAR a 1
+                PRECONDITION        COMPLETED intro
+
+            CAMPAIGN_DEF
+                BASENAME            angola
+                DISABLE_HELP_TIPS
+                POST_ACTION         GIVE_FUNDS 100
+        "#;
+
+        let mut reader = ConfigReader::new(std::io::Cursor::new(data)).unwrap();
+        let campaign_defs = CampaignDefs::from_config(&mut reader).unwrap();
+        assert_eq!(campaign_defs.campaign_defs.len(), 2);
+
+        let training = &campaign_defs.campaign_defs[0];
+        assert_eq!(training.base_name, "training");
+        assert_eq!(training.title, "Training Camp");
+        assert_eq!(training.playtest_funds, 5000);
+        assert_eq!(training.multiplayer_funds, [1000, 2000, 3000]);
+        assert_eq!(training.emitter_configs[0].config, "rain.txt");
+        assert_eq!(training.pre_actions[0].params, ["a", "1"]);
+        assert_eq!(training.preconditions[0].name, "COMPLETED");
+        assert!(training.post_actions.is_empty());
+
+        let angola = &campaign_defs.campaign_defs[1];
+        assert_eq!(angola.base_name, "angola");
+        assert!(angola.disable_help_tips);
+        assert_eq!(angola.post_actions[0].name, "GIVE_FUNDS");
+        assert!(angola.pre_actions.is_empty());
+    }
+
+    #[test]
+    fn unknown_keys_are_errors() {
+        let data = r#"
+            CAMPAIGN_DEF
+                BASENAME    training
+                NOT_A_KEY   1
+        "#;
+
+        let mut reader = ConfigReader::new(std::io::Cursor::new(data)).unwrap();
+        let result = CampaignDefs::from_config(&mut reader);
+        assert!(
+            matches!(result, Err(ParseConfigError::InvalidKey(_, ref key)) if key == "NOT_A_KEY")
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! Bitmap fonts stored as greyscale .raw font sheets.
+//!
+//! The sheet is an alpha mask, the glyphs are white. The layout of the glyphs comes either from a
+//! SPRITE3D definition, where every frame is a glyph (SPRITEFRAME_DXRUN describes a row of glyphs
+//! with different widths), or from a grid of equally sized cells.
+
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+use shadow_company_tools::fixed_string::{decode_windows_1252_char, encode_windows_1252_char};
+
+use crate::{Sprite3d, SpriteRect};
+
+/// The layout of a sheet with one glyph per cell, ordered left to right, top to bottom.
+#[derive(Clone, Debug)]
+pub struct FontGrid {
+    pub cell_width: u32,
+    pub cell_height: u32,
+    /// The character of the first cell.
+    pub first_char: char,
+    /// The number of glyphs. Defaults to all the cells on the sheet.
+    pub count: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Glyph {
+    pub char: char,
+    /// The glyph on the sheet.
+    pub rect: SpriteRect,
+    /// How far to move the cursor after drawing the glyph.
+    pub x_advance: i32,
+}
+
+pub struct Font {
+    pub name: String,
+    pub sheet: GrayImage,
+    /// The distance between lines, the height of the tallest glyph.
+    pub line_height: i32,
+    /// The distance from the top of a line to the baseline.
+    pub base: i32,
+    pub glyphs: Vec<Glyph>,
+}
+
+/// Pixels below this value are considered empty when trimming glyphs.
+const INK_THRESHOLD: u8 = 16;
+
+impl Font {
+    /// Create a font from a list of glyph rectangles, the glyph for `first_char` first. The glyphs
+    /// are advanced by their width, plus `spacing` pixels.
+    ///
+    /// The glyphs follow the Windows-1252 code page, so `first_char` should be part of it, otherwise
+    /// the glyphs start at `?`. Glyphs past the end of the code page are dropped.
+    pub fn from_rects(
+        name: impl Into<String>,
+        sheet: GrayImage,
+        rects: &[SpriteRect],
+        first_char: char,
+        spacing: i32,
+    ) -> Self {
+        let first_code = encode_windows_1252_char(first_char).unwrap_or(b'?');
+        let glyphs = rects
+            .iter()
+            .zip(first_code..=u8::MAX)
+            .map(|(rect, code)| Glyph {
+                char: decode_windows_1252_char(code),
+                rect: *rect,
+                x_advance: rect.width + spacing,
+            })
+            .collect::<Vec<_>>();
+
+        let line_height = glyphs.iter().map(|g| g.rect.height).max().unwrap_or(0);
+        let base = baseline(&sheet, &glyphs).unwrap_or(line_height);
+
+        Self {
+            name: name.into(),
+            sheet,
+            line_height,
+            base,
+            glyphs,
+        }
+    }
+
+    /// Create a font from a SPRITE3D definition. Every frame is a glyph.
+    pub fn from_sprite_3d(
+        sprite: &Sprite3d,
+        sheet: GrayImage,
+        first_char: char,
+        spacing: i32,
+    ) -> Self {
+        Self::from_rects(
+            sprite.name.clone(),
+            sheet,
+            &sprite.frame_rects(),
+            first_char,
+            spacing,
+        )
+    }
+
+    /// Create a font from a grid of cells. The empty columns on the left and right of every glyph
+    /// are trimmed, so the glyphs are proportional. Empty cells, like the space, are a third of the
+    /// cell wide.
+    pub fn from_grid(
+        name: impl Into<String>,
+        sheet: GrayImage,
+        grid: &FontGrid,
+        spacing: i32,
+    ) -> Self {
+        let columns = sheet.width() / grid.cell_width.max(1);
+        let rows = sheet.height() / grid.cell_height.max(1);
+        let count = grid.count.unwrap_or(columns * rows).min(columns * rows);
+
+        let rects = (0..count)
+            .map(|i| {
+                let cell = SpriteRect {
+                    x: ((i % columns) * grid.cell_width) as i32,
+                    y: ((i / columns) * grid.cell_height) as i32,
+                    width: grid.cell_width as i32,
+                    height: grid.cell_height as i32,
+                };
+                trim_glyph(&sheet, cell).unwrap_or(SpriteRect {
+                    width: cell.width / 3,
+                    ..cell
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Self::from_rects(name, sheet, &rects, grid.first_char, spacing)
+    }
+
+    pub fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs.iter().find(|glyph| glyph.char == c)
+    }
+
+    /// The sheet as white glyphs with the sheet as the alpha channel, for the .png page.
+    pub fn page_image(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.sheet.width(), self.sheet.height(), |x, y| {
+            Rgba([255, 255, 255, self.sheet.get_pixel(x, y).0[0]])
+        })
+    }
+
+    /// Write the font in the AngelCode BMFont text format, with `page_file` as the only page.
+    pub fn write_fnt<W>(&self, writer: &mut W, page_file: &str) -> std::io::Result<()>
+    where
+        W: std::io::Write,
+    {
+        writeln!(
+            writer,
+            "info face=\"{}\" size={} bold=0 italic=0 charset=\"\" unicode=1 stretchH=100 smooth=0 aa=1 padding=0,0,0,0 spacing=0,0",
+            self.name, self.line_height
+        )?;
+        writeln!(
+            writer,
+            "common lineHeight={} base={} scaleW={} scaleH={} pages=1 packed=0",
+            self.line_height,
+            self.base,
+            self.sheet.width(),
+            self.sheet.height()
+        )?;
+        writeln!(writer, "page id=0 file=\"{}\"", page_file)?;
+        writeln!(writer, "chars count={}", self.glyphs.len())?;
+        for glyph in self.glyphs.iter() {
+            writeln!(
+                writer,
+                "char id={} x={} y={} width={} height={} xoffset=0 yoffset=0 xadvance={} page=0 chnl=15",
+                glyph.char as u32,
+                glyph.rect.x,
+                glyph.rect.y,
+                glyph.rect.width,
+                glyph.rect.height,
+                glyph.x_advance
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The size of `text` in pixels. Lines are separated by '\n'.
+    pub fn measure(&self, text: &str) -> (u32, u32) {
+        let width = text
+            .lines()
+            .map(|line| line.chars().map(|c| self.advance(c)).sum::<i32>())
+            .max()
+            .unwrap_or(0);
+        let height = text.lines().count() as i32 * self.line_height;
+        (width.max(0) as u32, height.max(0) as u32)
+    }
+
+    /// Render `text` in `color` on a transparent image that fits the text. Characters without a
+    /// glyph are skipped.
+    pub fn render_text(&self, text: &str, color: Rgba<u8>) -> RgbaImage {
+        let (width, height) = self.measure(text);
+        let mut image = RgbaImage::new(width.max(1), height.max(1));
+
+        for (line_index, line) in text.lines().enumerate() {
+            let mut x = 0;
+            let y = line_index as i32 * self.line_height;
+            for c in line.chars() {
+                if let Some(glyph) = self.glyph(c) {
+                    self.draw_glyph(&mut image, glyph, x, y, color);
+                }
+                x += self.advance(c);
+            }
+        }
+
+        image
+    }
+
+    fn advance(&self, c: char) -> i32 {
+        self.glyph(c).map(|glyph| glyph.x_advance).unwrap_or(0)
+    }
+
+    fn draw_glyph(&self, image: &mut RgbaImage, glyph: &Glyph, x: i32, y: i32, color: Rgba<u8>) {
+        for gy in 0..glyph.rect.height {
+            for gx in 0..glyph.rect.width {
+                let Some(Luma([coverage])) =
+                    pixel(&self.sheet, glyph.rect.x + gx, glyph.rect.y + gy)
+                else {
+                    continue;
+                };
+                let (dx, dy) = (x + gx, y + gy);
+                if dx < 0 || dy < 0 || dx as u32 >= image.width() || dy as u32 >= image.height() {
+                    continue;
+                }
+
+                let alpha = (color.0[3] as u32 * coverage as u32 / 255) as u8;
+                let pixel = image.get_pixel_mut(dx as u32, dy as u32);
+                if alpha > pixel.0[3] {
+                    *pixel = Rgba([color.0[0], color.0[1], color.0[2], alpha]);
+                }
+            }
+        }
+    }
+}
+
+fn pixel(sheet: &GrayImage, x: i32, y: i32) -> Option<Luma<u8>> {
+    (x >= 0 && y >= 0 && (x as u32) < sheet.width() && (y as u32) < sheet.height())
+        .then(|| *sheet.get_pixel(x as u32, y as u32))
+}
+
+fn has_ink(sheet: &GrayImage, x: i32, y: i32) -> bool {
+    pixel(sheet, x, y)
+        .map(|p| p.0[0] >= INK_THRESHOLD)
+        .unwrap_or(false)
+}
+
+/// Shrink a cell horizontally to the columns with ink. Returns `None` for empty cells.
+fn trim_glyph(sheet: &GrayImage, cell: SpriteRect) -> Option<SpriteRect> {
+    let column_has_ink = |x: i32| (cell.y..cell.y + cell.height).any(|y| has_ink(sheet, x, y));
+
+    let left = (cell.x..cell.x + cell.width).find(|&x| column_has_ink(x))?;
+    let right = (cell.x..cell.x + cell.width)
+        .rev()
+        .find(|&x| column_has_ink(x))?;
+
+    Some(SpriteRect {
+        x: left,
+        width: right - left + 1,
+        ..cell
+    })
+}
+
+/// The baseline is the most common bottom of the digits and upper case letters, which don't have
+/// descenders. Fonts without them use all of their glyphs.
+fn baseline(sheet: &GrayImage, glyphs: &[Glyph]) -> Option<i32> {
+    let bottom = |glyph: &Glyph| {
+        let rect = glyph.rect;
+        (rect.y..rect.y + rect.height)
+            .rev()
+            .find(|&y| (rect.x..rect.x + rect.width).any(|x| has_ink(sheet, x, y)))
+            .map(|y| y - rect.y + 1)
+    };
+
+    let mut bottoms = glyphs
+        .iter()
+        .filter(|glyph| glyph.char.is_ascii_uppercase() || glyph.char.is_ascii_digit())
+        .filter_map(bottom)
+        .collect::<Vec<_>>();
+    if bottoms.is_empty() {
+        bottoms = glyphs.iter().filter_map(bottom).collect();
+    }
+    bottoms.sort_unstable();
+
+    let mut best = None;
+    for chunk in bottoms.chunk_by(|a, b| a == b) {
+        if best.map(|(_, count)| chunk.len() > count).unwrap_or(true) {
+            best = Some((chunk[0], chunk.len()));
+        }
+    }
+    best.map(|(bottom, _)| bottom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_font() {
+        // Two 4x4 cells: "A" is a 2 pixel wide bar in the middle, "B" is empty.
+        let sheet = GrayImage::from_fn(8, 4, |x, y| {
+            Luma([if (1..3).contains(&x) && y < 3 { 255 } else { 0 }])
+        });
+        let grid = FontGrid {
+            cell_width: 4,
+            cell_height: 4,
+            first_char: 'A',
+            count: None,
+        };
+        let font = Font::from_grid("test", sheet, &grid, 1);
+
+        assert_eq!(font.line_height, 4);
+        assert_eq!(font.base, 3);
+        let a = font.glyph('A').unwrap();
+        assert_eq!((a.rect.x, a.rect.width, a.x_advance), (1, 2, 3));
+        let b = font.glyph('B').unwrap();
+        assert_eq!((b.rect.x, b.rect.width, b.x_advance), (4, 1, 2));
+
+        let mut fnt = vec![];
+        font.write_fnt(&mut fnt, "test.png").unwrap();
+        let fnt = String::from_utf8(fnt).unwrap();
+        assert!(fnt.contains("common lineHeight=4 base=3 scaleW=8 scaleH=4"));
+        assert!(fnt.contains("char id=65 x=1 y=0 width=2 height=4 xoffset=0 yoffset=0 xadvance=3"));
+
+        let text = font.render_text("ABA", Rgba([255, 0, 0, 255]));
+        assert_eq!(text.dimensions(), (8, 4));
+        assert_eq!(text.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(text.get_pixel(2, 0).0[3], 0);
+        assert_eq!(text.get_pixel(5, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn windows_1252_glyphs() {
+        let rect = |x| SpriteRect {
+            x,
+            y: 0,
+            width: 1,
+            height: 1,
+        };
+        let rects = (0..4).map(rect).collect::<Vec<_>>();
+        let font = Font::from_rects("test", GrayImage::new(4, 1), &rects, '\u{FE}', 0);
+
+        // There are no glyphs after 0xFF.
+        let chars = font.glyphs.iter().map(|g| g.char).collect::<Vec<_>>();
+        assert_eq!(chars, ['\u{FE}', '\u{FF}']);
+
+        // 0x80 is the euro sign, not a C1 control character.
+        let font = Font::from_rects("test", GrayImage::new(4, 1), &rects, '\u{7E}', 0);
+        let chars = font.glyphs.iter().map(|g| g.char).collect::<Vec<_>>();
+        assert_eq!(chars, ['~', '\u{7F}', '\u{20AC}', '\u{81}']);
+    }
+}
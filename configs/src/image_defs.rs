@@ -1,4 +1,21 @@
+use shadow_company_tools::{
+    config::{Config, ConfigLine, ConfigReader, EndType, ParseConfigError, ParseConfigResult},
+    data_dir::{DataDir, DataDirError},
+    images::{load_bmp_file, ColorKeyRange},
+    io::Reader,
+};
 use shadow_company_tools_derive::Config;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ImageDefsError {
+    #[error("Data dir error: {0}")]
+    DataDir(#[from] DataDirError),
+    #[error("IO Error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Config error: {0}")]
+    Config(#[from] ParseConfigError),
+}
 
 // SPRITEFRAME <x1> <y1> <x2> <y2>
 #[derive(Config, Debug, Default)]
@@ -21,9 +38,9 @@ pub struct SpriteFrameXRun {
     #[param(1)]
     pub y1: i32,
     #[param(2)]
-    pub x2: i32,
+    pub dx: i32,
     #[param(3)]
-    pub y2: i32,
+    pub dy: i32,
     #[param(4)]
     pub num_frames: i32,
 }
@@ -41,8 +58,132 @@ pub struct SpriteFrameDxRun {
     pub dy: i32,
     #[param(4)]
     pub num_frame: i32,
+    /// The width of each frame.
     #[param(5)]
-    pub dx: i32,
+    pub dx: Vec<i32>,
+}
+
+/// A frame rectangle on a texture, in pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SpriteRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl SpriteFrame {
+    /// The frame as a rectangle. `x2` and `y2` are exclusive.
+    pub fn rect(&self) -> SpriteRect {
+        SpriteRect {
+            x: self.x1,
+            y: self.y1,
+            width: self.x2 - self.x1,
+            height: self.y2 - self.y1,
+        }
+    }
+}
+
+impl SpriteFrameXRun {
+    /// Expand the run into `num_frames` frames of the same size, placed next to each other from
+    /// left to right.
+    pub fn rects(&self) -> impl Iterator<Item = SpriteRect> + '_ {
+        (0..self.num_frames.max(0)).map(|i| SpriteRect {
+            x: self.x1 + i * self.dx,
+            y: self.y1,
+            width: self.dx,
+            height: self.dy,
+        })
+    }
+}
+
+impl SpriteFrameDxRun {
+    /// Expand the run into frames of varying width, placed from left to right with `sep_dx` pixels
+    /// between them.
+    pub fn rects(&self) -> impl Iterator<Item = SpriteRect> + '_ {
+        let mut x = self.x1;
+        self.dx
+            .iter()
+            .take(self.num_frame.max(0) as usize)
+            .map(move |&dx| {
+                let rect = SpriteRect {
+                    x,
+                    y: self.y1,
+                    width: dx,
+                    height: self.dy,
+                };
+                x += dx + self.sep_dx;
+                rect
+            })
+    }
+}
+
+/// One frame definition line of a sprite. The lines are kept in one list, because the order of
+/// the frames is the order of the lines, whatever their kind.
+#[derive(Debug)]
+pub enum FrameDef {
+    Frame(SpriteFrame),
+    XRun(SpriteFrameXRun),
+    DxRun(SpriteFrameDxRun),
+}
+
+impl Default for FrameDef {
+    fn default() -> Self {
+        Self::Frame(SpriteFrame::default())
+    }
+}
+
+impl From<&ConfigLine> for FrameDef {
+    fn from(line: &ConfigLine) -> Self {
+        match line.name.as_str() {
+            "SPRITEFRAME_XRUN" => Self::XRun(SpriteFrameXRun::from(line)),
+            "SPRITEFRAME_DXRUN" => Self::DxRun(SpriteFrameDxRun::from(line)),
+            _ => Self::Frame(SpriteFrame::from(line)),
+        }
+    }
+}
+
+// The frame definitions are single lines, so there are no child fields to parse.
+impl Config for FrameDef {
+    const HAS_CONFIG_CHILD_FIELDS: bool = false;
+
+    fn has_config_key(_key: &str) -> bool {
+        false
+    }
+
+    fn parse_config_line<R>(&mut self, _reader: &mut ConfigReader<R>) -> ParseConfigResult
+    where
+        R: Reader,
+    {
+        Ok(())
+    }
+
+    fn parse_config_with_end<R>(
+        &mut self,
+        _reader: &mut ConfigReader<R>,
+        _end_type: EndType,
+    ) -> ParseConfigResult
+    where
+        R: Reader,
+    {
+        Ok(())
+    }
+}
+
+impl FrameDef {
+    /// Expand the definition into rectangles.
+    pub fn rects(&self) -> Vec<SpriteRect> {
+        match self {
+            Self::Frame(frame) => vec![frame.rect()],
+            Self::XRun(xrun) => xrun.rects().collect(),
+            Self::DxRun(dxrun) => dxrun.rects().collect(),
+        }
+    }
+}
+
+/// Expand all the frame definitions of a sprite into rectangles, in the order they were declared.
+fn frame_rects(frames: &[FrameDef]) -> Vec<SpriteRect> {
+    frames.iter().flat_map(FrameDef::rects).collect()
 }
 
 // SPRITE3D <NAME> <TEXTURENAME> <TXTR_WIDTH> <TXTR_HEIGHT> [<ALPHA>] [<Color Key Enable>] [ <Rl> <Gl> <Bl> <Rh> <Gh> Bh> ]
@@ -56,11 +197,58 @@ pub struct Sprite3d {
     pub texture_width: i32,
     #[param(3)]
     pub texture_height: i32,
+    /// Non-zero if the texture has a .raw alpha channel.
     #[param(4)]
     pub alpha: i32,
+    /// Non-zero if pixels inside the color key range are transparent.
+    #[param(5)]
+    pub color_key_enable: i32,
+    #[param(6)]
+    pub color_key_low_r: i32,
+    #[param(7)]
+    pub color_key_low_g: i32,
+    #[param(8)]
+    pub color_key_low_b: i32,
+    #[param(9)]
+    pub color_key_high_r: i32,
+    #[param(10)]
+    pub color_key_high_g: i32,
+    #[param(11)]
+    pub color_key_high_b: i32,
+
+    #[field("SPRITEFRAME")]
+    #[field("SPRITEFRAME_XRUN")]
+    #[field("SPRITEFRAME_DXRUN")]
+    pub frames: Vec<FrameDef>,
+}
 
-    #[field(key = "SPRITEFRAME")]
-    pub sprite_frames: Vec<SpriteFrame>,
+impl Sprite3d {
+    /// All the frames of the sprite. See [frame_rects].
+    pub fn frame_rects(&self) -> Vec<SpriteRect> {
+        frame_rects(&self.frames)
+    }
+
+    /// The color key range to use for the texture, if color keying is enabled. The range defaults
+    /// to pure black when it is not specified, like `load_bmp_file(color_keyd = true)`.
+    pub fn color_key(&self) -> Option<ColorKeyRange> {
+        if self.color_key_enable == 0 {
+            return None;
+        }
+
+        let channel = |value: i32| value.clamp(0, 255) as u8;
+        Some(ColorKeyRange {
+            low: [
+                channel(self.color_key_low_r),
+                channel(self.color_key_low_g),
+                channel(self.color_key_low_b),
+            ],
+            high: [
+                channel(self.color_key_high_r),
+                channel(self.color_key_high_g),
+                channel(self.color_key_high_b),
+            ],
+        })
+    }
 }
 
 #[derive(Config, Debug, Default)]
@@ -105,11 +293,49 @@ pub struct AnimSprite {
     #[field("FRAMEORDER")]
     pub frame_orders: Vec<FrameOrder>,
     #[field("SPRITEFRAME")]
-    pub sprite_frames: Vec<SpriteFrame>,
     #[field("SPRITEFRAME_XRUN")]
-    pub sprite_frame_xruns: Vec<SpriteFrameXRun>,
     #[field("SPRITEFRAME_DXRUN")]
-    pub sprite_frame_dxruns: Vec<SpriteFrameDxRun>,
+    pub frames: Vec<FrameDef>,
+}
+
+impl AnimSprite {
+    /// All the frames of the sprite. See [frame_rects].
+    pub fn frame_rects(&self) -> Vec<SpriteRect> {
+        frame_rects(&self.frames)
+    }
+
+    /// The indices into [AnimSprite::frame_rects] to show, in the order they are played. Uses the
+    /// FRAMEORDER lines if there are any, otherwise the images are played in order and repeated
+    /// until there are `num_frames` frames.
+    pub fn frame_sequence(&self) -> Vec<usize> {
+        let num_images = self.frame_rects().len();
+        if num_images == 0 {
+            return vec![];
+        }
+
+        if !self.frame_orders.is_empty() {
+            return self
+                .frame_orders
+                .iter()
+                .flat_map(|frame_order| frame_order.order.iter())
+                .filter_map(|&index| usize::try_from(index).ok())
+                .filter(|&index| index < num_images)
+                .collect();
+        }
+
+        let num_frames = match self.frame_descriptor.num_frames {
+            n if n > 0 => n as usize,
+            _ => num_images,
+        };
+        (0..num_frames).map(|i| i % num_images).collect()
+    }
+
+    /// How long each frame is shown, from the frame rate in the FRAMEDESCRIPTOR.
+    pub fn frame_duration(&self) -> Option<std::time::Duration> {
+        (self.frame_descriptor.frame_rate > 0).then(|| {
+            std::time::Duration::from_secs_f64(1.0 / self.frame_descriptor.frame_rate as f64)
+        })
+    }
 }
 
 #[derive(Config, Debug, Default)]
@@ -123,3 +349,195 @@ pub struct ImageDefs {
     #[field("ANIMSPRITE", end = "ENDDEF")]
     pub anim_sprites: Vec<AnimSprite>,
 }
+
+/// Compare the file stems of two paths, ignoring case. Definitions don't always include the
+/// extension of the file they reference.
+fn same_stem(a: &str, b: &str) -> bool {
+    fn stem(path: &str) -> &str {
+        let file_name = path.rsplit(['\\', '/']).next().unwrap_or(path);
+        file_name
+            .rsplit_once('.')
+            .map(|(stem, _)| stem)
+            .unwrap_or(file_name)
+    }
+
+    stem(a).eq_ignore_ascii_case(stem(b))
+}
+
+impl ImageDefs {
+    /// The path of the definitions inside the data directory.
+    pub const PATH: &'static str = "config\\image_defs.txt";
+
+    /// Read the definitions from `config\image_defs.txt` in the data directory.
+    pub fn load(data_dir: &DataDir) -> Result<Self, ImageDefsError> {
+        let file = data_dir.open(Self::PATH)?;
+        let mut reader = ConfigReader::new(file)?;
+        Ok(Self::from_config(&mut reader)?)
+    }
+
+    /// Returns the texture sizes declared by SPRITE3D definitions for a texture.
+    pub fn texture_sizes(&self, texture_name: &str) -> Vec<(u32, u32)> {
+        self.sprite_3ds
+            .iter()
+            .filter(|sprite| same_stem(&sprite.texture_name, texture_name))
+            .filter(|sprite| sprite.texture_width > 0 && sprite.texture_height > 0)
+            .map(|sprite| (sprite.texture_width as u32, sprite.texture_height as u32))
+            .collect()
+    }
+
+    /// Returns the color key of the first SPRITE3D definition for a texture that enables one.
+    pub fn texture_color_key(&self, texture_name: &str) -> Option<ColorKeyRange> {
+        self.sprite_3ds
+            .iter()
+            .filter(|sprite| same_stem(&sprite.texture_name, texture_name))
+            .find_map(Sprite3d::color_key)
+    }
+
+    /// The sizes declared for a .raw file, to pass to [detect_raw_size]. SPRITE3D definitions
+    /// declare the texture size, IMAGE definitions reference a .bmp that has the same size as the
+    /// .raw file.
+    ///
+    /// [detect_raw_size]: shadow_company_tools::images::detect_raw_size
+    pub fn raw_sizes(&self, data_dir: &DataDir, raw_name: &str) -> Vec<(u32, u32)> {
+        let mut sizes = self.texture_sizes(raw_name);
+        for image in self.images_for(raw_name) {
+            let bmp_path = std::path::Path::new(&image.filename).with_extension("bmp");
+            let Ok(mut file) = data_dir.open(&bmp_path) else {
+                continue;
+            };
+            if let Ok(bmp) = load_bmp_file(&mut file, false) {
+                sizes.push(bmp.dimensions());
+            }
+        }
+        sizes
+    }
+
+    /// Returns the IMAGE definitions that reference a file.
+    pub fn images_for<'a>(&'a self, filename: &'a str) -> impl Iterator<Item = &'a Image> {
+        self.images
+            .iter()
+            .filter(move |image| same_stem(&image.filename, filename))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let data = r#"
+            IMAGE logo interface\logo.bmp 1
+
+            SPRITE3D icons interface\icons.bmp 64 64 1 1 0 0 0 8 8 8
+                SPRITEFRAME 0 0 8 8
+            ENDDEF
+
+            ANIMSPRITE3D fire effects\fire.bmp 32 32
+                FRAMEDESCRIPTOR 2 4 10
+                FRAMEORDER 0 1 1 0
+                SPRITEFRAME_XRUN 0 0 16 16 2
+            ENDDEF
+
+            IMAGE frame interface\frame.bmp 0
+        "#;
+
+        let mut reader = ConfigReader::new(std::io::Cursor::new(data)).unwrap();
+        let image_defs = ImageDefs::from_config(&mut reader).unwrap();
+
+        assert_eq!(image_defs.images.len(), 2);
+        assert_eq!(image_defs.images[1].filename, "interface\\frame.bmp");
+
+        let icons = &image_defs.sprite_3ds[0];
+        assert_eq!(icons.texture_name, "interface\\icons.bmp");
+        assert_eq!((icons.texture_width, icons.texture_height), (64, 64));
+        assert_eq!(icons.color_key().map(|key| key.high), Some([8, 8, 8]));
+        assert_eq!(icons.frames.len(), 1);
+
+        let fire = &image_defs.anim_sprite_3ds[0];
+        assert_eq!(fire.frame_descriptor.num_frames, 4);
+        assert_eq!(fire.frame_orders[0].order, [0, 1, 1, 0]);
+        assert_eq!(fire.frame_rects().len(), 2);
+        assert!(image_defs.anim_sprites.is_empty());
+    }
+
+    #[test]
+    fn raw_sizes() {
+        let root = std::env::temp_dir().join(format!("raw_sizes_{}", std::process::id()));
+        std::fs::create_dir_all(root.join("interface")).unwrap();
+        let bmp = image::RgbImage::new(20, 10);
+        shadow_company_tools::images::write_bmp_file(
+            &mut std::fs::File::create(root.join("interface").join("logo.bmp")).unwrap(),
+            &bmp,
+        )
+        .unwrap();
+
+        let data = r#"
+            IMAGE logo interface\logo 1
+            SPRITE3D icons interface\logo.bmp 64 32
+            ENDDEF
+        "#;
+        let mut reader = ConfigReader::new(std::io::Cursor::new(data)).unwrap();
+        let image_defs = ImageDefs::from_config(&mut reader).unwrap();
+
+        let sizes = image_defs.raw_sizes(&DataDir::new(&root), "LOGO.raw");
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(sizes, [(64, 32), (20, 10)]);
+    }
+
+    #[test]
+    fn expand_frame_runs() {
+        let data = r#"
+            ANIMSPRITE cursor interface\cursors.bmp 64 32
+                FRAMEDESCRIPTOR 5 5 10
+                SPRITEFRAME 0 0 8 8
+                SPRITEFRAME_XRUN 0 8 10 12 2
+                SPRITEFRAME_DXRUN 0 20 2 4 2 5 7
+            ENDDEF
+        "#;
+
+        let mut reader = ConfigReader::new(std::io::Cursor::new(data)).unwrap();
+        let image_defs = ImageDefs::from_config(&mut reader).unwrap();
+        let rect = |x, y, width, height| SpriteRect {
+            x,
+            y,
+            width,
+            height,
+        };
+
+        assert_eq!(
+            image_defs.anim_sprites[0].frame_rects(),
+            [
+                rect(0, 0, 8, 8),
+                rect(0, 8, 10, 12),
+                rect(10, 8, 10, 12),
+                rect(0, 20, 5, 4),
+                rect(7, 20, 7, 4),
+            ]
+        );
+        assert_eq!(image_defs.anim_sprites[0].frame_sequence(), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn keep_frame_order() {
+        let data = r#"
+            SPRITE3D icons interface\icons.bmp 64 64
+                SPRITEFRAME_XRUN 0 0 8 8 2
+                SPRITEFRAME 32 32 40 40
+                SPRITEFRAME_DXRUN 0 16 0 8 1 4
+                SPRITEFRAME 48 48 56 56
+            ENDDEF
+        "#;
+
+        let mut reader = ConfigReader::new(std::io::Cursor::new(data)).unwrap();
+        let image_defs = ImageDefs::from_config(&mut reader).unwrap();
+        let rects = image_defs.sprite_3ds[0]
+            .frame_rects()
+            .iter()
+            .map(|rect| (rect.x, rect.y))
+            .collect::<Vec<_>>();
+
+        assert_eq!(rects, [(0, 0), (8, 0), (32, 32), (0, 16), (48, 48)]);
+    }
+}
@@ -1,9 +1,15 @@
 mod campaign_defs;
+mod font;
 mod image_defs;
 mod mtf;
+mod sprite_sheet;
 mod window_base;
+mod window_renderer;
 
 pub use campaign_defs::*;
+pub use font::*;
 pub use image_defs::*;
 pub use mtf::*;
+pub use sprite_sheet::*;
 pub use window_base::*;
+pub use window_renderer::*;
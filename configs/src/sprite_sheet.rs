@@ -0,0 +1,135 @@
+use image::RgbaImage;
+use shadow_company_tools::texture_resolver::{
+    Texture, TextureError, TextureKeying, TextureResolver,
+};
+use thiserror::Error;
+
+use crate::{AnimSprite, Sprite3d, SpriteRect};
+
+#[derive(Debug, Error)]
+pub enum SpriteSheetError {
+    #[error("Texture not found: {0}")]
+    TextureNotFound(String),
+    #[error("{0}")]
+    Texture(#[from] TextureError),
+}
+
+/// A sprite texture along with the rectangles of its frames.
+pub struct SpriteSheet {
+    pub name: String,
+    /// The path of the texture inside the data directory.
+    pub texture_path: String,
+    pub texture: RgbaImage,
+    pub frames: Vec<SpriteRect>,
+}
+
+impl SpriteSheet {
+    /// Load the texture for a SPRITE3D definition, applying its alpha channel and color key.
+    pub fn from_sprite_3d(
+        resolver: &TextureResolver,
+        sprite: &Sprite3d,
+    ) -> Result<Self, SpriteSheetError> {
+        let keying = TextureKeying {
+            color_key: sprite.color_key(),
+            raw_alpha: sprite.alpha != 0,
+        };
+        let texture = resolver.resolve_with_keying(&texture_name(&sprite.texture_name), keying)?;
+        Self::new(
+            &sprite.name,
+            &sprite.texture_name,
+            texture,
+            sprite.frame_rects(),
+        )
+    }
+
+    /// Load the texture for an ANIMSPRITE or ANIMSPRITE3D definition. The definitions don't say
+    /// how the texture is keyed, so it is keyed by its name like other textures.
+    pub fn from_anim_sprite(
+        resolver: &TextureResolver,
+        sprite: &AnimSprite,
+    ) -> Result<Self, SpriteSheetError> {
+        let texture = resolver.resolve(&texture_name(&sprite.texture_name))?;
+        Self::new(
+            &sprite.name,
+            &sprite.texture_name,
+            texture,
+            sprite.frame_rects(),
+        )
+    }
+
+    fn new(
+        name: &str,
+        texture_name: &str,
+        texture: Option<Texture>,
+        frames: Vec<SpriteRect>,
+    ) -> Result<Self, SpriteSheetError> {
+        let texture =
+            texture.ok_or_else(|| SpriteSheetError::TextureNotFound(texture_name.to_string()))?;
+        Ok(Self {
+            name: name.to_string(),
+            texture_path: texture.path,
+            texture: texture.image,
+            frames,
+        })
+    }
+
+    /// Crop a frame from the texture. Parts of the frame that fall outside of the texture are
+    /// clipped. Returns `None` if nothing of the frame is on the texture.
+    pub fn frame(&self, index: usize) -> Option<RgbaImage> {
+        let rect = self.frames.get(index)?;
+
+        let (width, height) = self.texture.dimensions();
+        let x1 = rect.x.clamp(0, width as i32) as u32;
+        let y1 = rect.y.clamp(0, height as i32) as u32;
+        let x2 = (rect.x + rect.width).clamp(0, width as i32) as u32;
+        let y2 = (rect.y + rect.height).clamp(0, height as i32) as u32;
+        if x2 <= x1 || y2 <= y1 {
+            return None;
+        }
+
+        Some(image::imageops::crop_imm(&self.texture, x1, y1, x2 - x1, y2 - y1).to_image())
+    }
+
+    /// Crop the frames in `sequence` and place them in the top left corner of images that are all
+    /// the same size, large enough to hold the largest frame. Frames that are not on the texture
+    /// are left empty.
+    pub fn animation(&self, sequence: &[usize]) -> Vec<RgbaImage> {
+        let frames = sequence
+            .iter()
+            .map(|&index| self.frame(index))
+            .collect::<Vec<_>>();
+
+        let width = frames
+            .iter()
+            .flatten()
+            .map(|f| f.width())
+            .max()
+            .unwrap_or(1);
+        let height = frames
+            .iter()
+            .flatten()
+            .map(|f| f.height())
+            .max()
+            .unwrap_or(1);
+
+        frames
+            .into_iter()
+            .map(|frame| {
+                let mut canvas = RgbaImage::new(width, height);
+                if let Some(frame) = frame {
+                    image::imageops::replace(&mut canvas, &frame, 0, 0);
+                }
+                canvas
+            })
+            .collect()
+    }
+}
+
+/// Sprite definitions name their texture without an extension, which means a .bmp.
+fn texture_name(name: &str) -> String {
+    if name.contains('.') {
+        name.to_string()
+    } else {
+        format!("{}.bmp", name)
+    }
+}
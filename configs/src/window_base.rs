@@ -1,3 +1,4 @@
+use shadow_company_tools::images::TiledImage;
 use shadow_company_tools_derive::Config;
 
 #[derive(Config, Debug, Default)]
@@ -102,6 +103,30 @@ pub struct GeometryTiled {
     pub chunk_dimensions: [i32; 2],
 }
 
+impl GeometryTiled {
+    /// The layout of the chunks the image is stored in. If no chunk dimensions are given, the
+    /// image is stored as a single chunk.
+    pub fn tiled_image(&self) -> TiledImage {
+        let name = self.jpg_name.replace('/', "\\");
+        let name = name.rsplit('\\').next().unwrap_or(&name);
+        let name = name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(name);
+
+        let [width, height] = self.jpg_dimensions.map(|d| d.max(1) as u32);
+        let [chunk_width, chunk_height] = match self.chunk_dimensions {
+            [dx, dy] if dx > 0 && dy > 0 => [dx as u32, dy as u32],
+            _ => [width, height],
+        };
+
+        TiledImage {
+            name: name.to_string(),
+            width,
+            height,
+            chunk_width,
+            chunk_height,
+        }
+    }
+}
+
 #[derive(Config, Debug, Default)]
 pub struct WindowBase {
     #[param(0)]
@@ -145,8 +170,8 @@ mod tests {
 
     use super::*;
 
-    //#[test]
-    fn _parse() {
+    #[test]
+    fn parse() {
         let data = r#"
             ;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
             ; main_menu.txt
@@ -194,6 +219,8 @@ mod tests {
                 GEOMETRY_POLYGONS           2
                     GEOMETRY_POLYGON        0       1       2
                     GEOMETRY_POLYGON        2       3       0
+
+            MODIFY_USER_IVAR button_offset_x    40
         "#;
 
         let cursor = std::io::Cursor::new(data);
@@ -215,9 +242,23 @@ mod tests {
         assert_eq!(window_base.user_ivars[1].name.as_str(), "button_offset_y");
         assert_eq!(window_base.user_ivars[1].value, 5);
 
-        // pub user_ivars_modify: Vec<UserIVar>,
-        // pub button_advices: Vec<ButtonAdvice>,
-        // pub geometry: Vec<Geometry>,
+        assert_eq!(window_base.user_ivars_modify.len(), 1);
+        assert_eq!(window_base.user_ivars_modify[0].value, 40);
+        assert_eq!(window_base.button_advices.len(), 3);
+        assert_eq!(window_base.button_advices[2].name.as_str(), "b_load_game");
+
+        // The nested vertex and polygon lists end at the keys of the geometry, and the geometry
+        // ends at the keys of the window.
+        assert_eq!(window_base.geometry.len(), 1);
+        let geometry = &window_base.geometry[0];
+        assert_eq!(geometry.texture, "interface_commando_1_ck.bmp");
+        assert_eq!(geometry.bilinear_filtering, "off");
+        assert_eq!(geometry.vertices.count, 4);
+        assert_eq!(geometry.vertices.vertices.len(), 4);
+        assert_eq!(geometry.vertices.vertices[2].x_pos, 128.0);
+        assert_eq!(geometry.polygons.count, 2);
+        assert_eq!(geometry.polygons.polygons.len(), 2);
+        assert_eq!(geometry.polygons.polygons[1].i1, 3);
 
         assert_eq!(window_base.geometry_tiled.len(), 2);
         assert_eq!(window_base.geometry_tiled[0].jpg_name.as_str(), "frame2");
@@ -0,0 +1,517 @@
+//! A software renderer for WINDOW_BASE definitions, used to preview menus without the game.
+
+use std::collections::HashMap;
+
+use image::{ImageError, Rgba, RgbaImage};
+use shadow_company_tools::{
+    images::TiledImage,
+    texture_resolver::{TextureError, TextureResolver},
+};
+
+use crate::{Geometry, WindowBase};
+
+/// Used when the window does not specify its size.
+const DEFAULT_SIZE: (u32, u32) = (640, 480);
+
+/// The color used to draw DEFINE_BUTTON_ADVICE rectangles.
+const BUTTON_ADVICE_COLOR: Rgba<u8> = Rgba([255, 0, 255, 255]);
+
+/// How the pixels of a geometry are combined with what is already drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Replace the destination.
+    Opaque,
+    /// Blend using the alpha of the source.
+    #[default]
+    Alpha,
+    /// Add the source, scaled by its alpha, to the destination.
+    Additive,
+    /// Multiply the destination with the source.
+    Multiply,
+}
+
+/// The names of the blend modes, as used by [`BlendMode::from_name`].
+const BLEND_MODE_NAMES: &[(&str, BlendMode)] = &[
+    ("opaque", BlendMode::Opaque),
+    ("alpha", BlendMode::Alpha),
+    ("additive", BlendMode::Additive),
+    ("multiply", BlendMode::Multiply),
+];
+
+impl BlendMode {
+    /// Look up a blend mode by its name: "opaque", "alpha", "additive" or "multiply". These are
+    /// not values of GEOMETRY_BLEND_MODE, see [`WindowRenderOptions::blend_modes`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        BLEND_MODE_NAMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, blend_mode)| *blend_mode)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WindowRenderOptions {
+    /// Draw the outlines of the DEFINE_BUTTON_ADVICE rectangles on top of the window.
+    pub button_advice: bool,
+    /// The blend mode to draw each value of GEOMETRY_BLEND_MODE with. None of the window
+    /// definitions seen so far set a blend mode, so the values the game accepts are not known.
+    /// Geometry without a blend mode is alpha blended.
+    pub blend_modes: Vec<(String, BlendMode)>,
+}
+
+impl WindowRenderOptions {
+    /// The blend mode for a value of GEOMETRY_BLEND_MODE, or `None` if the value is not in
+    /// `blend_modes`.
+    fn blend_mode(&self, value: &str) -> Option<BlendMode> {
+        if value.is_empty() {
+            return Some(BlendMode::default());
+        }
+
+        self.blend_modes
+            .iter()
+            .find(|(v, _)| v.eq_ignore_ascii_case(value))
+            .map(|(_, blend_mode)| *blend_mode)
+    }
+}
+
+pub struct WindowRender {
+    pub image: RgbaImage,
+    /// Textures that could not be found. Geometry using them is drawn with vertex colors only.
+    pub missing_textures: Vec<String>,
+    /// Values of GEOMETRY_BLEND_MODE that are not known. Geometry using them is alpha blended.
+    pub unknown_blend_modes: Vec<String>,
+}
+
+/// Render a window base to an image of `dx` x `dy` pixels. Tiled backgrounds are drawn first, then
+/// the geometry in the order it is defined.
+pub fn render_window_base(
+    window_base: &WindowBase,
+    textures: &TextureResolver,
+    options: &WindowRenderOptions,
+) -> Result<WindowRender, TextureError> {
+    let (width, height) = match (window_base.dx, window_base.dy) {
+        (dx, dy) if dx > 0 && dy > 0 => (dx as u32, dy as u32),
+        _ => DEFAULT_SIZE,
+    };
+    let mut image = RgbaImage::from_pixel(width, height, Rgba([0, 0, 0, 255]));
+
+    let mut missing_textures = vec![];
+    let mut unknown_blend_modes = vec![];
+
+    for tiled in window_base.geometry_tiled.iter() {
+        match load_tiled(textures, &tiled.tiled_image())? {
+            Some(background) => image::imageops::overlay(&mut image, &background, 0, 0),
+            None => missing_textures.push(tiled.jpg_name.clone()),
+        }
+    }
+
+    let mut cache: HashMap<String, Option<RgbaImage>> = HashMap::new();
+    let mut load = |name: &str| -> Result<Option<RgbaImage>, TextureError> {
+        let key = name.to_ascii_lowercase();
+        if let Some(texture) = cache.get(&key) {
+            return Ok(texture.clone());
+        }
+        let texture = textures.resolve(name)?.map(|texture| texture.image);
+        if texture.is_none() {
+            missing_textures.push(name.to_string());
+        }
+        cache.insert(key, texture.clone());
+        Ok(texture)
+    };
+
+    for geometry in window_base.geometry.iter() {
+        let texture = if geometry.texture.is_empty() {
+            None
+        } else {
+            load(&geometry.texture)?
+        };
+        let blend_mode = match options.blend_mode(&geometry.geometry_blend_mode) {
+            Some(blend_mode) => blend_mode,
+            None => {
+                unknown_blend_modes.push(geometry.geometry_blend_mode.clone());
+                BlendMode::default()
+            }
+        };
+        draw_geometry(&mut image, geometry, texture.as_ref(), blend_mode);
+    }
+
+    if options.button_advice {
+        for advice in window_base.button_advices.iter() {
+            draw_rect_outline(&mut image, advice.x, advice.y, advice.dx, advice.dy);
+        }
+    }
+
+    Ok(WindowRender {
+        image,
+        missing_textures,
+        unknown_blend_modes,
+    })
+}
+
+/// Load the chunks of a tiled background with the first naming pattern that matches. Images that
+/// are not split into chunks are loaded by their name.
+fn load_tiled(
+    textures: &TextureResolver,
+    tiled: &TiledImage,
+) -> Result<Option<RgbaImage>, TextureError> {
+    let single = tiled.chunk_name("{name}", 0, 0);
+    let pattern = tiled.detect_pattern(|name| textures.contains(name));
+    let pattern = match pattern {
+        Some(pattern) => pattern,
+        None if tiled.columns() * tiled.rows() == 1 || textures.contains(&single) => {
+            return Ok(textures.resolve(&single)?.map(|texture| texture.image));
+        }
+        None => return Ok(None),
+    };
+
+    // Missing chunks are left transparent.
+    let image = tiled.stitch(pattern, |name| match textures.resolve(name) {
+        Ok(texture) => Ok(texture.map(|t| t.image).unwrap_or_default()),
+        Err(TextureError::Image(err)) => Err(err),
+        Err(err) => Err(ImageError::IoError(std::io::Error::other(err))),
+    })?;
+    Ok(Some(image))
+}
+
+#[derive(Clone, Copy)]
+struct RasterVertex {
+    x: f32,
+    y: f32,
+    color: [f32; 4],
+    uv: [f32; 2],
+}
+
+fn draw_geometry(
+    image: &mut RgbaImage,
+    geometry: &Geometry,
+    texture: Option<&RgbaImage>,
+    blend_mode: BlendMode,
+) {
+    let bilinear = geometry.bilinear_filtering.eq_ignore_ascii_case("on");
+
+    // Texture coordinates are in pixels of the texture page the texture is packed into, which is
+    // GEOMETRY_TEXTURE_PACK_DX x GEOMETRY_TEXTURE_PACK_DY pixels. Scale them to the texture when
+    // its file has a different size.
+    let scale = |pack: i32, size: u32| {
+        if pack > 0 {
+            size as f32 / pack as f32
+        } else {
+            1.0
+        }
+    };
+    let (scale_u, scale_v) = texture.map_or((1.0, 1.0), |texture| {
+        (
+            scale(geometry.texture_pack_dx, texture.width()),
+            scale(geometry.texture_pack_dy, texture.height()),
+        )
+    });
+
+    let vertices = geometry
+        .vertices
+        .vertices
+        .iter()
+        .map(|v| RasterVertex {
+            x: v.x_pos,
+            y: v.y_pos,
+            color: [v.r, v.g, v.b, v.a],
+            uv: [v.tu * scale_u, v.tv * scale_v],
+        })
+        .collect::<Vec<_>>();
+
+    for polygon in geometry.polygons.polygons.iter() {
+        let get = |i: i32| {
+            usize::try_from(i)
+                .ok()
+                .and_then(|i| vertices.get(i).copied())
+        };
+        let (Some(v0), Some(v1), Some(v2)) = (get(polygon.i0), get(polygon.i1), get(polygon.i2))
+        else {
+            continue;
+        };
+
+        draw_triangle(image, [v0, v1, v2], texture, bilinear, blend_mode);
+    }
+}
+
+/// The edge function of `a -> b` at `p`. Positive on the inside of a triangle with a positive area.
+fn edge(a: &RasterVertex, b: &RasterVertex, px: f32, py: f32) -> f32 {
+    (b.x - a.x) * (py - a.y) - (b.y - a.y) * (px - a.x)
+}
+
+/// Pixels exactly on a top or left edge are drawn, the ones on bottom or right edges are not, so
+/// that triangles sharing an edge don't draw the same pixel twice.
+fn is_top_left(a: &RasterVertex, b: &RasterVertex) -> bool {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    (dy == 0.0 && dx > 0.0) || dy < 0.0
+}
+
+fn draw_triangle(
+    image: &mut RgbaImage,
+    mut vertices: [RasterVertex; 3],
+    texture: Option<&RgbaImage>,
+    bilinear: bool,
+    blend_mode: BlendMode,
+) {
+    let mut area = edge(&vertices[0], &vertices[1], vertices[2].x, vertices[2].y);
+    if area == 0.0 {
+        return;
+    }
+    if area < 0.0 {
+        vertices.swap(1, 2);
+        area = -area;
+    }
+    let [v0, v1, v2] = vertices;
+
+    let (width, height) = image.dimensions();
+    let min_x = v0.x.min(v1.x).min(v2.x).floor().max(0.0) as u32;
+    let min_y = v0.y.min(v1.y).min(v2.y).floor().max(0.0) as u32;
+    let max_x = (v0.x.max(v1.x).max(v2.x).ceil() as u32).min(width);
+    let max_y = (v0.y.max(v1.y).max(v2.y).ceil() as u32).min(height);
+
+    let edges = [(&v1, &v2), (&v2, &v0), (&v0, &v1)];
+    let top_left = edges.map(|(a, b)| is_top_left(a, b));
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let w = edges.map(|(a, b)| edge(a, b, px, py));
+            let inside = (0..3).all(|i| w[i] > 0.0 || (w[i] == 0.0 && top_left[i]));
+            if !inside {
+                continue;
+            }
+
+            let [b0, b1, b2] = w.map(|w| w / area);
+            let lerp = |a: f32, b: f32, c: f32| a * b0 + b * b1 + c * b2;
+
+            let mut color = [0.0; 4];
+            for (i, c) in color.iter_mut().enumerate() {
+                *c = lerp(v0.color[i], v1.color[i], v2.color[i]).clamp(0.0, 1.0);
+            }
+
+            if let Some(texture) = texture {
+                let u = lerp(v0.uv[0], v1.uv[0], v2.uv[0]);
+                let v = lerp(v0.uv[1], v1.uv[1], v2.uv[1]);
+                let texel = if bilinear {
+                    sample_bilinear(texture, u, v)
+                } else {
+                    sample_nearest(texture, u, v)
+                };
+                for (c, t) in color.iter_mut().zip(texel) {
+                    *c *= t;
+                }
+            }
+
+            let pixel = image.get_pixel_mut(x, y);
+            blend(pixel, color, blend_mode);
+        }
+    }
+}
+
+/// Texture coordinates are in pixels.
+fn texel(texture: &RgbaImage, x: i64, y: i64) -> [f32; 4] {
+    let x = x.clamp(0, texture.width() as i64 - 1) as u32;
+    let y = y.clamp(0, texture.height() as i64 - 1) as u32;
+    texture.get_pixel(x, y).0.map(|c| c as f32 / 255.0)
+}
+
+fn sample_nearest(texture: &RgbaImage, u: f32, v: f32) -> [f32; 4] {
+    texel(texture, u.floor() as i64, v.floor() as i64)
+}
+
+fn sample_bilinear(texture: &RgbaImage, u: f32, v: f32) -> [f32; 4] {
+    let (u, v) = (u - 0.5, v - 0.5);
+    let (x, y) = (u.floor(), v.floor());
+    let (fx, fy) = (u - x, v - y);
+    let (x, y) = (x as i64, y as i64);
+
+    let t00 = texel(texture, x, y);
+    let t10 = texel(texture, x + 1, y);
+    let t01 = texel(texture, x, y + 1);
+    let t11 = texel(texture, x + 1, y + 1);
+
+    let mut result = [0.0; 4];
+    for i in 0..4 {
+        let top = t00[i] + (t10[i] - t00[i]) * fx;
+        let bottom = t01[i] + (t11[i] - t01[i]) * fx;
+        result[i] = top + (bottom - top) * fy;
+    }
+    result
+}
+
+fn blend(pixel: &mut Rgba<u8>, src: [f32; 4], blend_mode: BlendMode) {
+    let alpha = src[3];
+
+    for (dst, src) in pixel.0.iter_mut().zip(src).take(3) {
+        let d = *dst as f32 / 255.0;
+        let out = match blend_mode {
+            BlendMode::Opaque => src,
+            BlendMode::Alpha => src * alpha + d * (1.0 - alpha),
+            BlendMode::Additive => d + src * alpha,
+            BlendMode::Multiply => d * src,
+        };
+        *dst = (out.clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+    pixel.0[3] = 255;
+}
+
+/// Draw a one pixel outline. Empty rectangles are drawn as a small cross at their position.
+fn draw_rect_outline(image: &mut RgbaImage, x: i32, y: i32, dx: i32, dy: i32) {
+    let mut put = |x: i32, y: i32| {
+        if x >= 0 && y >= 0 && (x as u32) < image.width() && (y as u32) < image.height() {
+            image.put_pixel(x as u32, y as u32, BUTTON_ADVICE_COLOR);
+        }
+    };
+
+    if dx <= 0 || dy <= 0 {
+        for i in -3..=3 {
+            put(x + i, y);
+            put(x, y + i);
+        }
+        return;
+    }
+
+    for i in x..x + dx {
+        put(i, y);
+        put(i, y + dy - 1);
+    }
+    for i in y..y + dy {
+        put(x, i);
+        put(x + dx - 1, i);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Polygon, Polygons, Vertex, Vertices};
+
+    #[test]
+    fn shared_edges_are_drawn_once() {
+        let vertex = |x, y| Vertex {
+            x_pos: x,
+            y_pos: y,
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+            a: 0.5,
+            ..Default::default()
+        };
+        let geometry = Geometry {
+            vertices: Vertices {
+                count: 4,
+                vertices: vec![
+                    vertex(1.0, 1.0),
+                    vertex(7.0, 1.0),
+                    vertex(7.0, 5.0),
+                    vertex(1.0, 5.0),
+                ],
+            },
+            polygons: Polygons {
+                count: 2,
+                polygons: vec![
+                    Polygon {
+                        i0: 0,
+                        i1: 1,
+                        i2: 2,
+                    },
+                    Polygon {
+                        i0: 2,
+                        i1: 3,
+                        i2: 0,
+                    },
+                ],
+            },
+            ..Default::default()
+        };
+
+        let mut image = RgbaImage::from_pixel(8, 8, Rgba([0, 0, 0, 255]));
+        draw_geometry(&mut image, &geometry, None, BlendMode::Alpha);
+
+        // Every pixel inside the quad is blended exactly once.
+        let covered = image.pixels().filter(|p| p.0[0] != 0).collect::<Vec<_>>();
+        assert_eq!(covered.len(), 6 * 4);
+        assert!(covered.iter().all(|p| p.0[0] == 128));
+    }
+
+    #[test]
+    fn blend_modes() {
+        // The geometry of main_menu.txt, see `window_base::tests::parse`, has no
+        // GEOMETRY_BLEND_MODE and is alpha blended.
+        let options = WindowRenderOptions::default();
+        assert_eq!(options.blend_mode(""), Some(BlendMode::Alpha));
+        assert_eq!(options.blend_mode("additive"), None);
+
+        let options = WindowRenderOptions {
+            blend_modes: vec![("glow".to_string(), BlendMode::Additive)],
+            ..Default::default()
+        };
+        assert_eq!(options.blend_mode("Glow"), Some(BlendMode::Additive));
+        // Values are matched exactly, not by parts of them.
+        assert_eq!(options.blend_mode("glo"), None);
+
+        assert_eq!(BlendMode::from_name("Opaque"), Some(BlendMode::Opaque));
+        assert_eq!(BlendMode::from_name("multiply"), Some(BlendMode::Multiply));
+        assert_eq!(BlendMode::from_name("none"), None);
+    }
+
+    #[test]
+    fn texture_pack_size_scales_tex_coords() {
+        let vertex = |x, y| Vertex {
+            x_pos: x,
+            y_pos: y,
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+            a: 1.0,
+            tu: x,
+            tv: y,
+        };
+        // A quad covering the left half of a 4x4 texture page.
+        let geometry = Geometry {
+            texture_pack_dx: 4,
+            texture_pack_dy: 4,
+            vertices: Vertices {
+                count: 4,
+                vertices: vec![
+                    vertex(0.0, 0.0),
+                    vertex(2.0, 0.0),
+                    vertex(2.0, 4.0),
+                    vertex(0.0, 4.0),
+                ],
+            },
+            polygons: Polygons {
+                count: 2,
+                polygons: vec![
+                    Polygon {
+                        i0: 0,
+                        i1: 1,
+                        i2: 2,
+                    },
+                    Polygon {
+                        i0: 2,
+                        i1: 3,
+                        i2: 0,
+                    },
+                ],
+            },
+            ..Default::default()
+        };
+
+        // The texture file is twice the size of the page, with a red left half.
+        let texture = RgbaImage::from_fn(8, 8, |x, _| {
+            if x < 4 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        });
+
+        let mut image = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255]));
+        draw_geometry(&mut image, &geometry, Some(&texture), BlendMode::Alpha);
+        for y in 0..4 {
+            assert_eq!(image.get_pixel(0, y), &Rgba([255, 0, 0, 255]));
+            assert_eq!(image.get_pixel(1, y), &Rgba([255, 0, 0, 255]));
+            assert_eq!(image.get_pixel(2, y), &Rgba([0, 0, 0, 255]));
+        }
+    }
+}
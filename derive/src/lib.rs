@@ -169,8 +169,10 @@ enum EndType {
 impl EndType {
     fn quote(&self) -> proc_macro2::TokenStream {
         match self {
+            // Nested structures without a start or end key end where their keys end, otherwise they
+            // would try to parse the lines that follow them in the parent.
             EndType::None => {
-                quote! { shadow_company_tools::config::EndType::None }
+                quote! { shadow_company_tools::config::EndType::UnknownKey }
             }
             EndType::StartKey(value) => {
                 quote! { shadow_company_tools::config::EndType::StartKey(#value) }
@@ -372,6 +374,11 @@ pub fn parse_line(input: TokenStream) -> TokenStream {
         quote!()
     };
 
+    let keys = fields
+        .iter()
+        .filter_map(|field| field.key_name.clone())
+        .collect::<Vec<_>>();
+
     let has_config_child_fields = if fields.iter().filter(|f| f.key_name.is_some()).count() > 0 {
         quote! { true }
     } else {
@@ -383,6 +390,10 @@ pub fn parse_line(input: TokenStream) -> TokenStream {
         impl shadow_company_tools::config::Config for #struct_name {
             const HAS_CONFIG_CHILD_FIELDS: bool = #has_config_child_fields;
 
+            fn has_config_key(key: &str) -> bool {
+                [#(#keys),*].contains(&key)
+            }
+
             fn parse_config_line<R>(
                 &mut self,
                 reader: &mut shadow_company_tools::config::ConfigReader<R>,
@@ -432,7 +443,8 @@ pub fn parse_line(input: TokenStream) -> TokenStream {
                     match end_type {
                         shadow_company_tools::config::EndType::None => {}
                         shadow_company_tools::config::EndType::StartKey(key) => {
-                            if line.name == key {
+                            // Sections started by a key also end at the keys of the parent.
+                            if line.name == key || !Self::has_config_key(&line.name) {
                                 // println!("found start key, breaking");
                                 break;
                             }
@@ -444,6 +456,11 @@ pub fn parse_line(input: TokenStream) -> TokenStream {
                                 break;
                             }
                         }
+                        shadow_company_tools::config::EndType::UnknownKey => {
+                            if !Self::has_config_key(&line.name) {
+                                break;
+                            }
+                        }
                     }
 
                     self.parse_config_line(reader)?;
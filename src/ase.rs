@@ -0,0 +1,1008 @@
+//! ASCII scene exports (.ase) from 3D Studio MAX. The headers of SMF files say they were made by
+//! "ASEParser", so this is the format the models were originally converted from.
+//!
+//! Only the parts that describe a model are supported: the materials with their diffuse bitmaps
+//! and the geometry and helper objects with their hierarchy, transform, mesh and material. The
+//! vertices of a mesh are stored in world space, not relative to the transform of the object.
+
+use glam::{Mat4, Quat, Vec3, Vec4};
+use thiserror::Error;
+
+use crate::fixed_string::{decode_windows_1252, encode_windows_1252};
+
+/// Converts from the left handed z-up coordinate system used by SC to the right handed z-up system
+/// used by 3D Studio MAX by mirroring the x axis, which is its own inverse. The winding of the
+/// faces has to be reversed as well and the normals are negated, because SMF normals point away
+/// from the front of the faces.
+pub const CONVERT_ASE: Mat4 = Mat4::from_cols(
+    Vec4::new(-1.0, 0.0, 0.0, 0.0),
+    Vec4::new(0.0, 1.0, 0.0, 0.0),
+    Vec4::new(0.0, 0.0, 1.0, 0.0),
+    Vec4::new(0.0, 0.0, 0.0, 1.0),
+);
+
+/// Convert a rotation with [CONVERT_ASE]. Mirroring the x axis reverses rotations around the y and z
+/// axes.
+pub fn convert_rotation(rotation: Quat) -> Quat {
+    Quat::from_xyzw(rotation.x, -rotation.y, -rotation.z, rotation.w)
+}
+
+#[derive(Debug, Error)]
+pub enum AseError {
+    #[error("IO Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Line {0}: {1}")]
+    Parse(usize, String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Scene {
+    /// The name of the scene, stored as the *SCENE_FILENAME.
+    pub name: String,
+    pub comment: Option<String>,
+    pub materials: Vec<Material>,
+    pub objects: Vec<Object>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Material {
+    pub name: String,
+    /// The path of the diffuse bitmap.
+    pub bitmap: Option<String>,
+    /// The materials of a "Multi/Sub-Object" material, selected by the material id of the faces.
+    pub sub_materials: Vec<Material>,
+}
+
+impl Material {
+    /// The material used by faces with `material_id`. 3D Studio MAX wraps the id around the number
+    /// of sub materials.
+    pub fn for_material_id(&self, material_id: u32) -> &Material {
+        if self.sub_materials.is_empty() {
+            self
+        } else {
+            &self.sub_materials[material_id as usize % self.sub_materials.len()]
+        }
+    }
+}
+
+/// A *GEOMOBJECT, or a *HELPEROBJECT if it has no mesh.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Object {
+    pub name: String,
+    pub parent: Option<String>,
+    /// The world transform of the object, from the *NODE_TM.
+    pub transform: Mat4,
+    pub mesh: Option<Mesh>,
+    /// Index into [Scene::materials].
+    pub material_ref: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    /// Positions in world space.
+    pub vertices: Vec<Vec3>,
+    pub faces: Vec<Face>,
+    pub tex_vertices: Vec<Vec3>,
+    /// Indices into `tex_vertices` for every face. Empty if the mesh is not mapped.
+    pub tex_faces: Vec<[u32; 3]>,
+    /// The vertex normals for the corners of every face. Empty if normals were not exported.
+    pub normals: Vec<[Vec3; 3]>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Face {
+    /// Indices into [Mesh::vertices], counter clockwise.
+    pub indices: [u32; 3],
+    pub material_id: u32,
+}
+
+impl Scene {
+    pub fn read(r: &mut impl std::io::Read) -> Result<Self, AseError> {
+        let mut data = vec![];
+        r.read_to_end(&mut data)?;
+        Self::parse(&decode_windows_1252(&data))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, AseError> {
+        let elements = parse_elements(&mut tokenize(text)?.into_iter().peekable(), None, 0)?;
+
+        let mut scene = Scene::default();
+        for element in elements.iter() {
+            match element.key.as_str() {
+                "COMMENT" => scene.comment = element.args.first().cloned(),
+                "SCENE" => {
+                    if let Some(file_name) = element.child("SCENE_FILENAME") {
+                        scene.name = file_name.arg(0)?.to_string();
+                    }
+                }
+                "MATERIAL_LIST" => {
+                    scene.materials = element
+                        .children_with_key("MATERIAL")
+                        .map(Material::parse)
+                        .collect::<Result<_, _>>()?;
+                }
+                "GEOMOBJECT" | "HELPEROBJECT" => scene.objects.push(Object::parse(element)?),
+                _ => {}
+            }
+        }
+
+        Ok(scene)
+    }
+
+    pub fn write(&self, w: &mut impl std::io::Write) -> std::io::Result<()> {
+        let mut w = AseWriter { w, depth: 0 };
+
+        w.line("3DSMAX_ASCIIEXPORT", "200")?;
+        if let Some(ref comment) = self.comment {
+            w.line("COMMENT", &quoted(comment))?;
+        }
+
+        w.open("SCENE", "")?;
+        w.line("SCENE_FILENAME", &quoted(&self.name))?;
+        w.line("SCENE_FIRSTFRAME", "0")?;
+        w.line("SCENE_LASTFRAME", "100")?;
+        w.line("SCENE_FRAMESPEED", "30")?;
+        w.line("SCENE_TICKSPERFRAME", "160")?;
+        w.close()?;
+
+        w.open("MATERIAL_LIST", "")?;
+        w.line("MATERIAL_COUNT", &self.materials.len().to_string())?;
+        for (index, material) in self.materials.iter().enumerate() {
+            material.write(&mut w, "MATERIAL", index)?;
+        }
+        w.close()?;
+
+        for object in self.objects.iter() {
+            object.write(&mut w)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Material {
+    fn parse(element: &Element) -> Result<Self, AseError> {
+        let name = match element.child("MATERIAL_NAME") {
+            Some(name) => name.arg(0)?.to_string(),
+            None => String::new(),
+        };
+        let bitmap = match element
+            .child("MAP_DIFFUSE")
+            .and_then(|map| map.child("BITMAP"))
+        {
+            Some(bitmap) => Some(bitmap.arg(0)?.to_string()),
+            None => None,
+        };
+        let sub_materials = element
+            .children_with_key("SUBMATERIAL")
+            .map(Material::parse)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            name,
+            bitmap,
+            sub_materials,
+        })
+    }
+
+    fn write<W: std::io::Write>(
+        &self,
+        w: &mut AseWriter<W>,
+        key: &str,
+        index: usize,
+    ) -> std::io::Result<()> {
+        w.open(key, &index.to_string())?;
+        w.line("MATERIAL_NAME", &quoted(&self.name))?;
+        if self.sub_materials.is_empty() {
+            w.line("MATERIAL_CLASS", &quoted("Standard"))?;
+        } else {
+            w.line("MATERIAL_CLASS", &quoted("Multi/Sub-Object"))?;
+        }
+        if let Some(ref bitmap) = self.bitmap {
+            w.open("MAP_DIFFUSE", "")?;
+            w.line("MAP_NAME", &quoted(&self.name))?;
+            w.line("MAP_CLASS", &quoted("Bitmap"))?;
+            w.line("MAP_AMOUNT", "1.0000")?;
+            w.line("BITMAP", &quoted(bitmap))?;
+            w.close()?;
+        }
+        if !self.sub_materials.is_empty() {
+            w.line("NUMSUBMTLS", &self.sub_materials.len().to_string())?;
+            for (index, sub_material) in self.sub_materials.iter().enumerate() {
+                sub_material.write(w, "SUBMATERIAL", index)?;
+            }
+        }
+        w.close()
+    }
+}
+
+impl Object {
+    fn parse(element: &Element) -> Result<Self, AseError> {
+        let name = element
+            .child("NODE_NAME")
+            .ok_or_else(|| element.error("Object without a *NODE_NAME."))?
+            .arg(0)?
+            .to_string();
+        let parent = match element.child("NODE_PARENT") {
+            Some(parent) => Some(parent.arg(0)?.to_string()),
+            None => None,
+        };
+        let transform = match element.child("NODE_TM") {
+            Some(tm) => parse_transform(tm)?,
+            None => Mat4::IDENTITY,
+        };
+        let mesh = match element.child("MESH") {
+            Some(mesh) if element.key == "GEOMOBJECT" => Some(Mesh::parse(mesh)?),
+            _ => None,
+        };
+        let material_ref = match element.child("MATERIAL_REF") {
+            Some(material_ref) => Some(material_ref.u32(0)? as usize),
+            None => None,
+        };
+
+        Ok(Self {
+            name,
+            parent,
+            transform,
+            mesh,
+            material_ref,
+        })
+    }
+
+    fn write<W: std::io::Write>(&self, w: &mut AseWriter<W>) -> std::io::Result<()> {
+        w.open(
+            if self.mesh.is_some() {
+                "GEOMOBJECT"
+            } else {
+                "HELPEROBJECT"
+            },
+            "",
+        )?;
+        w.line("NODE_NAME", &quoted(&self.name))?;
+        if let Some(ref parent) = self.parent {
+            w.line("NODE_PARENT", &quoted(parent))?;
+        }
+        if self.mesh.is_none() {
+            w.line("HELPER_CLASS", &quoted("Dummy"))?;
+        }
+
+        let (scale, rotation, translation) = self.transform.to_scale_rotation_translation();
+        let (axis, angle) = rotation.to_axis_angle();
+        w.open("NODE_TM", "")?;
+        w.line("NODE_NAME", &quoted(&self.name))?;
+        w.line("INHERIT_POS", "0 0 0")?;
+        w.line("INHERIT_ROT", "0 0 0")?;
+        w.line("INHERIT_SCL", "0 0 0")?;
+        w.line("TM_ROW0", &vec3(self.transform.x_axis.truncate()))?;
+        w.line("TM_ROW1", &vec3(self.transform.y_axis.truncate()))?;
+        w.line("TM_ROW2", &vec3(self.transform.z_axis.truncate()))?;
+        w.line("TM_ROW3", &vec3(self.transform.w_axis.truncate()))?;
+        w.line("TM_POS", &vec3(translation))?;
+        w.line("TM_ROTAXIS", &vec3(axis))?;
+        w.line("TM_ROTANGLE", &float(angle))?;
+        w.line("TM_SCALE", &vec3(scale))?;
+        w.line("TM_SCALEAXIS", "0 0 0")?;
+        w.line("TM_SCALEAXISANG", "0")?;
+        w.close()?;
+
+        if let Some(ref mesh) = self.mesh {
+            mesh.write(w)?;
+        }
+        if let Some(material_ref) = self.material_ref {
+            w.line("MATERIAL_REF", &material_ref.to_string())?;
+        }
+        w.close()
+    }
+}
+
+/// Use the rows of the matrix if they are there, otherwise build the matrix from the decomposed
+/// parts.
+fn parse_transform(tm: &Element) -> Result<Mat4, AseError> {
+    if let (Some(row0), Some(row1), Some(row2), Some(row3)) = (
+        tm.child("TM_ROW0"),
+        tm.child("TM_ROW1"),
+        tm.child("TM_ROW2"),
+        tm.child("TM_ROW3"),
+    ) {
+        return Ok(Mat4::from_cols(
+            row0.vec3(0)?.extend(0.0),
+            row1.vec3(0)?.extend(0.0),
+            row2.vec3(0)?.extend(0.0),
+            row3.vec3(0)?.extend(1.0),
+        ));
+    }
+
+    let translation = match tm.child("TM_POS") {
+        Some(pos) => pos.vec3(0)?,
+        None => Vec3::ZERO,
+    };
+    let rotation = match (tm.child("TM_ROTAXIS"), tm.child("TM_ROTANGLE")) {
+        (Some(axis), Some(angle)) => {
+            let axis = axis.vec3(0)?.normalize_or_zero();
+            if axis == Vec3::ZERO {
+                Quat::IDENTITY
+            } else {
+                Quat::from_axis_angle(axis, angle.f32(0)?)
+            }
+        }
+        _ => Quat::IDENTITY,
+    };
+    let scale = match tm.child("TM_SCALE") {
+        Some(scale) => scale.vec3(0)?,
+        None => Vec3::ONE,
+    };
+
+    Ok(Mat4::from_scale_rotation_translation(
+        scale,
+        rotation,
+        translation,
+    ))
+}
+
+impl Mesh {
+    fn parse(mesh_element: &Element) -> Result<Self, AseError> {
+        let mut mesh = Mesh::default();
+
+        for element in mesh_element.children.iter() {
+            match element.key.as_str() {
+                "MESH_VERTEX_LIST" => {
+                    let limit = list_limit(mesh_element, "MESH_NUMVERTEX", element, "MESH_VERTEX")?;
+                    for vertex in element.children_with_key("MESH_VERTEX") {
+                        set(&mut mesh.vertices, vertex, limit, vertex.vec3(1)?)?;
+                    }
+                }
+                "MESH_FACE_LIST" => {
+                    let limit = list_limit(mesh_element, "MESH_NUMFACES", element, "MESH_FACE")?;
+                    let mut current = None;
+                    for child in element.children.iter() {
+                        match child.key.as_str() {
+                            "MESH_FACE" => {
+                                let index = child.u32(0)?;
+                                let indices = [
+                                    child.labeled_u32("A:")?,
+                                    child.labeled_u32("B:")?,
+                                    child.labeled_u32("C:")?,
+                                ];
+                                set(
+                                    &mut mesh.faces,
+                                    child,
+                                    limit,
+                                    Face {
+                                        indices,
+                                        material_id: 0,
+                                    },
+                                )?;
+                                current = Some(index as usize);
+                            }
+                            // Written on the same line, after the face it belongs to.
+                            "MESH_MTLID" => {
+                                if let Some(face) = current.and_then(|i| mesh.faces.get_mut(i)) {
+                                    face.material_id = child.u32(0)?;
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                "MESH_TVERTLIST" => {
+                    let limit = list_limit(mesh_element, "MESH_NUMTVERTEX", element, "MESH_TVERT")?;
+                    for tex_vertex in element.children_with_key("MESH_TVERT") {
+                        set(
+                            &mut mesh.tex_vertices,
+                            tex_vertex,
+                            limit,
+                            tex_vertex.vec3(1)?,
+                        )?;
+                    }
+                }
+                "MESH_TFACELIST" => {
+                    let limit = list_limit(mesh_element, "MESH_NUMTVFACES", element, "MESH_TFACE")?;
+                    for tex_face in element.children_with_key("MESH_TFACE") {
+                        set(
+                            &mut mesh.tex_faces,
+                            tex_face,
+                            limit,
+                            [tex_face.u32(1)?, tex_face.u32(2)?, tex_face.u32(3)?],
+                        )?;
+                    }
+                }
+                "MESH_NORMALS" => {
+                    // Every *MESH_FACENORMAL is followed by the normals of its three corners.
+                    let limit =
+                        list_limit(mesh_element, "MESH_NUMFACES", element, "MESH_FACENORMAL")?;
+                    let mut current = None;
+                    let mut corner = 0;
+                    for normal in element.children.iter() {
+                        match normal.key.as_str() {
+                            "MESH_FACENORMAL" => {
+                                let index = normal.u32(0)?;
+                                set(&mut mesh.normals, normal, limit, [Vec3::ZERO; 3])?;
+                                current = Some(index as usize);
+                                corner = 0;
+                            }
+                            "MESH_VERTEXNORMAL" => {
+                                if let Some(normals) = current.and_then(|i| mesh.normals.get_mut(i))
+                                {
+                                    if corner < 3 {
+                                        normals[corner] = normal.vec3(1)?;
+                                        corner += 1;
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(mesh)
+    }
+
+    fn write<W: std::io::Write>(&self, w: &mut AseWriter<W>) -> std::io::Result<()> {
+        w.open("MESH", "")?;
+        w.line("TIMEVALUE", "0")?;
+        w.line("MESH_NUMVERTEX", &self.vertices.len().to_string())?;
+        w.line("MESH_NUMFACES", &self.faces.len().to_string())?;
+
+        w.open("MESH_VERTEX_LIST", "")?;
+        for (index, vertex) in self.vertices.iter().enumerate() {
+            w.line("MESH_VERTEX", &format!("{} {}", index, vec3(*vertex)))?;
+        }
+        w.close()?;
+
+        w.open("MESH_FACE_LIST", "")?;
+        for (index, face) in self.faces.iter().enumerate() {
+            let [a, b, c] = face.indices;
+            w.line(
+                "MESH_FACE",
+                &format!(
+                    "{}: A: {} B: {} C: {} AB: 1 BC: 1 CA: 1 *MESH_SMOOTHING 1 *MESH_MTLID {}",
+                    index, a, b, c, face.material_id
+                ),
+            )?;
+        }
+        w.close()?;
+
+        if !self.tex_faces.is_empty() {
+            w.line("MESH_NUMTVERTEX", &self.tex_vertices.len().to_string())?;
+            w.open("MESH_TVERTLIST", "")?;
+            for (index, tex_vertex) in self.tex_vertices.iter().enumerate() {
+                w.line("MESH_TVERT", &format!("{} {}", index, vec3(*tex_vertex)))?;
+            }
+            w.close()?;
+
+            w.line("MESH_NUMTVFACES", &self.tex_faces.len().to_string())?;
+            w.open("MESH_TFACELIST", "")?;
+            for (index, [a, b, c]) in self.tex_faces.iter().enumerate() {
+                w.line("MESH_TFACE", &format!("{} {} {} {}", index, a, b, c))?;
+            }
+            w.close()?;
+        }
+
+        if !self.normals.is_empty() {
+            w.open("MESH_NORMALS", "")?;
+            for (index, (face, normals)) in self.faces.iter().zip(self.normals.iter()).enumerate() {
+                let corner = |i: usize| self.vertices.get(face.indices[i] as usize).copied();
+                let face_normal = match (corner(0), corner(1), corner(2)) {
+                    (Some(a), Some(b), Some(c)) => (b - a).cross(c - a).normalize_or_zero(),
+                    _ => Vec3::ZERO,
+                };
+                w.line(
+                    "MESH_FACENORMAL",
+                    &format!("{} {}", index, vec3(face_normal)),
+                )?;
+                for (vertex, normal) in face.indices.iter().zip(normals.iter()) {
+                    w.line(
+                        "MESH_VERTEXNORMAL",
+                        &format!("{} {}", vertex, vec3(*normal)),
+                    )?;
+                }
+            }
+            w.close()?;
+        }
+
+        w.close()
+    }
+}
+
+/// The number of entries a list in `mesh` can hold: the count declared by `count_key`, but no more
+/// than the number of `entry_key` entries in it, so a broken index can not grow the list without
+/// bound.
+fn list_limit(
+    mesh: &Element,
+    count_key: &str,
+    list: &Element,
+    entry_key: &str,
+) -> Result<usize, AseError> {
+    let entries = list.children_with_key(entry_key).count();
+    Ok(match mesh.child(count_key) {
+        Some(count) => entries.min(count.u32(0)? as usize),
+        None => entries,
+    })
+}
+
+/// Store `value` at the index in the first argument of `entry`, growing `values` if needed. ASE
+/// lists are indexed explicitly.
+fn set<T: Clone + Default>(
+    values: &mut Vec<T>,
+    entry: &Element,
+    limit: usize,
+    value: T,
+) -> Result<(), AseError> {
+    let index = entry.u32(0)? as usize;
+    if index >= limit {
+        return Err(entry.error(&format!(
+            "Index {} is out of range, expected less than {}.",
+            index, limit
+        )));
+    }
+    if index >= values.len() {
+        values.resize(index + 1, T::default());
+    }
+    values[index] = value;
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    /// A keyword, without the leading '*'.
+    Key(String),
+    /// A quoted string, without the quotes.
+    String(String),
+    Value(String),
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, AseError> {
+    let mut tokens = vec![];
+    let mut line = 1;
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '{' => tokens.push((line, Token::Open)),
+            '}' => tokens.push((line, Token::Close)),
+            '"' => {
+                let start = line;
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            s.push(c);
+                        }
+                        None => return Err(AseError::Parse(start, "Unterminated string.".into())),
+                    }
+                }
+                tokens.push((start, Token::String(s)));
+            }
+            c => {
+                let mut s = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '{' | '}' | '"') {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                tokens.push((
+                    line,
+                    match s.strip_prefix('*') {
+                        Some(key) => Token::Key(key.to_string()),
+                        None => Token::Value(s),
+                    },
+                ));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// A keyword with its arguments and the elements in its block, if it has one. Keywords that
+/// appear on the same line, like the *MESH_MTLID of a *MESH_FACE, are separate elements.
+struct Element {
+    line: usize,
+    key: String,
+    args: Vec<String>,
+    children: Vec<Element>,
+}
+
+/// Blocks nested deeper than this are rejected. Files written by 3D Studio MAX nest about 5 deep.
+const MAX_DEPTH: usize = 64;
+
+/// Parse elements until the end of the block opened on `open_line`, or until the end of the
+/// tokens if there is no block. `depth` is the number of blocks that are open.
+fn parse_elements(
+    tokens: &mut std::iter::Peekable<impl Iterator<Item = (usize, Token)>>,
+    open_line: Option<usize>,
+    depth: usize,
+) -> Result<Vec<Element>, AseError> {
+    if depth > MAX_DEPTH {
+        return Err(AseError::Parse(
+            open_line.unwrap_or_default(),
+            "Blocks are nested too deep.".into(),
+        ));
+    }
+    let mut elements = vec![];
+
+    loop {
+        let Some((line, token)) = tokens.next() else {
+            return match open_line {
+                Some(line) => Err(AseError::Parse(line, "Block is not closed.".into())),
+                None => Ok(elements),
+            };
+        };
+
+        match token {
+            Token::Key(key) => {
+                let mut args = vec![];
+                while let Some((_, Token::String(arg) | Token::Value(arg))) = tokens.peek() {
+                    args.push(arg.clone());
+                    tokens.next();
+                }
+                let children = if let Some((_, Token::Open)) = tokens.peek() {
+                    tokens.next();
+                    parse_elements(tokens, Some(line), depth + 1)?
+                } else {
+                    vec![]
+                };
+                elements.push(Element {
+                    line,
+                    key,
+                    args,
+                    children,
+                });
+            }
+            Token::Close => {
+                return match open_line {
+                    Some(_) => Ok(elements),
+                    None => Err(AseError::Parse(line, "Unexpected '}'.".into())),
+                };
+            }
+            // Blocks without a keyword are skipped.
+            Token::Open => {
+                parse_elements(tokens, Some(line), depth + 1)?;
+            }
+            Token::String(_) | Token::Value(_) => {}
+        }
+    }
+}
+
+impl Element {
+    fn child(&self, key: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.key == key)
+    }
+
+    fn children_with_key<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.key == key)
+    }
+
+    fn error(&self, message: &str) -> AseError {
+        AseError::Parse(self.line, format!("*{}: {}", self.key, message))
+    }
+
+    fn arg(&self, index: usize) -> Result<&str, AseError> {
+        self.args
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| self.error(&format!("Missing argument {}.", index + 1)))
+    }
+
+    fn u32(&self, index: usize) -> Result<u32, AseError> {
+        let arg = self.arg(index)?;
+        // Face indices are written as "0:".
+        arg.trim_end_matches(':')
+            .parse()
+            .map_err(|_| self.error(&format!("Invalid integer \"{}\".", arg)))
+    }
+
+    fn f32(&self, index: usize) -> Result<f32, AseError> {
+        let arg = self.arg(index)?;
+        arg.parse()
+            .map_err(|_| self.error(&format!("Invalid number \"{}\".", arg)))
+    }
+
+    fn vec3(&self, index: usize) -> Result<Vec3, AseError> {
+        Ok(Vec3::new(
+            self.f32(index)?,
+            self.f32(index + 1)?,
+            self.f32(index + 2)?,
+        ))
+    }
+
+    /// The integer following a label like "A:".
+    fn labeled_u32(&self, label: &str) -> Result<u32, AseError> {
+        let index = self
+            .args
+            .iter()
+            .position(|arg| arg == label)
+            .ok_or_else(|| self.error(&format!("Missing \"{}\".", label)))?;
+        self.u32(index + 1)
+    }
+}
+
+struct AseWriter<'a, W: std::io::Write> {
+    w: &'a mut W,
+    depth: usize,
+}
+
+impl<W: std::io::Write> AseWriter<'_, W> {
+    fn line(&mut self, key: &str, args: &str) -> std::io::Result<()> {
+        if args.is_empty() {
+            self.write(&format!("*{}", key))
+        } else {
+            self.write(&format!("*{} {}", key, args))
+        }
+    }
+
+    fn open(&mut self, key: &str, args: &str) -> std::io::Result<()> {
+        if args.is_empty() {
+            self.write(&format!("*{} {{", key))?;
+        } else {
+            self.write(&format!("*{} {} {{", key, args))?;
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.depth = self.depth.saturating_sub(1);
+        self.write("}")
+    }
+
+    /// Write an indented line. The text is encoded as Windows-1252, like the names in SMF files.
+    fn write(&mut self, line: &str) -> std::io::Result<()> {
+        for _ in 0..self.depth {
+            self.w.write_all(b"\t")?;
+        }
+        self.w.write_all(&encode_windows_1252(line))?;
+        self.w.write_all(b"\n")
+    }
+}
+
+fn quoted(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "'"))
+}
+
+/// The shortest representation that reads back as the same value, so models survive a round trip.
+fn float(value: f32) -> String {
+    // Adding 0.0 turns -0.0 into 0.0.
+    format!("{}", value + 0.0)
+}
+
+fn vec3(v: Vec3) -> String {
+    format!("{} {} {}", float(v.x), float(v.y), float(v.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOX: &str = r#"*3DSMAX_ASCIIEXPORT	200
+*COMMENT "AsciiExport Version  2.00"
+*SCENE {
+	*SCENE_FILENAME "box.max"
+}
+*MATERIAL_LIST {
+	*MATERIAL_COUNT 1
+	*MATERIAL 0 {
+		*MATERIAL_NAME "Multi"
+		*MATERIAL_CLASS "Multi/Sub-Object"
+		*NUMSUBMTLS 2
+		*SUBMATERIAL 0 {
+			*MATERIAL_NAME "side"
+			*MAP_DIFFUSE {
+				*BITMAP "C:\maps\side.bmp"
+			}
+		}
+		*SUBMATERIAL 1 {
+			*MATERIAL_NAME "top"
+		}
+	}
+}
+*HELPEROBJECT {
+	*NODE_NAME "root"
+	*HELPER_CLASS "Dummy"
+	*NODE_TM {
+		*NODE_NAME "root"
+		*TM_POS 0.0000 0.0000 0.0000
+	}
+}
+*GEOMOBJECT {
+	*NODE_NAME "box"
+	*NODE_PARENT "root"
+	*NODE_TM {
+		*NODE_NAME "box"
+		*TM_ROW0 1.0000 0.0000 0.0000
+		*TM_ROW1 0.0000 1.0000 0.0000
+		*TM_ROW2 0.0000 0.0000 1.0000
+		*TM_ROW3 1.0000 2.0000 3.0000
+	}
+	*MESH {
+		*MESH_NUMVERTEX 3
+		*MESH_NUMFACES 1
+		*MESH_VERTEX_LIST {
+			*MESH_VERTEX    0	1.0000	2.0000	3.0000
+			*MESH_VERTEX    1	2.0000	2.0000	3.0000
+			*MESH_VERTEX    2	1.0000	3.0000	3.0000
+		}
+		*MESH_FACE_LIST {
+			*MESH_FACE    0:    A:    0 B:    1 C:    2 AB:    1 BC:    1 CA:    1	 *MESH_SMOOTHING 	*MESH_MTLID 1
+		}
+		*MESH_NUMTVERTEX 1
+		*MESH_TVERTLIST {
+			*MESH_TVERT 0	0.5000	0.2500	0.0000
+		}
+		*MESH_NUMTVFACES 1
+		*MESH_TFACELIST {
+			*MESH_TFACE 0	0	0	0
+		}
+		*MESH_NORMALS {
+			*MESH_FACENORMAL 0	0.0000	0.0000	1.0000
+				*MESH_VERTEXNORMAL 0	0.0000	0.0000	1.0000
+				*MESH_VERTEXNORMAL 1	0.0000	1.0000	0.0000
+				*MESH_VERTEXNORMAL 2	1.0000	0.0000	0.0000
+		}
+	}
+	*MATERIAL_REF 0
+}
+"#;
+
+    #[test]
+    fn parse_and_write_round_trip() {
+        let scene = Scene::parse(BOX).unwrap();
+
+        assert_eq!(scene.name, "box.max");
+        let material = &scene.materials[0];
+        assert_eq!(
+            material.for_material_id(0).bitmap.as_deref(),
+            Some("C:\\maps\\side.bmp")
+        );
+        assert_eq!(material.for_material_id(3).name, "top");
+
+        let [root, object] = scene.objects.as_slice() else {
+            panic!("Expected two objects.");
+        };
+        assert_eq!((root.parent.as_deref(), root.mesh.is_none()), (None, true));
+        assert_eq!(object.parent.as_deref(), Some("root"));
+        assert_eq!(object.transform.w_axis.truncate(), Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(object.material_ref, Some(0));
+
+        let mesh = object.mesh.as_ref().unwrap();
+        assert_eq!(mesh.vertices[1], Vec3::new(2.0, 2.0, 3.0));
+        assert_eq!(
+            mesh.faces,
+            vec![Face {
+                indices: [0, 1, 2],
+                material_id: 1
+            }]
+        );
+        assert_eq!(mesh.tex_vertices, vec![Vec3::new(0.5, 0.25, 0.0)]);
+        assert_eq!(mesh.tex_faces, vec![[0, 0, 0]]);
+        assert_eq!(mesh.normals, vec![[Vec3::Z, Vec3::Y, Vec3::X]]);
+
+        let mut data = vec![];
+        scene.write(&mut data).unwrap();
+        let written = Scene::read(&mut data.as_slice()).unwrap();
+        assert_eq!(written, scene);
+
+        // Text is stored as Windows-1252.
+        let scene = Scene {
+            name: "caf\u{E9}.max".to_string(),
+            ..scene
+        };
+        let mut data = vec![];
+        scene.write(&mut data).unwrap();
+        assert!(data.windows(5).any(|w| w == b"caf\xE9."));
+        assert_eq!(Scene::read(&mut data.as_slice()).unwrap(), scene);
+    }
+
+    fn parse_error(text: &str) -> (usize, String) {
+        match Scene::parse(text) {
+            Err(AseError::Parse(line, message)) => (line, message),
+            result => panic!("Expected a parse error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn malformed_input() {
+        assert_eq!(
+            parse_error("*SCENE {\n*SCENE_FILENAME \"box.max\n}\n"),
+            (2, "Unterminated string.".to_string())
+        );
+        assert_eq!(
+            parse_error("*SCENE {\n}\n}\n"),
+            (3, "Unexpected '}'.".to_string())
+        );
+        assert_eq!(
+            parse_error("*SCENE {\n*SCENE_FILENAME \"box.max\"\n"),
+            (1, "Block is not closed.".to_string())
+        );
+        assert_eq!(
+            parse_error(&"*A {".repeat(1000)),
+            (1, "Blocks are nested too deep.".to_string())
+        );
+
+        let missing_label = BOX.replace("A:    0 B:", "0 B:");
+        assert_eq!(parse_error(&missing_label).1, "*MESH_FACE: Missing \"A:\".");
+    }
+
+    #[test]
+    fn indices_out_of_range() {
+        // Larger than the declared *MESH_NUMVERTEX.
+        let (_, message) = parse_error(&BOX.replace("*MESH_VERTEX    2", "*MESH_VERTEX    3"));
+        assert_eq!(
+            message,
+            "*MESH_VERTEX: Index 3 is out of range, expected less than 3."
+        );
+
+        // A huge index must not allocate, even if the count claims the list is that large.
+        let huge = BOX
+            .replace("*MESH_NUMFACES 1", "*MESH_NUMFACES 4000000000")
+            .replace("*MESH_FACE    0:", "*MESH_FACE    3999999999:");
+        assert_eq!(
+            parse_error(&huge).1,
+            "*MESH_FACE: Index 3999999999 is out of range, expected less than 1."
+        );
+
+        let (_, message) = parse_error(&BOX.replace("*MESH_TFACE 0", "*MESH_TFACE 1"));
+        assert_eq!(
+            message,
+            "*MESH_TFACE: Index 1 is out of range, expected less than 1."
+        );
+    }
+
+    #[test]
+    fn sub_materials() {
+        let scene = Scene::parse(BOX).unwrap();
+        let material = &scene.materials[0];
+
+        assert_eq!(material.name, "Multi");
+        assert_eq!(material.bitmap, None);
+        assert_eq!(
+            material
+                .sub_materials
+                .iter()
+                .map(|sub_material| (sub_material.name.as_str(), sub_material.bitmap.as_deref()))
+                .collect::<Vec<_>>(),
+            [("side", Some("C:\\maps\\side.bmp")), ("top", None)]
+        );
+        // Material ids wrap around the number of sub materials.
+        assert_eq!(material.for_material_id(2).name, "side");
+        assert_eq!(material.sub_materials[1].for_material_id(5).name, "top");
+    }
+
+    #[test]
+    fn helper_objects() {
+        let scene = Scene::parse(BOX).unwrap();
+        let helper = &scene.objects[0];
+
+        assert_eq!(helper.name, "root");
+        assert_eq!(helper.mesh, None);
+        assert_eq!(helper.material_ref, None);
+        assert_eq!(helper.transform, Mat4::IDENTITY);
+
+        // A *MESH inside a *HELPEROBJECT is ignored, and helpers are written back as helpers.
+        let with_mesh = BOX.replace(
+            "*HELPER_CLASS \"Dummy\"",
+            "*HELPER_CLASS \"Dummy\"\n\t*MESH {\n\t\t*MESH_NUMVERTEX 0\n\t}",
+        );
+        assert_eq!(Scene::parse(&with_mesh).unwrap(), scene);
+
+        let mut data = vec![];
+        scene.write(&mut data).unwrap();
+        let text = String::from_utf8(data).unwrap();
+        assert_eq!(text.matches("*HELPEROBJECT {").count(), 1);
+        assert_eq!(text.matches("*GEOMOBJECT {").count(), 1);
+    }
+}
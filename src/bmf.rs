@@ -1,7 +1,7 @@
 use bitflags::bitflags;
 use glam::{Quat, Vec3};
 
-use crate::io;
+use crate::{fixed_string::FixedString, io};
 
 #[derive(Clone, Debug)]
 pub struct Bone {
@@ -46,7 +46,7 @@ bitflags! {
 
 #[derive(Debug)]
 pub struct Motion {
-    pub name: String,
+    pub name: FixedString,
     pub flags: MotionFlags,
     pub hash: u32,
     pub key_frame_count: u32,
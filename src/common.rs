@@ -1,6 +1,9 @@
-use crate::io::Reader;
+use std::hash::Hasher;
 
-const HASH_LOOKUP_TABLE: [u16; 256] = [
+use crate::{fixed_string::encode_windows_1252_char, io::Reader};
+
+/// The lookup table used by the engine's CRC-style name hash. See [encode_char].
+pub const HASH_LOOKUP_TABLE: [u16; 256] = [
     0x0000, 0x1021, 0x2042, 0x3063, 0x4084, 0x50A5, 0x60C6, 0x70E7, 0x8108, 0x9129, 0xA14A, 0xB16B,
     0xC18C, 0xD1AD, 0xE1CE, 0xF1EF, 0x1231, 0x210, 0x3273, 0x2252, 0x52B5, 0x4294, 0x72F7, 0x62D6,
     0x9339, 0x8318, 0xB37B, 0xA35A, 0xD3BD, 0xC39C, 0xF3FF, 0xE3DE, 0x2462, 0x3443, 0x420, 0x1401,
@@ -25,35 +28,213 @@ const HASH_LOOKUP_TABLE: [u16; 256] = [
     0x2E93, 0x3EB2, 0xED1, 0x1EF0,
 ];
 
-fn encode_char(hash: &mut u16, ch: u8) {
+/// Maps the low byte of each entry in [HASH_LOOKUP_TABLE] back to its index. The low bytes are all
+/// unique, which makes [encode_char] reversible.
+const HASH_REVERSE_TABLE: [u8; 256] = {
+    let mut table = [0_u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[(HASH_LOOKUP_TABLE[i] & 0xFF) as usize] = i as u8;
+        i += 1;
+    }
+    table
+};
+
+/// Update one of the two 16-bit hash lanes with a character.
+pub fn encode_char(hash: &mut u16, ch: u8) {
     let index = (*hash >> 8) ^ ch as u16;
     let hash_lookup = HASH_LOOKUP_TABLE[index as usize];
     *hash = hash_lookup ^ *hash << 8;
 }
 
+/// Undo [encode_char]. Given the lane value after `ch` was encoded, returns the value before it.
+pub fn decode_char(hash: &mut u16, ch: u8) {
+    let index = HASH_REVERSE_TABLE[(*hash & 0xFF) as usize];
+    let hash_lookup = HASH_LOOKUP_TABLE[index as usize];
+    let low = ((*hash ^ hash_lookup) >> 8) as u8;
+    let high = index ^ ch;
+    *hash = (high as u16) << 8 | low as u16;
+}
+
+/// Hash a name the way the engine does. ASCII letters are hashed as lowercase and hashing stops at
+/// the first NUL.
 pub fn hash(path: &[u8]) -> u32 {
-    let mut parts = [0xFFFFu16; 2];
-    let mut i = 0;
-    loop {
-        if i >= path.len() {
-            break;
+    let mut hasher = GameHasher::default();
+    hasher.write(path);
+    hasher.value()
+}
+
+/// An incremental version of [hash].
+///
+/// Characters at even positions are encoded into the high lane and characters at odd positions
+/// into the low lane. Once a NUL is written, everything after it is ignored.
+#[derive(Clone, Copy, Debug)]
+pub struct GameHasher {
+    lanes: [u16; 2],
+    position: usize,
+    terminated: bool,
+}
+
+impl Default for GameHasher {
+    fn default() -> Self {
+        Self {
+            lanes: [0xFFFF; 2],
+            position: 0,
+            terminated: false,
         }
-        let ch = {
-            if path[i] >= 0x41 && path[i] <= 0x5A {
-                path[i] + 0x20
-            } else {
-                path[i]
+    }
+}
+
+impl GameHasher {
+    /// The hash of everything written so far.
+    pub fn value(&self) -> u32 {
+        (self.lanes[0] as u32) << 16 | self.lanes[1] as u32
+    }
+}
+
+impl std::hash::Hasher for GameHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        if self.terminated {
+            return;
+        }
+
+        for ch in bytes.iter().map(u8::to_ascii_lowercase) {
+            if ch == 0 {
+                self.terminated = true;
+                return;
             }
-        };
-        if ch == 0 {
-            break;
+
+            encode_char(&mut self.lanes[self.position & 1], ch);
+            self.position += 1;
         }
+    }
 
-        encode_char(&mut parts[i & 1], ch);
-        i += 1;
+    /// The engine hash is only 32 bits, and hashbrown takes the control tag of an entry from the
+    /// top 7 bits of the hash. Returning the hash zero extended would give every entry the same
+    /// tag, so it is multiplied into the high bits. Equal names still get equal hashes.
+    fn finish(&self) -> u64 {
+        (self.value() as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+    }
+}
+
+/// A [std::hash::BuildHasher] for [GameHasher].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BuildGameHasher;
+
+impl std::hash::BuildHasher for BuildGameHasher {
+    type Hasher = GameHasher;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        GameHasher::default()
+    }
+}
+
+/// A [std::collections::HashMap] that hashes its keys with the engine's name hash. Use [GameName]
+/// keys to get case insensitive lookups that stop at a NUL, like the engine does.
+pub type GameHashMap<K, V> = std::collections::HashMap<K, V, BuildGameHasher>;
+
+/// The part of a name that the engine looks at, everything up to the first NUL.
+fn significant_bytes(bytes: &[u8]) -> &[u8] {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    &bytes[..end]
+}
+
+/// A borrowed name that compares and hashes like the engine does. This is the borrowed form of
+/// [GameName], so `&str` can be used to look up entries in a [GameHashMap].
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct GameStr(str);
+
+impl GameStr {
+    pub fn new(name: &str) -> &Self {
+        // SAFETY: GameStr is a transparent wrapper around str.
+        unsafe { &*(name as *const str as *const GameStr) }
     }
 
-    (parts[0] as u32) << 16 | parts[1] as u32
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for GameStr {
+    fn eq(&self, other: &Self) -> bool {
+        significant_bytes(self.0.as_bytes())
+            .eq_ignore_ascii_case(significant_bytes(other.0.as_bytes()))
+    }
+}
+
+impl Eq for GameStr {}
+
+impl std::hash::Hash for GameStr {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        // Write the Windows-1252 bytes the engine sees, without a length or terminator, so the
+        // result matches [hash] when used with a [GameHasher]. Everything after a NUL is already
+        // ignored by the comparison, so it is not written.
+        let name = self.0.split('\0').next().unwrap_or_default();
+        for ch in name.chars() {
+            state.write_u8(encode_windows_1252_char(ch).unwrap_or(b'?'));
+        }
+    }
+}
+
+/// An owned name that compares and hashes like the engine does.
+#[derive(Clone, Debug, Default)]
+pub struct GameName(String);
+
+impl GameName {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::ops::Deref for GameName {
+    type Target = GameStr;
+
+    fn deref(&self) -> &Self::Target {
+        GameStr::new(&self.0)
+    }
+}
+
+impl std::borrow::Borrow<GameStr> for GameName {
+    fn borrow(&self) -> &GameStr {
+        GameStr::new(&self.0)
+    }
+}
+
+impl PartialEq for GameName {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for GameName {}
+
+impl std::hash::Hash for GameName {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        (**self).hash(state);
+    }
+}
+
+impl From<&str> for GameName {
+    fn from(value: &str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<String> for GameName {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl std::fmt::Display for GameName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
 }
 
 pub fn decrypt_buf(s: &mut [u8]) {
@@ -67,3 +248,48 @@ pub fn print_buf(r: &mut impl Reader, len: usize) -> std::io::Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_char_reverses_encode_char() {
+        for start in [0x0000_u16, 0xFFFF, 0x1234, 0xBEEF] {
+            for ch in 0..=255_u8 {
+                let mut hash = start;
+                encode_char(&mut hash, ch);
+                decode_char(&mut hash, ch);
+                assert_eq!(hash, start);
+            }
+        }
+    }
+
+    #[test]
+    fn game_hash_map() {
+        use std::hash::{BuildHasher, Hash};
+
+        let engine_hash = |name: &str| {
+            let mut hasher = BuildGameHasher.build_hasher();
+            GameStr::new(name).hash(&mut hasher);
+            hasher.value()
+        };
+        assert_eq!(engine_hash("Data\\Models\0garbage"), hash(b"data\\models"));
+        assert_eq!(
+            BuildGameHasher.hash_one(GameStr::new("Data\\Models\0garbage")),
+            BuildGameHasher.hash_one(GameStr::new("data\\models"))
+        );
+        // The control tags of hashbrown come from the top 7 bits, they must not all be the same.
+        let tags = ["a", "b", "c", "d", "bip01", "bip01 spine"]
+            .map(|name| BuildGameHasher.hash_one(GameStr::new(name)) >> 57);
+        assert!(tags.iter().any(|&tag| tag != tags[0]));
+
+        let mut map = GameHashMap::default();
+        map.insert(GameName::from("Bip01 Spine"), 1);
+        assert_eq!(map.get(GameStr::new("BIP01 SPINE")), Some(&1));
+        assert_eq!(map.get(GameStr::new("bip01 spine\0")), Some(&1));
+        assert_eq!(map.get(GameStr::new("bip01")), None);
+
+        assert_eq!(engine_hash("Caf\u{C9}"), hash(b"caf\xC9"));
+    }
+}
@@ -5,6 +5,9 @@ pub enum EndType {
     None,
     StartKey(&'static str),
     EndKey(&'static str),
+    /// Stop at the first key the structure does not know about, so the parent can handle it. Used
+    /// for nested structures that have no start or end key.
+    UnknownKey,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -24,6 +27,9 @@ where
 {
     const HAS_CONFIG_CHILD_FIELDS: bool;
 
+    /// Returns true if `key` is the key of one of the child fields.
+    fn has_config_key(key: &str) -> bool;
+
     fn parse_config_line<R>(&mut self, reader: &mut ConfigReader<R>) -> ParseConfigResult
     where
         R: Reader;
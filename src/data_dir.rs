@@ -24,6 +24,16 @@ pub struct DataDir {
     root: PathBuf,
 }
 
+/// The result of [DataDir::walk].
+#[derive(Debug, Default)]
+pub struct DataDirWalk {
+    /// The paths of the files that were found.
+    pub files: Vec<String>,
+    /// The directories, entries and .gut archives that could not be read, with their path and
+    /// the error. They are skipped.
+    pub errors: Vec<(String, DataDirError)>,
+}
+
 #[derive(Debug)]
 pub enum File {
     Standalone {
@@ -146,9 +156,7 @@ impl DataDir {
             let path_as_str = path_as_str.to_string_lossy();
 
             // See if the entry is inside the .gut file.
-            let entry = gut
-                .entries()
-                .find(|entry| entry.name.eq_ignore_ascii_case(&path_as_str));
+            let entry = gut.entry(&path_as_str);
 
             if let Some(entry) = entry {
                 let mut gut_file = std::fs::File::open(gut_file_path)?;
@@ -169,6 +177,71 @@ impl DataDir {
         )))
     }
 
+    /// List the paths of all the files in the data directory that can be passed to
+    /// [DataDir::open], including the entries of .gut archives. The paths use data dir separators
+    /// and are sorted by directory. Anything below the root that can not be read is skipped and
+    /// returned in [DataDirWalk::errors], only an unreadable root is an error.
+    pub fn walk(&self) -> Result<DataDirWalk, DataDirError> {
+        let mut walk = DataDirWalk::default();
+
+        let relative = |path: &Path| {
+            path.strip_prefix(&self.root)
+                .unwrap_or(path)
+                .with_data_dir_separators()
+                .to_string_lossy()
+                .to_string()
+        };
+
+        let mut pending = vec![self.root.clone()];
+        while let Some(dir) = pending.pop() {
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(err) if dir == self.root => return Err(err.into()),
+                Err(err) => {
+                    walk.errors.push((relative(&dir), err.into()));
+                    continue;
+                }
+            };
+
+            let mut paths = vec![];
+            for entry in entries {
+                match entry {
+                    Ok(entry) => paths.push(entry.path()),
+                    Err(err) => walk.errors.push((relative(&dir), err.into())),
+                }
+            }
+            paths.sort();
+
+            for path in paths {
+                if path.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+
+                let relative = relative(&path);
+                let is_gut = relative
+                    .rsplit_once('.')
+                    .map(|(_, ext)| ext.eq_ignore_ascii_case("gut"))
+                    .unwrap_or(false);
+                if is_gut {
+                    let gut_file = std::fs::File::open(&path)
+                        .map_err(DataDirError::from)
+                        .and_then(|mut file| Ok(GutFile::open(&mut file)?));
+                    match gut_file {
+                        Ok(gut_file) => walk
+                            .files
+                            .extend(gut_file.entries().map(|entry| entry.name.to_string())),
+                        Err(err) => walk.errors.push((relative, err)),
+                    }
+                } else {
+                    walk.files.push(relative);
+                }
+            }
+        }
+
+        Ok(walk)
+    }
+
     fn find_gut_file_path_for(&self, path: impl AsRef<Path>) -> Option<PathBuf> {
         // Use OS separators for the path, because we'll be checking the filesystem with it.
         let path = path.as_ref().with_os_separators();
@@ -0,0 +1,218 @@
+//! Strings stored in fixed size, NUL terminated fields.
+//!
+//! The game was built for Windows with the Windows-1252 code page, so names are decoded with that
+//! code page for display. The raw bytes of the field, including anything after the NUL
+//! terminator, are kept so that the field can be written back without changes.
+
+/// Unicode code points for the Windows-1252 bytes in the range `0x80..=0x9F`. The bytes that are
+/// not defined by the code page map to the matching C1 control character, so that every byte
+/// survives a decode/encode round trip.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// Decode a single Windows-1252 byte.
+pub fn decode_windows_1252_char(byte: u8) -> char {
+    match byte {
+        0x80..=0x9F => WINDOWS_1252_HIGH[(byte - 0x80) as usize],
+        _ => byte as char,
+    }
+}
+
+/// Encode a single character as Windows-1252. Returns `None` if the character is not part of the
+/// code page.
+pub fn encode_windows_1252_char(ch: char) -> Option<u8> {
+    match ch as u32 {
+        0x00..=0x7F | 0xA0..=0xFF => Some(ch as u8),
+        _ => WINDOWS_1252_HIGH
+            .iter()
+            .position(|c| *c == ch)
+            .map(|index| 0x80 + index as u8),
+    }
+}
+
+/// Decode Windows-1252 bytes into a [String].
+pub fn decode_windows_1252(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .copied()
+        .map(decode_windows_1252_char)
+        .collect()
+}
+
+/// Encode a string as Windows-1252 bytes. Characters that are not part of the code page are
+/// replaced with `?`.
+pub fn encode_windows_1252(s: &str) -> Vec<u8> {
+    s.chars()
+        .map(|ch| encode_windows_1252_char(ch).unwrap_or(b'?'))
+        .collect()
+}
+
+/// A string read from a fixed size field.
+///
+/// Equality, ordering and hashing only consider the decoded text, not the padding.
+#[derive(Clone, Default)]
+pub struct FixedString {
+    /// All the bytes of the field, including the NUL terminator and padding.
+    bytes: Vec<u8>,
+    /// The text up to the first NUL, decoded as Windows-1252.
+    text: String,
+}
+
+impl FixedString {
+    /// Create a string from the raw bytes of a field.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        let text = decode_windows_1252(&bytes[..end]);
+        Self { bytes, text }
+    }
+
+    /// The decoded text.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// The raw bytes of the field, including the NUL terminator and padding if the string was read
+    /// from a file.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the bytes to store in a field of `len` bytes. The original bytes are returned as is
+    /// if they fit, otherwise the text is truncated to leave room for the NUL terminator. Short
+    /// strings are padded with zeroes.
+    pub fn to_field_bytes(&self, len: usize) -> Vec<u8> {
+        let mut bytes = self.bytes.clone();
+        if bytes.len() > len {
+            bytes.truncate(len);
+            if let Some(last) = bytes.last_mut() {
+                *last = 0;
+            }
+        }
+        bytes.resize(len, 0);
+        bytes
+    }
+}
+
+impl From<&str> for FixedString {
+    fn from(value: &str) -> Self {
+        let mut bytes = encode_windows_1252(value);
+        bytes.push(0);
+        Self::from_bytes(bytes)
+    }
+}
+
+impl From<String> for FixedString {
+    fn from(value: String) -> Self {
+        Self::from(value.as_str())
+    }
+}
+
+impl From<FixedString> for String {
+    fn from(value: FixedString) -> Self {
+        value.text
+    }
+}
+
+impl std::ops::Deref for FixedString {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.text
+    }
+}
+
+impl AsRef<str> for FixedString {
+    fn as_ref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl std::borrow::Borrow<str> for FixedString {
+    fn borrow(&self) -> &str {
+        &self.text
+    }
+}
+
+impl std::fmt::Debug for FixedString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.text, f)
+    }
+}
+
+impl std::fmt::Display for FixedString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.text, f)
+    }
+}
+
+impl PartialEq for FixedString {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl Eq for FixedString {}
+
+impl PartialEq<str> for FixedString {
+    fn eq(&self, other: &str) -> bool {
+        self.text == other
+    }
+}
+
+impl PartialEq<&str> for FixedString {
+    fn eq(&self, other: &&str) -> bool {
+        self.text == *other
+    }
+}
+
+impl PartialEq<String> for FixedString {
+    fn eq(&self, other: &String) -> bool {
+        &self.text == other
+    }
+}
+
+impl PartialOrd for FixedString {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FixedString {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.text.cmp(&other.text)
+    }
+}
+
+impl std::hash::Hash for FixedString {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.text.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_padding() {
+        let bytes = b"abc\0garbage\0\0\0\0\0".to_vec();
+        let s = FixedString::from_bytes(bytes.clone());
+        assert_eq!(s, "abc");
+        assert_eq!(s.to_field_bytes(bytes.len()), bytes);
+    }
+
+    #[test]
+    fn windows_1252_round_trip() {
+        let bytes = (0..=255_u8).collect::<Vec<_>>();
+        let decoded = decode_windows_1252(&bytes);
+        assert_eq!(encode_windows_1252(&decoded), bytes);
+
+        let s = FixedString::from("caf\u{E9} \u{20AC}");
+        assert_eq!(s.as_bytes(), b"caf\xE9 \x80\0");
+        assert_eq!(s.to_field_bytes(4), b"caf\0");
+    }
+}
@@ -1,6 +1,10 @@
 use thiserror::Error;
 
-use crate::{common::hash, io::Reader};
+use crate::{
+    common::{hash, GameHashMap, GameName, GameStr},
+    fixed_string::FixedString,
+    io::Reader,
+};
 
 #[derive(Debug, Error)]
 pub enum GutError {
@@ -10,7 +14,7 @@ pub enum GutError {
 
 #[derive(Debug)]
 pub struct Entry {
-    pub name: String,
+    pub name: FixedString,
     pub offset: u64,
     pub size: u64,
     pub is_plain_text: bool,
@@ -23,6 +27,9 @@ pub struct GutFile {
     pub header_size: u64,
 
     entries: Vec<Entry>,
+
+    /// Index into `entries` by name, using the same semantics as the engine.
+    index: GameHashMap<GameName, usize>,
 }
 
 impl GutFile {
@@ -32,13 +39,23 @@ impl GutFile {
     pub fn open(reader: &mut impl Reader) -> Result<Self, GutError> {
         let header_size = reader.skip_sinister_header_2(Self::MAGIC, 0x4000)?;
         let entries = read_entries(reader)?;
+        let index = build_index(&entries);
 
         Ok(Self {
             header_size,
             entries,
+            index,
         })
     }
 
+    /// Find an entry by name. Names are matched case insensitively, like the engine does. If
+    /// more than one entry has the name, the first one is returned.
+    pub fn entry(&self, name: &str) -> Option<&Entry> {
+        self.index
+            .get(GameStr::new(name))
+            .map(|index| &self.entries[*index])
+    }
+
     /// Get an iterator over the entries in the .gut file.
     pub fn entries(&self) -> EntryIter<'_> {
         EntryIter {
@@ -48,6 +65,15 @@ impl GutFile {
     }
 }
 
+/// Index the entries by name. The first entry with a name wins, like a linear search would.
+fn build_index(entries: &[Entry]) -> GameHashMap<GameName, usize> {
+    let mut index = GameHashMap::default();
+    for (i, entry) in entries.iter().enumerate() {
+        index.entry(GameName::new(entry.name.as_str())).or_insert(i);
+    }
+    index
+}
+
 fn read_entries(reader: &mut impl Reader) -> std::io::Result<Vec<Entry>> {
     // 4 - file count
     // 32 - filename
@@ -75,18 +101,14 @@ fn read_entries(reader: &mut impl Reader) -> std::io::Result<Vec<Entry>> {
         reader.read_exact(&mut encrypted_filename)?;
         crate::common::decrypt_buf(encrypted_filename.as_mut());
 
-        if filename_hash != hash(encrypted_filename.as_ref()) {
-            eprintln!(
-                "hashes do not match for file: {}",
-                String::from_utf8_lossy(&encrypted_filename)
-            );
+        let name = FixedString::from_bytes(encrypted_filename);
+
+        if filename_hash != hash(name.as_bytes()) {
+            eprintln!("hashes do not match for file: {}", name);
         }
 
         entries.push(Entry {
-            name: String::from_utf8_lossy(
-                encrypted_filename[0..(filename_length - 1) as usize].as_mut(),
-            )
-            .to_string(),
+            name,
             offset: file_offset as u64 + header_size,
             size: file_size as u64,
             is_plain_text: is_text,
@@ -113,3 +135,32 @@ impl<'entries> Iterator for EntryIter<'entries> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_names_return_the_first_entry() {
+        let entry = |name: &str, offset: u64| Entry {
+            name: FixedString::from(name),
+            offset,
+            size: 0,
+            is_plain_text: false,
+            hash: hash(name.as_bytes()),
+        };
+        let entries = vec![
+            entry("config\\a.txt", 1),
+            entry("CONFIG\\A.TXT", 2),
+            entry("config\\b.txt", 3),
+        ];
+        let gut_file = GutFile {
+            header_size: 0,
+            index: build_index(&entries),
+            entries,
+        };
+
+        assert_eq!(gut_file.entry("config\\A.txt").map(|e| e.offset), Some(1));
+        assert_eq!(gut_file.entry("config\\b.txt").map(|e| e.offset), Some(3));
+    }
+}
@@ -0,0 +1,269 @@
+//! A reverse lookup table for the name hashes used by the engine.
+//!
+//! [hash] can not be reversed, but most hashed names also show up as plain strings somewhere in the
+//! data files. The dictionary collects those strings and maps their hashes back to them.
+
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use crate::{
+    common::hash,
+    config::read_config_line,
+    data_dir::{DataDir, DataDirError},
+    fixed_string::encode_windows_1252,
+    gut::GutFile,
+    io::Reader,
+    map::Map,
+    smf,
+};
+
+#[derive(Debug, Default)]
+pub struct HashDictionary {
+    names: BTreeMap<u32, Vec<String>>,
+}
+
+impl HashDictionary {
+    /// Add a name to the dictionary and return its hash. Names that only differ in case hash to
+    /// the same value and are only stored once. The name is hashed as Windows-1252, like the
+    /// engine sees it.
+    pub fn insert(&mut self, name: &str) -> u32 {
+        let hash = hash(&encode_windows_1252(name));
+        if name.is_empty() {
+            return hash;
+        }
+
+        let names = self.names.entry(hash).or_default();
+        if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            names.push(name.to_string());
+        }
+
+        hash
+    }
+
+    /// Add a name along with its file name and file stem, if the name looks like a path.
+    pub fn insert_path(&mut self, path: &str) {
+        self.insert(path);
+
+        let file_name = path.rsplit(['\\', '/']).next().unwrap_or(path);
+        self.insert(file_name);
+
+        if let Some((stem, _)) = file_name.rsplit_once('.') {
+            self.insert(stem);
+        }
+    }
+
+    /// Returns the first known name for the hash.
+    pub fn lookup(&self, hash: u32) -> Option<&str> {
+        self.names
+            .get(&hash)
+            .and_then(|names| names.first())
+            .map(String::as_str)
+    }
+
+    /// Returns all the known names for the hash. More than one name means there was a collision.
+    pub fn names(&self, hash: u32) -> &[String] {
+        self.names.get(&hash).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Iterate over all the hashes and their names, ordered by hash.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.names
+            .iter()
+            .flat_map(|(hash, names)| names.iter().map(|name| (*hash, name.as_str())))
+    }
+
+    /// Add the names of all the entries in a .gut file.
+    pub fn add_gut_file(&mut self, gut_file: &GutFile) {
+        for entry in gut_file.entries() {
+            self.insert_path(&entry.name);
+        }
+    }
+
+    /// Add the names of all the nodes, meshes and textures in a model.
+    pub fn add_model(&mut self, model: &smf::Model) {
+        self.insert(&model.name);
+        for node in model.nodes.iter() {
+            self.insert(&node.name);
+            for mesh in node.meshes.iter() {
+                self.insert(&mesh.name);
+                self.insert_path(&mesh.texture_name);
+            }
+        }
+    }
+
+    /// Add the group, model and title of all the objects in a map.
+    pub fn add_map(&mut self, map: &Map) {
+        for object in map.objects.iter() {
+            self.insert(&object.group_name);
+            self.insert_path(&object.model_name);
+            self.insert(&object.title);
+        }
+    }
+
+    /// Add every string parameter in a config file.
+    pub fn add_config(&mut self, reader: &mut impl Reader) -> std::io::Result<()> {
+        while let Some(line) = read_config_line(reader)? {
+            for param in line.params.iter() {
+                // Numbers are not names.
+                if param.parse::<f32>().is_ok() {
+                    continue;
+                }
+                self.insert_path(param);
+            }
+        }
+        Ok(())
+    }
+
+    /// Walk a data directory and collect names from all the files in it, including the files
+    /// stored inside .gut archives. Files that can not be read are skipped with a warning, only
+    /// an unreadable root is an error.
+    pub fn add_data_dir(&mut self, root: impl AsRef<Path>) -> Result<(), DataDirError> {
+        let data_dir = DataDir::new(root);
+        let walk = data_dir.walk()?;
+
+        for (path, err) in walk.errors.iter() {
+            eprintln!("Could not read {}: {}", path, err);
+        }
+
+        for path in walk.files.iter() {
+            self.insert_path(path);
+            if let Err(err) = self.add_file(&data_dir, path) {
+                eprintln!("Could not read file {}: {}", path, err);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_file(&mut self, data_dir: &DataDir, path: &str) -> Result<(), DataDirError> {
+        if has_extension(path, "txt") || has_extension(path, "mtf") {
+            let mut file = data_dir.open(path)?;
+            if let Err(err) = self.add_config(&mut file) {
+                eprintln!("Could not read config file {}: {}", path, err);
+            }
+        } else if has_extension(path, "smf") {
+            let mut file = data_dir.open(path)?;
+            match smf::Model::read(&mut file) {
+                Ok(model) => self.add_model(&model),
+                Err(err) => eprintln!("Could not read model {}: {}", path, err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Load a dictionary saved with [HashDictionary::save], or collect one from a data directory if
+    /// the path is a directory.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, DataDirError> {
+        if path.as_ref().is_dir() {
+            let mut dictionary = Self::default();
+            dictionary.add_data_dir(path)?;
+            Ok(dictionary)
+        } else {
+            let file = std::fs::File::open(path)?;
+            Ok(Self::load(std::io::BufReader::new(file))?)
+        }
+    }
+
+    /// Read a dictionary from a text file with one name per line. A line may start with the hash
+    /// of the name, as written by [HashDictionary::save]. It is ignored, because the hash is
+    /// recalculated from the name. Empty lines and lines starting with `;` are skipped.
+    pub fn load(reader: impl BufRead) -> std::io::Result<Self> {
+        let mut dictionary = Self::default();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.trim().is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            let name = match line.split_once(char::is_whitespace) {
+                Some((hash, name)) if hash.len() == 8 && u32::from_str_radix(hash, 16).is_ok() => {
+                    name.trim_start()
+                }
+                _ => line,
+            };
+            dictionary.insert(name);
+        }
+        Ok(dictionary)
+    }
+
+    /// Write the dictionary as text, one `<HASH>  <name>` pair per line, in the same format that
+    /// the `hash` tool prints.
+    pub fn save(&self, mut writer: impl Write) -> std::io::Result<()> {
+        for (hash, name) in self.iter() {
+            writeln!(writer, "{:08X}  {}", hash, name)?;
+        }
+        Ok(())
+    }
+
+    /// Format a hash for display, including its name if it is known.
+    pub fn describe(&self, hash: u32) -> String {
+        match self.names(hash) {
+            [] => format!("0x{:08X}", hash),
+            names => format!("0x{:08X} \"{}\"", hash, names.join("\" | \"")),
+        }
+    }
+}
+
+fn has_extension(path: &str, extension: &str) -> bool {
+    path.rsplit_once('.')
+        .map(|(_, ext)| ext.eq_ignore_ascii_case(extension))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let mut dictionary = HashDictionary::default();
+        let hash = dictionary.insert("Medical Kit");
+        dictionary.insert_path("textures\\oil_helipad.bmp");
+
+        let mut buf = Vec::new();
+        dictionary.save(&mut buf).unwrap();
+        let loaded = HashDictionary::load(buf.as_slice()).unwrap();
+
+        assert_eq!(loaded.lookup(hash), Some("Medical Kit"));
+        assert_eq!(
+            loaded.lookup(crate::common::hash(b"OIL_HELIPAD")),
+            Some("oil_helipad")
+        );
+        assert_eq!(loaded.len(), dictionary.len());
+    }
+
+    #[test]
+    fn hash_windows_1252() {
+        let mut dictionary = HashDictionary::default();
+        assert_eq!(dictionary.insert("Caf\u{E9}"), hash(b"caf\xE9"));
+        assert_eq!(dictionary.insert("\u{20AC}"), hash(b"\x80"));
+    }
+
+    #[test]
+    fn skip_unreadable_files() {
+        let root = std::env::temp_dir().join(format!("hash_dictionary_{}", std::process::id()));
+        std::fs::create_dir_all(root.join("config")).unwrap();
+        std::fs::write(root.join("broken.gut"), b"not an archive").unwrap();
+        std::fs::write(root.join("config").join("names.txt"), b"NAME Medical_Kit\n").unwrap();
+
+        let mut dictionary = HashDictionary::default();
+        let result = dictionary.add_data_dir(&root);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(dictionary.lookup(hash(b"medical_kit")), Some("Medical_Kit"));
+    }
+}
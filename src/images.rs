@@ -5,9 +5,12 @@
 //! .bmp files are for RGB images.
 //! .pcx files are used for data, such as height maps, a-star map data, etc.
 
-use image::{GrayImage, ImageDecoder, ImageResult, RgbaImage};
+use image::{
+    error::{DecodingError, ImageFormatHint},
+    GrayImage, ImageDecoder, ImageError, ImageResult, RgbImage, RgbaImage,
+};
 
-use crate::io::Reader;
+use crate::{data_dir::DataDir, io::Reader};
 
 /// Load a .raw file from the reader and returns it as a single channel grayscale image.
 pub fn load_raw_file<R>(reader: &mut R, width: u32, height: u32) -> ImageResult<GrayImage>
@@ -24,9 +27,40 @@ where
         .expect("Not enought bytes from reader. Are the width or height invalid?"))
 }
 
+/// An inclusive range of colors that are treated as transparent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColorKeyRange {
+    pub low: [u8; 3],
+    pub high: [u8; 3],
+}
+
+impl ColorKeyRange {
+    /// Only pure black is transparent. This is the default color key used by the engine.
+    pub const BLACK: Self = Self {
+        low: [0, 0, 0],
+        high: [0, 0, 0],
+    };
+
+    pub fn contains(&self, rgb: [u8; 3]) -> bool {
+        (0..3).all(|i| rgb[i] >= self.low[i] && rgb[i] <= self.high[i])
+    }
+}
+
 /// Load a .bmp file from the reader and returns it as a RGB image.  If `color_keyd` is specified,
 /// then all black pixels are turned to alpha 0.
 pub fn load_bmp_file<R>(reader: &mut R, color_keyd: bool) -> ImageResult<RgbaImage>
+where
+    R: Reader,
+{
+    load_bmp_file_with_color_key(reader, color_keyd.then_some(ColorKeyRange::BLACK))
+}
+
+/// Load a .bmp file from the reader and returns it as a RGBA image. Pixels inside the `color_key`
+/// range are turned to alpha 0.
+pub fn load_bmp_file_with_color_key<R>(
+    reader: &mut R,
+    color_key: Option<ColorKeyRange>,
+) -> ImageResult<RgbaImage>
 where
     R: Reader,
 {
@@ -38,19 +72,13 @@ where
     decoder.read_image(&mut buf)?;
 
     let mut rgba = Vec::with_capacity(buf.len() / 3 * 4);
-    if color_keyd {
-        for chunk in buf.chunks_exact(3) {
-            let alpha = if chunk[0] == 0 && chunk[1] == 0 && chunk[2] == 0 {
-                0
-            } else {
-                255
-            };
-            rgba.extend_from_slice(&[chunk[0], chunk[1], chunk[2], alpha]);
-        }
-    } else {
-        for chunk in buf.chunks_exact(3) {
-            rgba.extend_from_slice(&[chunk[0], chunk[1], chunk[2], 255]);
-        }
+    for chunk in buf.chunks_exact(3) {
+        let rgb = [chunk[0], chunk[1], chunk[2]];
+        let alpha = match color_key {
+            Some(color_key) if color_key.contains(rgb) => 0,
+            _ => 255,
+        };
+        rgba.extend_from_slice(&[rgb[0], rgb[1], rgb[2], alpha]);
     }
 
     Ok(RgbaImage::from_raw(width, height, rgba).expect("Failed to create RGBA image"))
@@ -69,3 +97,702 @@ pub fn combine_bmp_and_raw(bmp: &RgbaImage, raw: &GrayImage) -> RgbaImage {
 
     rgba
 }
+
+/// Split an RGBA image into an RGB image (for a .bmp file) and a grayscale image holding the alpha
+/// channel (for a .raw file). This is the inverse of [combine_bmp_and_raw].
+pub fn split_rgba_to_bmp_and_raw(rgba: &RgbaImage) -> (RgbImage, GrayImage) {
+    let (width, height) = rgba.dimensions();
+    let rgb = RgbImage::from_fn(width, height, |x, y| {
+        let [r, g, b, _] = rgba.get_pixel(x, y).0;
+        image::Rgb([r, g, b])
+    });
+    let alpha = GrayImage::from_fn(width, height, |x, y| {
+        image::Luma([rgba.get_pixel(x, y).0[3]])
+    });
+
+    (rgb, alpha)
+}
+
+/// Returns true if every pixel in the image is either fully transparent or fully opaque, which
+/// means the alpha can be stored as a color key instead of a separate .raw file.
+pub fn has_binary_alpha(rgba: &RgbaImage) -> bool {
+    rgba.pixels().all(|p| p.0[3] == 0 || p.0[3] == 255)
+}
+
+/// Convert an RGBA image with binary alpha to an RGB image where transparent pixels are black, to
+/// be loaded with `load_bmp_file(color_keyd = true)`. Opaque pixels that are black are changed to
+/// the closest color that is not black, otherwise they would become transparent. Returns `None` if
+/// the image has partially transparent pixels.
+pub fn color_key_rgba(rgba: &RgbaImage) -> Option<RgbImage> {
+    if !has_binary_alpha(rgba) {
+        return None;
+    }
+
+    let (width, height) = rgba.dimensions();
+    Some(RgbImage::from_fn(width, height, |x, y| {
+        match rgba.get_pixel(x, y).0 {
+            [_, _, _, 0] => image::Rgb([0, 0, 0]),
+            [0, 0, 0, _] => image::Rgb([0, 0, 1]),
+            [r, g, b, _] => image::Rgb([r, g, b]),
+        }
+    }))
+}
+
+/// Write an RGB image as a 24-bit .bmp file.
+pub fn write_bmp_file<W>(writer: &mut W, rgb: &RgbImage) -> ImageResult<()>
+where
+    W: std::io::Write,
+{
+    use image::codecs::bmp::BmpEncoder;
+
+    let (width, height) = rgb.dimensions();
+    BmpEncoder::new(writer).encode(rgb.as_raw(), width, height, image::ExtendedColorType::Rgb8)
+}
+
+/// Write a grayscale image as a .raw file. The file has no header, only the pixels, one byte each,
+/// so the dimensions have to be known when loading it again.
+pub fn write_raw_file<W>(writer: &mut W, gray: &GrayImage) -> ImageResult<()>
+where
+    W: std::io::Write,
+{
+    writer.write_all(gray.as_raw())?;
+    Ok(())
+}
+
+/// Common screen and UI sizes, used when guessing the dimensions of a .raw file.
+pub const KNOWN_UI_SIZES: &[(u32, u32)] = &[
+    (320, 200),
+    (320, 240),
+    (512, 384),
+    (640, 400),
+    (640, 480),
+    (640, 512),
+    (800, 600),
+    (1024, 768),
+    (1280, 1024),
+];
+
+/// Where a guessed .raw size came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RawSizeSource {
+    /// The size was declared in a definition, like a texture size in image_defs.txt.
+    Definition,
+    /// Both dimensions are powers of two.
+    PowerOfTwo,
+    /// One of [KNOWN_UI_SIZES].
+    UiSize,
+    /// Any other factorization of the file size.
+    Factor,
+}
+
+#[derive(Clone, Debug)]
+pub struct RawSizeCandidate {
+    pub width: u32,
+    pub height: u32,
+    pub source: RawSizeSource,
+    /// How similar neighbouring rows are, from 0.0 to 1.0. The right width lines up the rows of
+    /// the image, so it usually has the highest score.
+    pub score: f32,
+}
+
+/// Guess the dimensions of a .raw file from its contents. `definitions` are sizes declared for the
+/// image elsewhere, they are only used if they match the size of the data and are always ranked
+/// first. The rest of the candidates are factorizations of the data size, ranked by score.
+pub fn detect_raw_size(data: &[u8], definitions: &[(u32, u32)]) -> Vec<RawSizeCandidate> {
+    let len = data.len() as u64;
+    if len == 0 {
+        return vec![];
+    }
+
+    let mut candidates: Vec<RawSizeCandidate> = vec![];
+    let mut push = |width: u32, height: u32, source: RawSizeSource| {
+        if candidates
+            .iter()
+            .any(|c| c.width == width && c.height == height)
+        {
+            return;
+        }
+        candidates.push(RawSizeCandidate {
+            width,
+            height,
+            source,
+            score: row_correlation(data, width, height),
+        });
+    };
+
+    for &(width, height) in definitions {
+        if width as u64 * height as u64 == len {
+            push(width, height, RawSizeSource::Definition);
+        }
+    }
+
+    let mut factors = vec![];
+    let mut width = 1_u64;
+    while width * width <= len {
+        if len.is_multiple_of(width) {
+            factors.push((width as u32, (len / width) as u32));
+            factors.push(((len / width) as u32, width as u32));
+        }
+        width += 1;
+    }
+
+    for (width, height) in factors {
+        let source = if KNOWN_UI_SIZES.contains(&(width, height)) {
+            RawSizeSource::UiSize
+        } else {
+            // Skip very narrow or very wide images. Neighbouring rows of those are only a few
+            // pixels apart in the real image, so they score well without being right.
+            let (short, long) = (width.min(height), width.max(height));
+            if short < 8 || long > short * 16 {
+                continue;
+            }
+
+            if width.is_power_of_two() && height.is_power_of_two() {
+                RawSizeSource::PowerOfTwo
+            } else {
+                RawSizeSource::Factor
+            }
+        };
+        push(width, height, source);
+    }
+
+    let definition_count = candidates
+        .iter()
+        .take_while(|c| c.source == RawSizeSource::Definition)
+        .count();
+    candidates[definition_count..].sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    candidates
+}
+
+/// Returns 1.0 minus the mean absolute difference between vertically neighbouring pixels.
+fn row_correlation(data: &[u8], width: u32, height: u32) -> f32 {
+    let width = width as usize;
+    let height = height as usize;
+    if height < 2 || width * height > data.len() {
+        return 0.0;
+    }
+
+    let total = data[..width * (height - 1)]
+        .iter()
+        .zip(data[width..width * height].iter())
+        .map(|(a, b)| a.abs_diff(*b) as u64)
+        .sum::<u64>();
+
+    1.0 - total as f32 / (width * (height - 1)) as f32 / 255.0
+}
+
+/// Naming patterns tried by [TiledImage::detect_pattern], most likely first. See
+/// [TiledImage::chunk_name] for the placeholders.
+pub const TILED_CHUNK_PATTERNS: &[&str] = &[
+    "{name}_{index}",
+    "{name}_{index:2}",
+    "{name}{index:2}",
+    "{name}{index}",
+    "{name}_{col}_{row}",
+    "{name}_{row}_{col}",
+];
+
+/// The layout of a large image that is stored as a grid of smaller chunk images, like the
+/// GEOMETRY_TILED backgrounds. Chunks on the right and bottom edges are stored at the full chunk
+/// size, with padding past the edge of the image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TiledImage {
+    /// The name of the image, without an extension.
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub chunk_width: u32,
+    pub chunk_height: u32,
+}
+
+impl TiledImage {
+    pub fn columns(&self) -> u32 {
+        self.width.div_ceil(self.chunk_width.max(1))
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.height.div_ceil(self.chunk_height.max(1))
+    }
+
+    /// The name of a chunk for a naming `pattern`. The pattern can contain `{name}`, `{col}`,
+    /// `{row}` and `{index}`, where the index counts the chunks row by row. A placeholder can be
+    /// zero padded with a width, like `{index:2}`. `.jpg` is added if the pattern has no extension.
+    pub fn chunk_name(&self, pattern: &str, column: u32, row: u32) -> String {
+        let index = row * self.columns() + column;
+
+        let mut result = String::new();
+        let mut rest = pattern;
+        while let Some(start) = rest.find('{') {
+            result.push_str(&rest[..start]);
+            let Some(end) = rest[start..].find('}') else {
+                break;
+            };
+            let placeholder = &rest[start + 1..start + end];
+            rest = &rest[start + end + 1..];
+
+            let (key, width) = match placeholder.split_once(':') {
+                Some((key, width)) => (key, width.parse::<usize>().unwrap_or(0)),
+                None => (placeholder, 0),
+            };
+            let value = match key {
+                "name" => {
+                    result.push_str(&self.name);
+                    continue;
+                }
+                "col" => column,
+                "row" => row,
+                "index" => index,
+                _ => {
+                    result.push_str(&format!("{{{}}}", placeholder));
+                    continue;
+                }
+            };
+            result.push_str(&format!("{:0width$}", value, width = width));
+        }
+        result.push_str(rest);
+
+        if !result.contains('.') {
+            result.push_str(".jpg");
+        }
+        result
+    }
+
+    /// Find the first pattern in [TILED_CHUNK_PATTERNS] for which `exists` returns true for every
+    /// chunk. Checking more than the first chunk tells apart patterns that name it the same, like
+    /// `{col}_{row}` and `{row}_{col}`. In a square grid those two name the same set of files, and
+    /// the first one is used.
+    pub fn detect_pattern(&self, exists: impl Fn(&str) -> bool) -> Option<&'static str> {
+        TILED_CHUNK_PATTERNS.iter().copied().find(|pattern| {
+            (0..self.rows()).all(|row| {
+                (0..self.columns()).all(|column| exists(&self.chunk_name(pattern, column, row)))
+            })
+        })
+    }
+
+    /// Stitch the chunks into one image. `load_chunk` is called with the name of each chunk.
+    /// Padding past the edges of the image is cropped.
+    pub fn stitch(
+        &self,
+        pattern: &str,
+        mut load_chunk: impl FnMut(&str) -> ImageResult<RgbaImage>,
+    ) -> ImageResult<RgbaImage> {
+        let mut image = RgbaImage::new(self.width, self.height);
+        for row in 0..self.rows() {
+            for column in 0..self.columns() {
+                let chunk = load_chunk(&self.chunk_name(pattern, column, row))?;
+                image::imageops::replace(
+                    &mut image,
+                    &chunk,
+                    (column * self.chunk_width) as i64,
+                    (row * self.chunk_height) as i64,
+                );
+            }
+        }
+        Ok(image)
+    }
+
+    /// Load the chunks from `dir` inside the data directory, which can be in a .gut archive, and
+    /// stitch them together. If no `pattern` is given it is detected with
+    /// [TiledImage::detect_pattern].
+    pub fn load(
+        &self,
+        data_dir: &DataDir,
+        dir: &str,
+        pattern: Option<&str>,
+    ) -> ImageResult<RgbaImage> {
+        let path = |name: &str| {
+            if dir.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}\\{}", dir.trim_end_matches(['\\', '/']), name)
+            }
+        };
+
+        let pattern = match pattern {
+            Some(pattern) => pattern,
+            None => self
+                .detect_pattern(|name| data_dir.open(path(name)).is_ok())
+                .ok_or_else(|| {
+                    ImageError::IoError(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("No chunks found for {} in {}", self.name, dir),
+                    ))
+                })?,
+        };
+
+        self.stitch(pattern, |name| {
+            let mut file = data_dir.open(path(name)).map_err(|err| {
+                ImageError::IoError(std::io::Error::new(std::io::ErrorKind::NotFound, err))
+            })?;
+            // Archived files can only be read in exact sizes.
+            let mut data = vec![0_u8; file.size()? as usize];
+            std::io::Read::read_exact(&mut file, &mut data)?;
+            Ok(image::load_from_memory(&data)?.to_rgba8())
+        })
+    }
+
+    /// Split an image into chunks, returning the name and image of each chunk. Edge chunks are
+    /// padded to the full chunk size by repeating the last row and column of the image.
+    pub fn split(&self, image: &RgbaImage, pattern: &str) -> Vec<(String, RgbaImage)> {
+        let (width, height) = image.dimensions();
+        let mut chunks = Vec::with_capacity((self.rows() * self.columns()) as usize);
+        for row in 0..self.rows() {
+            for column in 0..self.columns() {
+                let x0 = column * self.chunk_width;
+                let y0 = row * self.chunk_height;
+                let chunk = RgbaImage::from_fn(self.chunk_width, self.chunk_height, |x, y| {
+                    *image.get_pixel(
+                        (x0 + x).min(width.saturating_sub(1)),
+                        (y0 + y).min(height.saturating_sub(1)),
+                    )
+                });
+                chunks.push((self.chunk_name(pattern, column, row), chunk));
+            }
+        }
+        chunks
+    }
+}
+
+/// Write an image as a .jpg file. `quality` is from 1 to 100.
+pub fn write_jpg_file<W>(writer: &mut W, rgb: &RgbImage, quality: u8) -> ImageResult<()>
+where
+    W: std::io::Write,
+{
+    use image::codecs::jpeg::JpegEncoder;
+
+    let (width, height) = rgb.dimensions();
+    JpegEncoder::new_with_quality(writer, quality).encode(
+        rgb.as_raw(),
+        width,
+        height,
+        image::ExtendedColorType::Rgb8,
+    )
+}
+
+/// A decoded .pcx file. Paletted images keep their indices, because .pcx files are mostly used to
+/// store data like height maps, where the index is the value.
+#[derive(Clone, Debug)]
+pub enum PcxImage {
+    /// An 8-bit image with a 256 color palette.
+    Indexed {
+        indices: GrayImage,
+        palette: Box<[[u8; 3]; 256]>,
+    },
+    /// A 24-bit image.
+    Rgb(RgbImage),
+}
+
+impl PcxImage {
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            PcxImage::Indexed { indices, .. } => indices.dimensions(),
+            PcxImage::Rgb(rgb) => rgb.dimensions(),
+        }
+    }
+
+    /// Convert the image to RGBA, looking up the colors of indexed images in the palette.
+    pub fn to_rgba(&self) -> RgbaImage {
+        use image::buffer::ConvertBuffer;
+
+        match self {
+            PcxImage::Indexed { indices, palette } => {
+                let (width, height) = indices.dimensions();
+                RgbaImage::from_fn(width, height, |x, y| {
+                    let [r, g, b] = palette[indices.get_pixel(x, y).0[0] as usize];
+                    image::Rgba([r, g, b, 255])
+                })
+            }
+            PcxImage::Rgb(rgb) => rgb.convert(),
+        }
+    }
+}
+
+fn pcx_error(message: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name("PCX".to_string()),
+        message.to_string(),
+    ))
+}
+
+/// Load a .pcx file from the reader. Only 8-bit paletted and 24-bit RGB images are supported.
+pub fn load_pcx_file<R>(reader: &mut R) -> ImageResult<PcxImage>
+where
+    R: Reader,
+{
+    const HEADER_SIZE: usize = 128;
+
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+    if data.len() < HEADER_SIZE {
+        return Err(pcx_error("File is too small for a PCX header."));
+    }
+
+    let mut header = std::io::Cursor::new(&data[..HEADER_SIZE]);
+    let manufacturer = header.read_u8()?;
+    let _version = header.read_u8()?;
+    let encoding = header.read_u8()?;
+    let bits_per_pixel = header.read_u8()?;
+    let x_min = header.read_u16()?;
+    let y_min = header.read_u16()?;
+    let x_max = header.read_u16()?;
+    let y_max = header.read_u16()?;
+    header.set_position(65);
+    let planes = header.read_u8()?;
+    let bytes_per_line = header.read_u16()? as usize;
+
+    if manufacturer != 0x0A {
+        return Err(pcx_error("Invalid PCX manufacturer byte."));
+    }
+    if x_max < x_min || y_max < y_min {
+        return Err(pcx_error("Invalid PCX image dimensions."));
+    }
+    if bits_per_pixel != 8 || !(planes == 1 || planes == 3) {
+        return Err(pcx_error(
+            "Only 8-bit paletted and 24-bit PCX images are supported.",
+        ));
+    }
+
+    let width = (x_max - x_min) as usize + 1;
+    let height = (y_max - y_min) as usize + 1;
+    let planes = planes as usize;
+    if bytes_per_line < width {
+        return Err(pcx_error(
+            "PCX scan lines are shorter than the image width.",
+        ));
+    }
+
+    // Decode all the scan lines at once, some encoders let runs cross line boundaries.
+    let line_size = bytes_per_line * planes;
+    let mut lines = Vec::with_capacity(line_size * height);
+    let mut pos = HEADER_SIZE;
+    while lines.len() < line_size * height {
+        let Some(&byte) = data.get(pos) else {
+            return Err(pcx_error("Unexpected end of PCX image data."));
+        };
+        pos += 1;
+
+        if encoding == 1 && byte >= 0xC0 {
+            let Some(&value) = data.get(pos) else {
+                return Err(pcx_error("Unexpected end of PCX image data."));
+            };
+            pos += 1;
+            let count = (byte & 0x3F) as usize;
+            lines.extend(std::iter::repeat_n(value, count));
+        } else {
+            lines.push(byte);
+        }
+    }
+    lines.truncate(line_size * height);
+
+    if planes == 1 {
+        // The 256 color palette is stored at the end of the file, after a 0x0C marker.
+        let palette_start = data.len().saturating_sub(769);
+        if palette_start < pos || data[palette_start] != 0x0C {
+            return Err(pcx_error("PCX file does not have a 256 color palette."));
+        }
+
+        let mut palette = Box::new([[0_u8; 3]; 256]);
+        for (color, rgb) in palette
+            .iter_mut()
+            .zip(data[palette_start + 1..].chunks_exact(3))
+        {
+            color.copy_from_slice(rgb);
+        }
+
+        let mut indices = Vec::with_capacity(width * height);
+        for line in lines.chunks_exact(line_size) {
+            indices.extend_from_slice(&line[..width]);
+        }
+
+        Ok(PcxImage::Indexed {
+            indices: GrayImage::from_raw(width as u32, height as u32, indices)
+                .expect("Failed to create index image"),
+            palette,
+        })
+    } else {
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        for line in lines.chunks_exact(line_size) {
+            for x in 0..width {
+                rgb.push(line[x]);
+                rgb.push(line[bytes_per_line + x]);
+                rgb.push(line[bytes_per_line * 2 + x]);
+            }
+        }
+
+        Ok(PcxImage::Rgb(
+            RgbImage::from_raw(width as u32, height as u32, rgb)
+                .expect("Failed to create RGB image"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_and_combine_round_trip() {
+        let rgba = RgbaImage::from_fn(4, 3, |x, y| {
+            image::Rgba([
+                (x as u8).saturating_sub(1) * 60,
+                y as u8 * 80,
+                0,
+                if x == 0 { 0 } else { 255 },
+            ])
+        });
+
+        let (rgb, alpha) = split_rgba_to_bmp_and_raw(&rgba);
+        let mut bmp = vec![];
+        write_bmp_file(&mut bmp, &rgb).unwrap();
+        let mut raw = vec![];
+        write_raw_file(&mut raw, &alpha).unwrap();
+        assert_eq!(raw.len(), 12);
+
+        let bmp = load_bmp_file(&mut std::io::Cursor::new(&bmp), false).unwrap();
+        let raw = load_raw_file(&mut std::io::Cursor::new(&raw), 4, 3).unwrap();
+        assert_eq!(combine_bmp_and_raw(&bmp, &raw), rgba);
+
+        // Pixel (1, 0) is opaque black, so it has to survive the color key.
+        let keyed = color_key_rgba(&rgba).unwrap();
+        let mut bmp = vec![];
+        write_bmp_file(&mut bmp, &keyed).unwrap();
+        let loaded = load_bmp_file(&mut std::io::Cursor::new(&bmp), true).unwrap();
+        for (a, b) in loaded.pixels().zip(rgba.pixels()) {
+            assert_eq!(a.0[3], b.0[3]);
+        }
+    }
+
+    #[test]
+    fn detect_raw_size_from_rows() {
+        // Rows that change slowly, so the right width lines them up best.
+        let (width, height) = (64, 32);
+        let data = (0..width * height)
+            .map(|i| ((i % width) * 2 + (i / width) * 3) as u8)
+            .collect::<Vec<_>>();
+
+        assert_eq!(row_correlation(&data, 64, 32), 1.0 - 3.0 / 255.0);
+        assert!(row_correlation(&data, 32, 64) < row_correlation(&data, 64, 32));
+        assert_eq!(row_correlation(&data, 64, 64), 0.0);
+
+        let candidates = detect_raw_size(&data, &[]);
+        assert_eq!((candidates[0].width, candidates[0].height), (64, 32));
+        assert_eq!(candidates[0].source, RawSizeSource::PowerOfTwo);
+        assert!(candidates.iter().all(|c| c.width * c.height == 2048));
+        // Too narrow to be likely.
+        assert!(!candidates.iter().any(|c| c.width == 4));
+
+        // Definitions that match the data size come first, whatever their score.
+        let candidates = detect_raw_size(&data, &[(100, 100), (32, 64)]);
+        assert_eq!(
+            (
+                candidates[0].width,
+                candidates[0].height,
+                candidates[0].source
+            ),
+            (32, 64, RawSizeSource::Definition)
+        );
+        assert_eq!((candidates[1].width, candidates[1].height), (64, 32));
+
+        assert!(detect_raw_size(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn color_key_range() {
+        let rgb = RgbImage::from_fn(3, 1, |x, _| image::Rgb([x as u8 * 100, 0, 255]));
+        let mut bmp = vec![];
+        write_bmp_file(&mut bmp, &rgb).unwrap();
+
+        let color_key = ColorKeyRange {
+            low: [0, 0, 200],
+            high: [150, 10, 255],
+        };
+        let rgba =
+            load_bmp_file_with_color_key(&mut std::io::Cursor::new(&bmp), Some(color_key)).unwrap();
+        let alpha = rgba.pixels().map(|p| p.0[3]).collect::<Vec<_>>();
+        assert_eq!(alpha, [0, 0, 255]);
+    }
+
+    #[test]
+    fn split_and_stitch_tiled_image() {
+        let tiled = TiledImage {
+            name: "frame".to_string(),
+            width: 5,
+            height: 3,
+            chunk_width: 2,
+            chunk_height: 2,
+        };
+        assert_eq!((tiled.columns(), tiled.rows()), (3, 2));
+        assert_eq!(tiled.chunk_name("{name}_{index:2}", 1, 1), "frame_04.jpg");
+        assert_eq!(
+            tiled.chunk_name("{name}_{col}_{row}.bmp", 2, 0),
+            "frame_2_0.bmp"
+        );
+
+        let image = RgbaImage::from_fn(5, 3, |x, y| image::Rgba([x as u8, y as u8, 0, 255]));
+        let chunks = tiled.split(&image, "{name}_{index}");
+        assert_eq!(chunks.len(), 6);
+        assert!(chunks.iter().all(|(_, chunk)| chunk.dimensions() == (2, 2)));
+
+        let stitched = tiled
+            .stitch("{name}_{index}", |name| {
+                Ok(chunks.iter().find(|(n, _)| n == name).unwrap().1.clone())
+            })
+            .unwrap();
+        assert_eq!(stitched, image);
+    }
+
+    #[test]
+    fn detect_tiled_chunk_pattern() {
+        let tiled = TiledImage {
+            name: "frame".to_string(),
+            width: 6,
+            height: 4,
+            chunk_width: 2,
+            chunk_height: 2,
+        };
+
+        // Both patterns name the first chunk "frame_0_0.jpg".
+        for pattern in [
+            "{name}_{col}_{row}",
+            "{name}_{row}_{col}",
+            "{name}_{index:2}",
+        ] {
+            let names = tiled
+                .split(&RgbaImage::new(6, 4), pattern)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>();
+            let detected = tiled.detect_pattern(|name| names.iter().any(|n| n == name));
+            assert_eq!(detected, Some(pattern));
+        }
+
+        assert_eq!(tiled.detect_pattern(|name| name == "frame_0.jpg"), None);
+    }
+
+    #[test]
+    fn load_rle_pcx() {
+        let mut data = vec![0_u8; 128];
+        data[0] = 0x0A; // manufacturer
+        data[1] = 5; // version
+        data[2] = 1; // RLE encoding
+        data[3] = 8; // bits per pixel
+        data[8] = 2; // x_max, so the image is 3 pixels wide
+        data[10] = 1; // y_max, so the image is 2 pixels high
+        data[65] = 1; // planes
+        data[66] = 4; // bytes per line, padded to an even number
+
+        // Index 0xC5 has to be stored as a run, because it looks like a run marker.
+        data.extend_from_slice(&[0xC2, 7, 0xC1, 0xC5, 0, 1, 2, 3, 0]);
+        data.push(0x0C);
+        data.extend((0..256).flat_map(|i| [i as u8, 0, 255 - i as u8]));
+
+        let pcx = load_pcx_file(&mut std::io::Cursor::new(data)).unwrap();
+        let PcxImage::Indexed { indices, palette } = pcx else {
+            panic!("expected an indexed image");
+        };
+        assert_eq!(indices.dimensions(), (3, 2));
+        assert_eq!(indices.as_raw(), &[7, 7, 0xC5, 1, 2, 3]);
+        assert_eq!(palette[0xC5], [0xC5, 0, 0x3A]);
+    }
+}
@@ -2,6 +2,8 @@ use std::path::{Path, PathBuf};
 
 use glam::{Vec2, Vec3};
 
+use crate::fixed_string::FixedString;
+
 fn change_separator(path: impl AsRef<Path>, separator: char) -> PathBuf {
     PathBuf::from(
         path.as_ref()
@@ -33,6 +35,11 @@ pub trait Reader: std::io::Read + std::io::Seek + Sized {
         byteorder::ReadBytesExt::read_u8(self)
     }
 
+    #[inline]
+    fn read_u16(&mut self) -> std::io::Result<u16> {
+        byteorder::ReadBytesExt::read_u16::<byteorder::LittleEndian>(self)
+    }
+
     #[inline]
     fn read_u32(&mut self) -> std::io::Result<u32> {
         byteorder::ReadBytesExt::read_u32::<byteorder::LittleEndian>(self)
@@ -56,22 +63,12 @@ pub trait Reader: std::io::Read + std::io::Seek + Sized {
         Ok(Vec3::new(x, y, z))
     }
 
-    fn read_fixed_string(&mut self, len: usize) -> std::io::Result<String> {
-        let mut result = String::with_capacity(len);
-        let mut len = len - 1;
-        loop {
-            let ch = self.read_u8()?;
-            if ch == 0 || len == 0 {
-                break;
-            }
-            result.push(ch as char);
-            len -= 1;
-        }
-        while len != 0 {
-            self.read_u8()?;
-            len -= 1;
-        }
-        Ok(result)
+    /// Read a NUL terminated string from a field of `len` bytes. All the bytes of the field are
+    /// kept, so the string can be written back unchanged.
+    fn read_fixed_string(&mut self, len: usize) -> std::io::Result<FixedString> {
+        let mut bytes = vec![0_u8; len];
+        self.read_exact(&mut bytes)?;
+        Ok(FixedString::from_bytes(bytes))
     }
 
     fn skip_sinister_header(&mut self) -> std::io::Result<()> {
@@ -134,3 +131,43 @@ pub trait Reader: std::io::Read + std::io::Seek + Sized {
 }
 
 impl<R: std::io::Read + std::io::Seek + Sized> Reader for R {}
+
+pub trait Writer: std::io::Write + Sized {
+    #[inline]
+    fn write_u8(&mut self, value: u8) -> std::io::Result<()> {
+        byteorder::WriteBytesExt::write_u8(self, value)
+    }
+
+    #[inline]
+    fn write_u16(&mut self, value: u16) -> std::io::Result<()> {
+        byteorder::WriteBytesExt::write_u16::<byteorder::LittleEndian>(self, value)
+    }
+
+    #[inline]
+    fn write_u32(&mut self, value: u32) -> std::io::Result<()> {
+        byteorder::WriteBytesExt::write_u32::<byteorder::LittleEndian>(self, value)
+    }
+
+    #[inline]
+    fn write_f32(&mut self, value: f32) -> std::io::Result<()> {
+        byteorder::WriteBytesExt::write_f32::<byteorder::LittleEndian>(self, value)
+    }
+
+    fn write_vec2(&mut self, value: Vec2) -> std::io::Result<()> {
+        self.write_f32(value.x)?;
+        self.write_f32(value.y)
+    }
+
+    fn write_vec3(&mut self, value: Vec3) -> std::io::Result<()> {
+        self.write_f32(value.x)?;
+        self.write_f32(value.y)?;
+        self.write_f32(value.z)
+    }
+
+    /// Write a string to a field of `len` bytes. See [FixedString::to_field_bytes].
+    fn write_fixed_string(&mut self, value: &FixedString, len: usize) -> std::io::Result<()> {
+        self.write_all(&value.to_field_bytes(len))
+    }
+}
+
+impl<W: std::io::Write + Sized> Writer for W {}
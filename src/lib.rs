@@ -1,11 +1,18 @@
+pub mod ase;
 pub mod bmf;
 pub mod common;
 pub mod config;
 pub mod data_dir;
+pub mod fixed_string;
 pub mod gut;
+pub mod hash_dictionary;
 pub mod images;
 pub mod io;
+pub mod lint;
 pub mod map;
+pub mod mesh_processing;
+pub mod render;
 pub mod smf;
+pub mod texture_resolver;
 
 pub use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
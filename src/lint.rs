@@ -0,0 +1,590 @@
+//! Checks for structural problems in models. Mods often ship broken models that crash the game,
+//! [lint] finds the problems before they do.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{mesh_processing, smf, texture_resolver::TextureResolver};
+
+/// How far the length of a normal can be from 1.0.
+const NORMAL_TOLERANCE: f32 = 0.01;
+/// How far the length of a rotation can be from 1.0.
+const ROTATION_TOLERANCE: f32 = 0.001;
+/// How far texture coordinates can be outside the texture, before they are reported.
+const TEX_COORD_TOLERANCE: f32 = 0.001;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Unusual, but probably on purpose.
+    Info,
+    /// Rendered incorrectly, or wasteful.
+    Warning,
+    /// Likely to crash the game.
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The name of the node, if the problem is in a node.
+    pub node: Option<String>,
+    /// The name of the mesh, if the problem is in a mesh.
+    pub mesh: Option<String>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.severity.as_str())?;
+        if let Some(ref node) = self.node {
+            write!(f, "Node({}) ", node)?;
+        }
+        if let Some(ref mesh) = self.mesh {
+            write!(f, "Mesh({}) ", mesh)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+struct Diagnostics<'a> {
+    node: Option<&'a smf::Node>,
+    mesh: Option<&'a smf::Mesh>,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl Diagnostics<'_> {
+    fn add(&mut self, severity: Severity, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            node: self.node.map(|node| node.name.to_string()),
+            mesh: self.mesh.map(|mesh| mesh.name.to_string()),
+            message: message.into(),
+        });
+    }
+
+    /// Add a diagnostic for the items with a problem, if there are any. Only the first item is
+    /// named, so a broken mesh doesn't add thousands of diagnostics.
+    fn add_count(&mut self, severity: Severity, items: &[u32], total: usize, message: &str) {
+        if let Some(first) = items.first() {
+            self.add(
+                severity,
+                format!(
+                    "{} of {} {}, the first is {}",
+                    items.len(),
+                    total,
+                    message,
+                    first
+                ),
+            );
+        }
+    }
+}
+
+/// Check a model for problems, ordered by node and mesh. The texture names are only checked if
+/// there is a resolver.
+pub fn lint(model: &smf::Model, textures: Option<&TextureResolver>) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    let mut model_diagnostics = Diagnostics {
+        node: None,
+        mesh: None,
+        diagnostics: &mut diagnostics,
+    };
+
+    // Parents are found by name, so duplicate names break the hierarchy.
+    let mut names = HashMap::<&str, usize>::new();
+    for node in model.nodes.iter() {
+        *names.entry(node.name.as_str()).or_default() += 1;
+    }
+    let mut duplicates = names
+        .into_iter()
+        .filter(|&(_, count)| count > 1)
+        .collect::<Vec<_>>();
+    duplicates.sort();
+    for (name, count) in duplicates {
+        model_diagnostics.add(
+            Severity::Error,
+            format!("{} nodes are named {}", count, name),
+        );
+    }
+
+    if let Err(errors) = model.validate_hierarchy() {
+        for error in errors {
+            model_diagnostics.add(Severity::Error, error.to_string());
+        }
+    }
+
+    for node in model.nodes.iter() {
+        let mut node_diagnostics = Diagnostics {
+            node: Some(node),
+            mesh: None,
+            diagnostics: &mut diagnostics,
+        };
+
+        if !node.position.is_finite() {
+            node_diagnostics.add(
+                Severity::Error,
+                format!("position is not a number: {:?}", node.position),
+            );
+        }
+        if !node.rotation.is_finite() {
+            node_diagnostics.add(
+                Severity::Error,
+                format!("rotation is not a number: {:?}", node.rotation),
+            );
+        } else if (node.rotation.length() - 1.0).abs() > ROTATION_TOLERANCE {
+            node_diagnostics.add(
+                Severity::Warning,
+                format!(
+                    "rotation is not normalized, the length is {}",
+                    node.rotation.length()
+                ),
+            );
+        }
+
+        for mesh in node.meshes.iter() {
+            lint_mesh(
+                &mut Diagnostics {
+                    node: Some(node),
+                    mesh: Some(mesh),
+                    diagnostics: &mut diagnostics,
+                },
+                mesh,
+                textures,
+            );
+        }
+    }
+
+    diagnostics
+}
+
+fn lint_mesh(diagnostics: &mut Diagnostics, mesh: &smf::Mesh, textures: Option<&TextureResolver>) {
+    let texture_name = mesh.texture_name.as_str();
+    if let Some(textures) = textures {
+        if !texture_name.is_empty() && textures.find(texture_name).is_none() {
+            diagnostics.add(
+                Severity::Warning,
+                format!("texture {} could not be found", texture_name),
+            );
+        }
+    }
+
+    if mesh.faces.is_empty() {
+        diagnostics.add(Severity::Warning, "has no faces");
+    }
+
+    let vertex_count = mesh.vertices.len();
+    let mut out_of_range = vec![];
+    let mut degenerate = vec![];
+    let mut duplicate = vec![];
+    let mut back_face = vec![];
+    let mut wrong_face_index = vec![];
+    let mut used = vec![false; vertex_count];
+    let mut seen = HashSet::new();
+    for (index, face) in mesh.faces.iter().enumerate() {
+        if face.index != index as u32 {
+            wrong_face_index.push(index as u32);
+        }
+
+        if face.indices.iter().any(|&i| i as usize >= vertex_count) {
+            out_of_range.push(index as u32);
+            continue;
+        }
+        for i in face.indices {
+            used[i as usize] = true;
+        }
+
+        if mesh_processing::is_degenerate(&mesh.vertices, face) {
+            degenerate.push(index as u32);
+        }
+
+        // Start at the smallest corner, which keeps the winding. Faces with the same corners in
+        // the opposite winding are the back of another face, which is how double sided faces
+        // are made.
+        let mut corners = face.indices;
+        let smallest = (0..3).min_by_key(|&i| corners[i]).unwrap_or(0);
+        corners.rotate_left(smallest);
+        let [a, b, c] = corners;
+        if seen.contains(&corners) {
+            duplicate.push(index as u32);
+        } else if seen.contains(&[a, c, b]) {
+            back_face.push(index as u32);
+        }
+        seen.insert(corners);
+    }
+
+    let face_count = mesh.faces.len();
+    diagnostics.add_count(
+        Severity::Error,
+        &out_of_range,
+        face_count,
+        "faces use vertices out of range",
+    );
+    diagnostics.add_count(
+        Severity::Warning,
+        &degenerate,
+        face_count,
+        "faces are degenerate",
+    );
+    diagnostics.add_count(
+        Severity::Warning,
+        &duplicate,
+        face_count,
+        "faces are duplicates",
+    );
+    diagnostics.add_count(
+        Severity::Info,
+        &back_face,
+        face_count,
+        "faces are the back of another face",
+    );
+    diagnostics.add_count(
+        Severity::Warning,
+        &wrong_face_index,
+        face_count,
+        "faces have an index that doesn't match their position",
+    );
+
+    let mut unused = vec![];
+    let mut bad_position = vec![];
+    let mut bad_normal = vec![];
+    let mut non_unit_normal = vec![];
+    let mut bad_tex_coord = vec![];
+    let mut outside_tex_coord = vec![];
+    let mut wrong_vertex_index = vec![];
+    for (index, vertex) in mesh.vertices.iter().enumerate() {
+        let index_u32 = index as u32;
+        if vertex.index != index_u32 {
+            wrong_vertex_index.push(index_u32);
+        }
+        if !used[index] {
+            unused.push(index_u32);
+        }
+        if !vertex.position.is_finite() {
+            bad_position.push(index_u32);
+        }
+
+        if !vertex.normal.is_finite() {
+            bad_normal.push(index_u32);
+        } else if (vertex.normal.length() - 1.0).abs() > NORMAL_TOLERANCE {
+            non_unit_normal.push(index_u32);
+        }
+
+        if !vertex.tex_coord.is_finite() {
+            bad_tex_coord.push(index_u32);
+        } else if vertex.tex_coord.min_element() < -TEX_COORD_TOLERANCE
+            || vertex.tex_coord.max_element() > 1.0 + TEX_COORD_TOLERANCE
+        {
+            outside_tex_coord.push(index_u32);
+        }
+    }
+
+    diagnostics.add_count(
+        Severity::Error,
+        &bad_position,
+        vertex_count,
+        "vertices have a position that is not a number",
+    );
+    diagnostics.add_count(
+        Severity::Error,
+        &bad_normal,
+        vertex_count,
+        "vertices have a normal that is not a number",
+    );
+    diagnostics.add_count(
+        Severity::Error,
+        &bad_tex_coord,
+        vertex_count,
+        "vertices have texture coordinates that are not a number",
+    );
+    diagnostics.add_count(
+        Severity::Warning,
+        &non_unit_normal,
+        vertex_count,
+        "vertices have a normal that is not unit length",
+    );
+    diagnostics.add_count(
+        Severity::Warning,
+        &wrong_vertex_index,
+        vertex_count,
+        "vertices have an index that doesn't match their position",
+    );
+    diagnostics.add_count(
+        Severity::Info,
+        &unused,
+        vertex_count,
+        "vertices are not used by a face",
+    );
+    // Repeating textures use texture coordinates outside the texture on purpose.
+    diagnostics.add_count(
+        Severity::Info,
+        &outside_tex_coord,
+        vertex_count,
+        "vertices have texture coordinates outside the texture",
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixed_string::FixedString;
+    use glam::{Quat, Vec2, Vec3};
+
+    fn face(index: u32, indices: [u32; 3]) -> smf::Face {
+        smf::Face { index, indices }
+    }
+
+    /// The messages for a model with a single mesh, without the node and mesh names.
+    fn mesh_messages(
+        mesh: smf::Mesh,
+        textures: Option<&TextureResolver>,
+    ) -> Vec<(Severity, String)> {
+        lint(
+            &smf::Model::new(vec![smf::Node {
+                meshes: vec![mesh],
+                ..smf::Node::new("root", smf::ROOT_PARENT)
+            }]),
+            textures,
+        )
+        .into_iter()
+        .map(|d| (d.severity, d.message))
+        .collect()
+    }
+
+    /// A square of two faces, with every vertex used.
+    fn square() -> smf::Mesh {
+        smf::Mesh {
+            name: FixedString::from("square"),
+            texture_name: FixedString::from(""),
+            vertices: vec![
+                smf::Vertex::new(0, Vec3::ZERO, Vec3::Z),
+                smf::Vertex::new(1, Vec3::X, Vec3::Z),
+                smf::Vertex::new(2, Vec3::Y, Vec3::Z),
+                smf::Vertex::new(3, Vec3::new(1.0, 1.0, 0.0), Vec3::Z),
+            ],
+            faces: vec![face(0, [0, 1, 2]), face(1, [2, 1, 3])],
+        }
+    }
+
+    #[test]
+    fn lint_broken_model() {
+        let mesh = smf::Mesh {
+            name: FixedString::from("mesh"),
+            texture_name: FixedString::from(""),
+            vertices: vec![
+                smf::Vertex::new(0, Vec3::ZERO, Vec3::Z),
+                smf::Vertex::new(1, Vec3::X, Vec3::Z),
+                smf::Vertex::new(2, Vec3::Y, Vec3::Z * 2.0),
+                smf::Vertex::new(3, Vec3::ONE, Vec3::Z),
+            ],
+            faces: vec![face(0, [0, 1, 2]), face(1, [2, 0, 1]), face(2, [0, 1, 7])],
+        };
+
+        let mut root = smf::Node::new("root", smf::ROOT_PARENT);
+        root.rotation = Quat::from_xyzw(0.0, 0.0, 0.0, 2.0);
+        let model = smf::Model::new(vec![
+            root,
+            smf::Node {
+                meshes: vec![mesh],
+                ..smf::Node::new("child", "root")
+            },
+            smf::Node::new("child", "root"),
+        ]);
+
+        let diagnostics = lint(&model, None)
+            .into_iter()
+            .map(|d| (d.severity, d.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (Severity::Error, "error: 2 nodes are named child".to_string()),
+                (
+                    Severity::Warning,
+                    "warning: Node(root) rotation is not normalized, the length is 2".to_string()
+                ),
+                (
+                    Severity::Error,
+                    "error: Node(child) Mesh(mesh) 1 of 3 faces use vertices out of range, the first is 2"
+                        .to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "warning: Node(child) Mesh(mesh) 1 of 3 faces are duplicates, the first is 1"
+                        .to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "warning: Node(child) Mesh(mesh) 1 of 4 vertices have a normal that is not unit length, the first is 2"
+                        .to_string()
+                ),
+                (
+                    Severity::Info,
+                    "info: Node(child) Mesh(mesh) 1 of 4 vertices are not used by a face, the first is 3"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn clean_mesh() {
+        assert_eq!(mesh_messages(square(), None), vec![]);
+    }
+
+    #[test]
+    fn degenerate_and_back_faces() {
+        let mut mesh = square();
+        mesh.vertices
+            .push(smf::Vertex::new(4, Vec3::new(2.0, 0.0, 0.0), Vec3::Z));
+        mesh.faces = vec![
+            face(0, [0, 1, 2]),
+            // The back of the first face.
+            face(1, [0, 2, 1]),
+            face(2, [2, 1, 3]),
+            // Uses a vertex twice.
+            face(3, [3, 3, 1]),
+            // The corners are on a line.
+            face(4, [0, 1, 4]),
+        ];
+        assert_eq!(
+            mesh_messages(mesh, None),
+            vec![
+                (
+                    Severity::Warning,
+                    "2 of 5 faces are degenerate, the first is 3".to_string()
+                ),
+                (
+                    Severity::Info,
+                    "1 of 5 faces are the back of another face, the first is 1".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn values_that_are_not_numbers() {
+        let mut mesh = square();
+        mesh.vertices[1].position.x = f32::NAN;
+        mesh.vertices[2].normal = Vec3::splat(f32::INFINITY);
+        mesh.vertices[3].tex_coord.y = f32::NAN;
+        let mut messages = mesh_messages(mesh, None);
+        // The face with a NaN position has no area.
+        messages.retain(|(_, message)| !message.contains("degenerate"));
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    Severity::Error,
+                    "1 of 4 vertices have a position that is not a number, the first is 1"
+                        .to_string()
+                ),
+                (
+                    Severity::Error,
+                    "1 of 4 vertices have a normal that is not a number, the first is 2"
+                        .to_string()
+                ),
+                (
+                    Severity::Error,
+                    "1 of 4 vertices have texture coordinates that are not a number, the first is 3"
+                        .to_string()
+                ),
+            ]
+        );
+
+        let mut root = smf::Node::new("root", smf::ROOT_PARENT);
+        root.position = Vec3::new(0.0, f32::NAN, 0.0);
+        root.rotation = Quat::from_xyzw(0.0, 0.0, f32::NAN, 1.0);
+        let messages = lint(&smf::Model::new(vec![root]), None)
+            .into_iter()
+            .map(|d| d.severity)
+            .collect::<Vec<_>>();
+        assert_eq!(messages, vec![Severity::Error, Severity::Error]);
+    }
+
+    #[test]
+    fn tex_coords_outside_the_texture() {
+        let mut mesh = square();
+        // Within the tolerance.
+        mesh.vertices[0].tex_coord = Vec2::new(1.0005, -0.0005);
+        mesh.vertices[2].tex_coord = Vec2::new(0.5, -0.5);
+        mesh.vertices[3].tex_coord = Vec2::new(2.0, 0.5);
+        assert_eq!(
+            mesh_messages(mesh, None),
+            vec![(
+                Severity::Info,
+                "2 of 4 vertices have texture coordinates outside the texture, the first is 2"
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn index_mismatches() {
+        let mut mesh = square();
+        mesh.faces[1].index = 0;
+        mesh.vertices[2].index = 7;
+        mesh.vertices[3].index = 2;
+        assert_eq!(
+            mesh_messages(mesh, None),
+            vec![
+                (
+                    Severity::Warning,
+                    "1 of 2 faces have an index that doesn't match their position, the first is 1"
+                        .to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "2 of 4 vertices have an index that doesn't match their position, the first is 2"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn texture_resolution() {
+        let root = std::env::temp_dir().join(format!("lint_textures_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        crate::images::write_bmp_file(
+            &mut std::fs::File::create(root.join("Found.bmp")).unwrap(),
+            &image::RgbImage::new(1, 1),
+        )
+        .unwrap();
+        let mut resolver = TextureResolver::default();
+        resolver.add_root(&root).unwrap();
+
+        let textured = |texture_name: &str| smf::Mesh {
+            texture_name: FixedString::from(texture_name),
+            ..square()
+        };
+        // Case doesn't matter, and other image extensions are tried.
+        assert_eq!(
+            mesh_messages(textured("FOUND.BMP"), Some(&resolver)),
+            vec![]
+        );
+        assert_eq!(
+            mesh_messages(textured("found.jpg"), Some(&resolver)),
+            vec![]
+        );
+        assert_eq!(
+            mesh_messages(textured("missing.bmp"), Some(&resolver)),
+            vec![(
+                Severity::Warning,
+                "texture missing.bmp could not be found".to_string()
+            )]
+        );
+        // Without a resolver, textures are not checked.
+        assert_eq!(mesh_messages(textured("missing.bmp"), None), vec![]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
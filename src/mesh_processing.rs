@@ -0,0 +1,592 @@
+//! Passes that clean up the geometry of [Mesh]es: recomputing normals, welding vertices, removing
+//! degenerate faces and reordering the faces for the vertex cache. Converters can run them with
+//! [process].
+//!
+//! Every pass renumbers [Vertex::index] and [Face::index] to match their position.
+
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+use crate::smf::{Face, Mesh, Vertex};
+
+/// How far apart the attributes of two vertices can be to be welded into one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WeldTolerance {
+    pub position: f32,
+    pub tex_coord: f32,
+    /// The distance between the normals, which are unit vectors.
+    pub normal: f32,
+}
+
+impl Default for WeldTolerance {
+    fn default() -> Self {
+        Self {
+            position: 0.0001,
+            tex_coord: 0.0001,
+            normal: 0.001,
+        }
+    }
+}
+
+/// The passes to run with [process]. Everything is off by default.
+#[derive(Clone, Debug, Default)]
+pub struct ProcessOptions {
+    /// Recompute the normals, smoothing faces with an angle up to this many radians between them.
+    pub smoothing_angle: Option<f32>,
+    pub weld: Option<WeldTolerance>,
+    pub remove_degenerate_faces: bool,
+    pub optimize_vertex_cache: bool,
+}
+
+/// Command line arguments for the passes, shared by the tools that write models.
+#[cfg(feature = "clap")]
+#[derive(clap::Args, Clone, Debug, Default)]
+// Keep the doc comment out of the help of the tools this is flattened into.
+#[command(about = None, long_about = None)]
+pub struct ProcessArgs {
+    /// Recompute the normals, smoothing faces with at most this many degrees between them. 0 gives
+    /// flat normals.
+    #[arg(long, value_name = "DEGREES")]
+    pub smoothing_angle: Option<f32>,
+    /// Merge vertices with the same position, texture coordinates and normal.
+    #[arg(long)]
+    pub weld: bool,
+    /// Remove faces without an area.
+    #[arg(long)]
+    pub remove_degenerate_faces: bool,
+    /// Reorder the faces and vertices to make better use of the vertex cache.
+    #[arg(long)]
+    pub optimize_vertex_cache: bool,
+}
+
+#[cfg(feature = "clap")]
+impl ProcessArgs {
+    pub fn process_options(&self) -> ProcessOptions {
+        ProcessOptions {
+            smoothing_angle: self.smoothing_angle.map(f32::to_radians),
+            weld: self.weld.then(WeldTolerance::default),
+            remove_degenerate_faces: self.remove_degenerate_faces,
+            optimize_vertex_cache: self.optimize_vertex_cache,
+        }
+    }
+}
+
+/// Run the passes enabled in `options`, in an order where they don't undo each other.
+pub fn process(mesh: &mut Mesh, options: &ProcessOptions) {
+    if let Some(smoothing_angle) = options.smoothing_angle {
+        recompute_normals(mesh, smoothing_angle);
+    }
+    if let Some(ref tolerance) = options.weld {
+        weld_vertices(mesh, tolerance);
+    }
+    // Welding can collapse faces, so this runs after it.
+    if options.remove_degenerate_faces {
+        remove_degenerate_faces(mesh);
+        remove_unused_vertices(mesh);
+    }
+    if options.optimize_vertex_cache {
+        optimize_vertex_cache(mesh);
+    }
+}
+
+/// The area weighted normal of a face. SMF normals point away from the front of the faces, so it
+/// is the opposite of the cross product of the edges.
+fn face_normal(vertices: &[Vertex], face: &Face) -> Option<Vec3> {
+    let [p0, p1, p2] = [
+        vertices.get(face.indices[0] as usize)?.position,
+        vertices.get(face.indices[1] as usize)?.position,
+        vertices.get(face.indices[2] as usize)?.position,
+    ];
+    Some(-(p1 - p0).cross(p2 - p0))
+}
+
+/// Recompute the vertex normals from the faces. The normal of a corner is the area weighted average
+/// of the faces around its position that have an angle of at most `smoothing_angle` radians with
+/// the face of the corner, so 0.0 gives flat normals and PI smooths everything. Vertices are split
+/// where their corners end up with different normals. Vertices not used by a face are removed.
+/// Meshes with an index out of range are left as they are.
+pub fn recompute_normals(mesh: &mut Mesh, smoothing_angle: f32) {
+    if has_index_out_of_range(mesh) {
+        return;
+    }
+
+    let face_normals = mesh
+        .faces
+        .iter()
+        .map(|face| face_normal(&mesh.vertices, face).unwrap_or(Vec3::ZERO))
+        .collect::<Vec<_>>();
+
+    let mut faces_at_position = HashMap::<[u32; 3], Vec<usize>>::new();
+    for (face_index, face) in mesh.faces.iter().enumerate() {
+        for index in face.indices {
+            faces_at_position
+                .entry(
+                    mesh.vertices[index as usize]
+                        .position
+                        .to_array()
+                        .map(f32::to_bits),
+                )
+                .or_default()
+                .push(face_index);
+        }
+    }
+
+    // A small margin, so faces in the same plane are always smoothed.
+    let min_cos = smoothing_angle.cos() - 0.000001;
+
+    let mut vertices = vec![];
+    let mut split = HashMap::<(u32, [u32; 3]), u32>::new();
+    for (face_index, face) in mesh.faces.iter_mut().enumerate() {
+        let own = face_normals[face_index].normalize_or_zero();
+        for index in face.indices.iter_mut() {
+            let vertex = &mesh.vertices[*index as usize];
+            let key = vertex.position.to_array().map(f32::to_bits);
+            let mut normal = faces_at_position[&key]
+                .iter()
+                .map(|&other| face_normals[other])
+                .filter(|other| other.normalize_or_zero().dot(own) >= min_cos)
+                .sum::<Vec3>()
+                .normalize_or_zero();
+            if normal == Vec3::ZERO {
+                normal = own;
+            }
+
+            *index = *split
+                .entry((*index, normal.to_array().map(f32::to_bits)))
+                .or_insert_with(|| {
+                    vertices.push(Vertex {
+                        normal,
+                        ..vertex.clone()
+                    });
+                    vertices.len() as u32 - 1
+                });
+        }
+    }
+
+    mesh.vertices = vertices;
+    renumber(mesh);
+}
+
+/// Merge vertices with attributes within `tolerance` of each other and remap the faces to the
+/// merged vertices. Returns the number of vertices that were removed.
+pub fn weld_vertices(mesh: &mut Mesh, tolerance: &WeldTolerance) -> usize {
+    let cell_size = tolerance.position.max(0.000001);
+    let cell = |position: Vec3| (position / cell_size).floor().as_ivec3();
+
+    let mut grid = HashMap::<glam::IVec3, Vec<u32>>::new();
+    let mut vertices: Vec<Vertex> = vec![];
+    let mut remap = Vec::with_capacity(mesh.vertices.len());
+
+    for vertex in mesh.vertices.iter() {
+        let center = cell(vertex.position);
+        let mut found = None;
+        'search: for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let Some(candidates) = grid.get(&(center + glam::IVec3::new(x, y, z))) else {
+                        continue;
+                    };
+                    for &candidate in candidates {
+                        let other = &vertices[candidate as usize];
+                        if other.position.distance(vertex.position) <= tolerance.position
+                            && other.tex_coord.distance(vertex.tex_coord) <= tolerance.tex_coord
+                            && other.normal.distance(vertex.normal) <= tolerance.normal
+                        {
+                            found = Some(candidate);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+
+        let index = found.unwrap_or_else(|| {
+            vertices.push(vertex.clone());
+            let index = vertices.len() as u32 - 1;
+            grid.entry(center).or_default().push(index);
+            index
+        });
+        remap.push(index);
+    }
+
+    for face in mesh.faces.iter_mut() {
+        for index in face.indices.iter_mut() {
+            if let Some(&new_index) = remap.get(*index as usize) {
+                *index = new_index;
+            }
+        }
+    }
+
+    let removed = mesh.vertices.len() - vertices.len();
+    mesh.vertices = vertices;
+    renumber(mesh);
+    removed
+}
+
+/// Remove faces that don't cover an area: faces with an index out of range, faces that use a
+/// vertex more than once and faces with their corners on a line. Returns the number of faces that
+/// were removed.
+pub fn remove_degenerate_faces(mesh: &mut Mesh) -> usize {
+    let before = mesh.faces.len();
+    let vertices = &mesh.vertices;
+    mesh.faces.retain(|face| !is_degenerate(vertices, face));
+    renumber(mesh);
+    before - mesh.faces.len()
+}
+
+/// True if the face has an index out of range, uses a vertex more than once or has no area.
+pub fn is_degenerate(vertices: &[Vertex], face: &Face) -> bool {
+    let [i0, i1, i2] = face.indices;
+    if i0 == i1 || i1 == i2 || i2 == i0 {
+        return true;
+    }
+    let Some(normal) = face_normal(vertices, face) else {
+        return true;
+    };
+
+    // Relative to the size of the face, so small models are not affected.
+    let positions = face.indices.map(|i| vertices[i as usize].position);
+    let longest_edge = (positions[1] - positions[0])
+        .length_squared()
+        .max((positions[2] - positions[1]).length_squared())
+        .max((positions[0] - positions[2]).length_squared());
+    normal.length() <= longest_edge * 0.000001
+}
+
+/// Remove the vertices that are not used by any face. Returns the number of vertices that were
+/// removed.
+pub fn remove_unused_vertices(mesh: &mut Mesh) -> usize {
+    let mut used = vec![false; mesh.vertices.len()];
+    for face in mesh.faces.iter() {
+        for index in face.indices {
+            if let Some(used) = used.get_mut(index as usize) {
+                *used = true;
+            }
+        }
+    }
+
+    let mut remap = vec![0; mesh.vertices.len()];
+    let mut vertices = Vec::with_capacity(mesh.vertices.len());
+    for (index, vertex) in mesh.vertices.iter().enumerate() {
+        if used[index] {
+            remap[index] = vertices.len() as u32;
+            vertices.push(vertex.clone());
+        }
+    }
+
+    for face in mesh.faces.iter_mut() {
+        for index in face.indices.iter_mut() {
+            if let Some(&new_index) = remap.get(*index as usize) {
+                *index = new_index;
+            }
+        }
+    }
+
+    let removed = mesh.vertices.len() - vertices.len();
+    mesh.vertices = vertices;
+    renumber(mesh);
+    removed
+}
+
+/// Reorder the faces so vertices that were used recently are used again, using Tom Forsyth's
+/// "Linear-Speed Vertex Cache Optimisation". The vertices are then reordered in the order the
+/// faces first use them. Meshes with an index out of range are left as they are.
+pub fn optimize_vertex_cache(mesh: &mut Mesh) {
+    const CACHE_SIZE: usize = 32;
+
+    if has_index_out_of_range(mesh) {
+        return;
+    }
+    let vertex_count = mesh.vertices.len();
+
+    fn vertex_score(cache_position: Option<usize>, remaining_faces: usize) -> f32 {
+        if remaining_faces == 0 {
+            return -1.0;
+        }
+        let cache_score = match cache_position {
+            // The vertices of the last face are used, but a bit less, so the next face is not
+            // always a neighbour of the last one.
+            Some(position) if position < 3 => 0.75,
+            Some(position) => {
+                let scale = 1.0 / (CACHE_SIZE - 3) as f32;
+                (1.0 - (position - 3) as f32 * scale).powf(1.5)
+            }
+            None => 0.0,
+        };
+        // Prefer vertices with few faces left, so no lonely faces are left behind.
+        cache_score + 2.0 * (remaining_faces as f32).powf(-0.5)
+    }
+
+    let mut vertex_faces = vec![vec![]; vertex_count];
+    for (face_index, face) in mesh.faces.iter().enumerate() {
+        for index in face.indices {
+            vertex_faces[index as usize].push(face_index);
+        }
+    }
+
+    let mut cache_positions = vec![None; vertex_count];
+    let mut scores = vertex_faces
+        .iter()
+        .map(|faces| vertex_score(None, faces.len()))
+        .collect::<Vec<_>>();
+    let face_score =
+        |scores: &[f32], face: &Face| face.indices.map(|i| scores[i as usize]).iter().sum();
+
+    let mut added = vec![false; mesh.faces.len()];
+    let mut order = Vec::with_capacity(mesh.faces.len());
+    let mut cache: Vec<u32> = vec![];
+
+    let best_of_all = |scores: &[f32], added: &[bool]| {
+        (0..mesh.faces.len())
+            .filter(|&face| !added[face])
+            .map(|face| (face, face_score(scores, &mesh.faces[face])))
+            .max_by(|(_, a): &(usize, f32), (_, b)| a.total_cmp(b))
+            .map(|(face, _)| face)
+    };
+
+    let mut next = best_of_all(&scores, &added);
+    while let Some(face_index) = next {
+        added[face_index] = true;
+        order.push(face_index);
+
+        let indices = mesh.faces[face_index].indices;
+        for index in indices {
+            vertex_faces[index as usize].retain(|&face| face != face_index);
+        }
+
+        // Move the vertices of the face to the front of the cache.
+        let mut new_cache = indices.to_vec();
+        new_cache.extend(cache.iter().filter(|i| !indices.contains(i)));
+        let evicted = new_cache.split_off(new_cache.len().min(CACHE_SIZE));
+        for index in evicted.iter() {
+            cache_positions[*index as usize] = None;
+        }
+        for (position, index) in new_cache.iter().enumerate() {
+            cache_positions[*index as usize] = Some(position);
+        }
+        cache = new_cache;
+
+        for &index in cache.iter().chain(evicted.iter()) {
+            let index = index as usize;
+            scores[index] = vertex_score(cache_positions[index], vertex_faces[index].len());
+        }
+
+        // The best face is usually one that uses the vertices in the cache.
+        next = cache
+            .iter()
+            .flat_map(|&index| vertex_faces[index as usize].iter().copied())
+            .map(|face| (face, face_score(&scores, &mesh.faces[face])))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(face, _)| face)
+            .or_else(|| best_of_all(&scores, &added));
+    }
+
+    let faces = order
+        .into_iter()
+        .map(|face| mesh.faces[face].clone())
+        .collect::<Vec<_>>();
+    mesh.faces = faces;
+
+    // Vertices in the order they are first used, unused vertices at the end.
+    let mut remap = vec![None; vertex_count];
+    let mut vertex_order = Vec::with_capacity(vertex_count);
+    for face in mesh.faces.iter_mut() {
+        for index in face.indices.iter_mut() {
+            let new_index = *remap[*index as usize].get_or_insert_with(|| {
+                vertex_order.push(*index as usize);
+                vertex_order.len() as u32 - 1
+            });
+            *index = new_index;
+        }
+    }
+    vertex_order.extend((0..vertex_count).filter(|&i| remap[i].is_none()));
+    mesh.vertices = vertex_order
+        .into_iter()
+        .map(|index| mesh.vertices[index].clone())
+        .collect();
+
+    renumber(mesh);
+}
+
+fn has_index_out_of_range(mesh: &Mesh) -> bool {
+    mesh.faces.iter().any(|face| {
+        face.indices
+            .iter()
+            .any(|&i| i as usize >= mesh.vertices.len())
+    })
+}
+
+fn renumber(mesh: &mut Mesh) {
+    for (index, vertex) in mesh.vertices.iter_mut().enumerate() {
+        vertex.index = index as u32;
+    }
+    for (index, face) in mesh.faces.iter_mut().enumerate() {
+        face.index = index as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixed_string::FixedString;
+
+    /// Two faces folded along the x axis at 90 degrees, with the vertices on the fold duplicated.
+    fn folded_mesh() -> Mesh {
+        let vertex = |position: Vec3| Vertex::new(0, position, Vec3::ZERO);
+        let face = |indices: [u32; 3]| Face { index: 0, indices };
+
+        Mesh {
+            name: FixedString::from("folded"),
+            texture_name: FixedString::from("folded.bmp"),
+            vertices: vec![
+                vertex(Vec3::ZERO),
+                vertex(Vec3::X),
+                vertex(Vec3::Y),
+                vertex(Vec3::ZERO),
+                vertex(Vec3::X),
+                vertex(Vec3::Z),
+            ],
+            faces: vec![
+                face([0, 2, 1]),
+                face([3, 4, 5]),
+                // Degenerate.
+                face([0, 0, 1]),
+            ],
+        }
+    }
+
+    #[test]
+    fn process_passes() {
+        let mut mesh = folded_mesh();
+        assert_eq!(remove_degenerate_faces(&mut mesh), 1);
+
+        // Flat normals keep the vertices on the fold apart.
+        recompute_normals(&mut mesh, 0.0);
+        assert_eq!(mesh.vertices.len(), 6);
+        assert_eq!(mesh.vertices[0].normal, Vec3::Z);
+        assert_eq!(mesh.vertices[3].normal, Vec3::Y);
+        assert_eq!(weld_vertices(&mut mesh, &WeldTolerance::default()), 0);
+
+        // Smooth normals are the same on both sides of the fold, so they can be welded.
+        recompute_normals(&mut mesh, std::f32::consts::FRAC_PI_2);
+        let fold = (Vec3::Y + Vec3::Z).normalize();
+        assert!(mesh.vertices[0].normal.abs_diff_eq(fold, 0.0001));
+        assert_eq!(weld_vertices(&mut mesh, &WeldTolerance::default()), 2);
+        assert_eq!(mesh.faces[1].indices, [0, 2, 3]);
+
+        let before = mesh.clone();
+        optimize_vertex_cache(&mut mesh);
+        assert_eq!(mesh.faces.len(), before.faces.len());
+        assert!(mesh
+            .vertices
+            .iter()
+            .enumerate()
+            .all(|(index, vertex)| vertex.index == index as u32));
+    }
+
+    #[test]
+    fn recompute_normals_with_index_out_of_range() {
+        let mut mesh = folded_mesh();
+        mesh.faces[1].indices[2] = 6;
+        let before = mesh.clone();
+        recompute_normals(&mut mesh, 0.0);
+        assert_eq!(mesh, before);
+    }
+
+    /// A grid of `size` by `size` quads, with the faces in a scrambled order.
+    fn grid_mesh(size: u32) -> Mesh {
+        let vertices = (0..=size)
+            .flat_map(|y| (0..=size).map(move |x| (x, y)))
+            .map(|(x, y)| Vertex::new(0, Vec3::new(x as f32, y as f32, 0.0), Vec3::Z))
+            .collect();
+
+        let vertex = |x: u32, y: u32| y * (size + 1) + x;
+        let mut faces = vec![];
+        for y in 0..size {
+            for x in 0..size {
+                let [a, b, c, d] = [
+                    vertex(x, y),
+                    vertex(x + 1, y),
+                    vertex(x, y + 1),
+                    vertex(x + 1, y + 1),
+                ];
+                faces.push([a, c, b]);
+                faces.push([b, c, d]);
+            }
+        }
+        // 37 has no common factor with the number of faces, so this visits every face once.
+        let faces = (0..faces.len())
+            .map(|i| Face {
+                index: 0,
+                indices: faces[i * 37 % faces.len()],
+            })
+            .collect();
+
+        let mut mesh = Mesh {
+            name: FixedString::from("grid"),
+            texture_name: FixedString::from("grid.bmp"),
+            vertices,
+            faces,
+        };
+        renumber(&mut mesh);
+        mesh
+    }
+
+    /// The faces by the positions of their corners, starting at the smallest one so the winding is
+    /// kept, in a stable order.
+    fn faces_by_position(mesh: &Mesh) -> Vec<[[u32; 3]; 3]> {
+        let mut faces = mesh
+            .faces
+            .iter()
+            .map(|face| {
+                let mut corners = face.indices.map(|i| {
+                    mesh.vertices[i as usize]
+                        .position
+                        .to_array()
+                        .map(f32::to_bits)
+                });
+                let smallest = (0..3).min_by_key(|&i| corners[i]).unwrap();
+                corners.rotate_left(smallest);
+                corners
+            })
+            .collect::<Vec<_>>();
+        faces.sort();
+        faces
+    }
+
+    /// The number of vertices that are not in a FIFO cache of `cache_size` vertices when they are
+    /// used.
+    fn cache_misses(mesh: &Mesh, cache_size: usize) -> usize {
+        let mut cache = std::collections::VecDeque::new();
+        let mut misses = 0;
+        for index in mesh.faces.iter().flat_map(|face| face.indices) {
+            if !cache.contains(&index) {
+                misses += 1;
+                cache.push_back(index);
+                if cache.len() > cache_size {
+                    cache.pop_front();
+                }
+            }
+        }
+        misses
+    }
+
+    #[test]
+    fn optimize_vertex_cache_keeps_faces() {
+        let mut mesh = grid_mesh(8);
+        let before = mesh.clone();
+        optimize_vertex_cache(&mut mesh);
+
+        assert_eq!(faces_by_position(&mesh), faces_by_position(&before));
+        assert_eq!(mesh.vertices.len(), before.vertices.len());
+
+        let (misses_before, misses_after) = (cache_misses(&before, 16), cache_misses(&mesh, 16));
+        assert!(
+            misses_after < misses_before * 3 / 4,
+            "{} cache misses before, {} after",
+            misses_before,
+            misses_after
+        );
+    }
+}
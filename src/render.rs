@@ -0,0 +1,502 @@
+//! A software rasterizer for previews of models, so thumbnails can be rendered on machines
+//! without a GPU. Faces are drawn with a depth buffer, nearest texel sampling, alpha testing and
+//! lambert shading from the vertex normals.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use glam::{Mat4, Vec2, Vec3, Vec3Swizzles, Vec4Swizzles};
+use image::{imageops, Rgba, RgbaImage};
+
+use crate::{
+    smf::{self, CONVERT},
+    texture_resolver::{TextureError, TextureResolver},
+};
+
+/// A camera on a sphere around the model, looking at the center of the model. The model is
+/// framed so it fits the image from every angle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    /// The angle around the vertical axis, in degrees.
+    pub yaw: f32,
+    /// The angle above the horizon, in degrees.
+    pub pitch: f32,
+    /// The vertical field of view, in degrees.
+    pub fov: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            yaw: 30.0,
+            pitch: 25.0,
+            fov: 30.0,
+        }
+    }
+}
+
+impl Camera {
+    /// `count` cameras spaced evenly around the model, starting with this one.
+    pub fn orbit(&self, count: usize) -> Vec<Camera> {
+        (0..count)
+            .map(|i| Camera {
+                yaw: self.yaw + i as f32 * 360.0 / count as f32,
+                ..*self
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    pub width: u32,
+    pub height: u32,
+    pub background: Rgba<u8>,
+    /// The direction the light comes from, relative to the camera: x is right, y is up and z
+    /// points to the viewer.
+    pub light: Vec3,
+    /// The brightness of the faces that are not lit, from 0.0 to 1.0.
+    pub ambient: f32,
+    /// Texels with an alpha below this are not drawn.
+    pub alpha_cutoff: u8,
+    /// Render this many times larger and scale the image down, to smooth the edges.
+    pub supersampling: u32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            width: 256,
+            height: 256,
+            background: Rgba([64, 64, 64, 255]),
+            light: Vec3::new(-0.4, 0.6, 0.7),
+            ambient: 0.35,
+            alpha_cutoff: 128,
+            supersampling: 2,
+        }
+    }
+}
+
+/// A texture that could not be loaded by [TextureCache::load].
+#[derive(Debug)]
+pub struct MissingTexture {
+    pub name: String,
+    /// None if there is no image with the name.
+    pub error: Option<TextureError>,
+}
+
+/// The textures used for rendering, by name. Textures are loaded once per file, so models that
+/// share textures can be rendered quickly. Meshes without a texture are rendered white.
+#[derive(Default)]
+pub struct TextureCache {
+    /// The images by the file they were loaded from: the root of the data directory and the path
+    /// inside it. None if the image could not be loaded.
+    images: HashMap<(PathBuf, String), Option<RgbaImage>>,
+    /// The file of every lowercase texture name of the model passed to the last
+    /// [TextureCache::load]. Models in different directories can use different files for a name.
+    files: HashMap<String, (PathBuf, String)>,
+    /// The images set with [TextureCache::insert], by lowercase name.
+    inserted: HashMap<String, RgbaImage>,
+    /// The lowercase names of the textures that were not found.
+    not_found: HashSet<String>,
+}
+
+impl TextureCache {
+    /// Find the textures of a model and load the ones that were not loaded before. Returns the
+    /// textures that could not be loaded, only the first time they are used.
+    pub fn load(&mut self, model: &smf::Model, resolver: &TextureResolver) -> Vec<MissingTexture> {
+        self.files.clear();
+
+        let mut missing = vec![];
+        for mesh in model.nodes.iter().flat_map(|node| node.meshes.iter()) {
+            let name = mesh.texture_name.to_string();
+            let key = name.to_ascii_lowercase();
+            if name.is_empty() || self.files.contains_key(&key) {
+                continue;
+            }
+
+            let Some((root, path)) = resolver.find_with_root(&name) else {
+                if self.not_found.insert(key) {
+                    missing.push(MissingTexture { name, error: None });
+                }
+                continue;
+            };
+            let file = (root.to_path_buf(), path.to_string());
+
+            if !self.images.contains_key(&file) {
+                let image = match resolver.resolve(&name) {
+                    Ok(texture) => texture.map(|texture| texture.image),
+                    Err(err) => {
+                        missing.push(MissingTexture {
+                            name,
+                            error: Some(err),
+                        });
+                        None
+                    }
+                };
+                self.images.insert(file.clone(), image);
+            }
+            self.files.insert(key, file);
+        }
+        missing
+    }
+
+    /// Use an image for a texture name, instead of the one found by [TextureCache::load].
+    pub fn insert(&mut self, name: &str, image: RgbaImage) {
+        self.inserted.insert(name.to_ascii_lowercase(), image);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RgbaImage> {
+        let key = name.to_ascii_lowercase();
+        self.inserted.get(&key).or_else(|| {
+            self.files
+                .get(&key)
+                .and_then(|file| self.images.get(file))
+                .and_then(Option::as_ref)
+        })
+    }
+}
+
+/// A vertex projected to the render target. The attributes are divided by w, so they can be
+/// interpolated in screen space.
+#[derive(Clone, Copy)]
+struct ScreenVertex {
+    /// x and y in pixels, z is the depth from 0.0 to 1.0.
+    position: Vec3,
+    inv_w: f32,
+    tex_coord: Vec2,
+    normal: Vec3,
+}
+
+struct Target<'a> {
+    image: RgbaImage,
+    depth: Vec<f32>,
+    options: &'a RenderOptions,
+    light: Vec3,
+}
+
+/// Render a model with the nodes in their bind pose.
+pub fn render(
+    model: &smf::Model,
+    camera: &Camera,
+    textures: &TextureCache,
+    options: &RenderOptions,
+) -> RgbaImage {
+    let supersampling = options.supersampling.max(1);
+    let (width, height) = (
+        options.width.max(1) * supersampling,
+        options.height.max(1) * supersampling,
+    );
+
+    let mut target = Target {
+        image: RgbaImage::from_pixel(width, height, options.background),
+        depth: vec![f32::INFINITY; width as usize * height as usize],
+        options,
+        light: options.light.normalize_or_zero(),
+    };
+
+    // Render in the right handed, y up coordinates of smf2gltf, so the camera is the usual one.
+    let transforms = model
+        .world_transforms()
+        .into_iter()
+        .map(|world| CONVERT * world)
+        .collect::<Vec<_>>();
+
+    let positions = || {
+        model
+            .nodes
+            .iter()
+            .zip(transforms.iter())
+            .flat_map(|(node, transform)| {
+                node.meshes
+                    .iter()
+                    .flat_map(|mesh| mesh.vertices.iter())
+                    .map(|vertex| transform.transform_point3(vertex.position))
+            })
+            .filter(|position| position.is_finite())
+    };
+    let Some((min, max)) = positions().fold(None, |bounds, p| match bounds {
+        None => Some((p, p)),
+        Some((min, max)) => Some((p.min(min), p.max(max))),
+    }) else {
+        return scale_down(target.image, options);
+    };
+    let center = (min + max) / 2.0;
+    let radius = positions()
+        .map(|p| p.distance(center))
+        .fold(0.0_f32, f32::max)
+        .max(0.001);
+
+    // Move the camera back far enough to fit the bounding sphere in the narrowest field of view.
+    let aspect = width as f32 / height as f32;
+    let fov = camera.fov.clamp(1.0, 170.0).to_radians();
+    let half_fov = ((fov / 2.0).tan() * aspect.min(1.0)).atan();
+    let distance = radius / half_fov.sin();
+
+    let (yaw, pitch) = (
+        camera.yaw.to_radians(),
+        camera.pitch.clamp(-89.0, 89.0).to_radians(),
+    );
+    let direction = Vec3::new(
+        pitch.cos() * yaw.sin(),
+        pitch.sin(),
+        pitch.cos() * yaw.cos(),
+    );
+    let view = Mat4::look_at_rh(center + direction * distance, center, Vec3::Y);
+    let projection = Mat4::perspective_rh(
+        fov,
+        aspect,
+        (distance - radius).max(distance * 0.01),
+        distance + radius,
+    );
+
+    for (node, transform) in model.nodes.iter().zip(transforms.iter()) {
+        let model_view = view * *transform;
+        let clip = projection * model_view;
+        let normal_matrix = model_view.inverse().transpose();
+
+        for mesh in node.meshes.iter() {
+            let texture = textures.get(&mesh.texture_name);
+
+            let vertices = mesh
+                .vertices
+                .iter()
+                .map(|vertex| {
+                    let position = clip * vertex.position.extend(1.0);
+                    if position.w <= f32::EPSILON {
+                        return None;
+                    }
+                    let inv_w = 1.0 / position.w;
+                    let ndc = position.xyz() * inv_w;
+                    // SMF normals point away from the front of the faces, see smf2gltf.
+                    let normal = -normal_matrix
+                        .transform_vector3(vertex.normal)
+                        .normalize_or_zero();
+                    Some(ScreenVertex {
+                        position: Vec3::new(
+                            (ndc.x + 1.0) / 2.0 * width as f32,
+                            (1.0 - ndc.y) / 2.0 * height as f32,
+                            ndc.z,
+                        ),
+                        inv_w,
+                        tex_coord: vertex.tex_coord * inv_w,
+                        normal: normal * inv_w,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            for face in mesh.faces.iter() {
+                let corners = face
+                    .indices
+                    .map(|i| vertices.get(i as usize).copied().flatten());
+                if let [Some(v0), Some(v1), Some(v2)] = corners {
+                    target.draw_triangle([v0, v1, v2], texture);
+                }
+            }
+        }
+    }
+
+    scale_down(target.image, options)
+}
+
+fn scale_down(image: RgbaImage, options: &RenderOptions) -> RgbaImage {
+    if options.supersampling <= 1 {
+        return image;
+    }
+    imageops::resize(
+        &image,
+        options.width.max(1),
+        options.height.max(1),
+        imageops::FilterType::Triangle,
+    )
+}
+
+fn edge(a: Vec3, b: Vec3, p: Vec2) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+fn sample(texture: &RgbaImage, tex_coord: Vec2) -> Rgba<u8> {
+    // Texture coordinates repeat, like the engine does.
+    let x = (tex_coord.x.rem_euclid(1.0) * texture.width() as f32) as u32;
+    let y = (tex_coord.y.rem_euclid(1.0) * texture.height() as f32) as u32;
+    *texture.get_pixel(x.min(texture.width() - 1), y.min(texture.height() - 1))
+}
+
+impl Target<'_> {
+    /// Draw a triangle, from either side.
+    fn draw_triangle(&mut self, [v0, v1, v2]: [ScreenVertex; 3], texture: Option<&RgbaImage>) {
+        let area = edge(v0.position, v1.position, v2.position.xy());
+        if area.abs() <= f32::EPSILON || !area.is_finite() {
+            return;
+        }
+
+        let (width, height) = self.image.dimensions();
+        let min = v0.position.min(v1.position).min(v2.position);
+        let max = v0.position.max(v1.position).max(v2.position);
+        let (x0, y0) = (min.x.max(0.0) as u32, min.y.max(0.0) as u32);
+        let (x1, y1) = (
+            (max.x.ceil().max(0.0) as u32).min(width),
+            (max.y.ceil().max(0.0) as u32).min(height),
+        );
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let p = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                // Dividing by the signed area makes the weights positive inside the triangle for
+                // both windings.
+                let w0 = edge(v1.position, v2.position, p) / area;
+                let w1 = edge(v2.position, v0.position, p) / area;
+                let w2 = edge(v0.position, v1.position, p) / area;
+                if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                    continue;
+                }
+
+                let depth = w0 * v0.position.z + w1 * v1.position.z + w2 * v2.position.z;
+                let index = y as usize * width as usize + x as usize;
+                if !(0.0..=1.0).contains(&depth) || depth >= self.depth[index] {
+                    continue;
+                }
+
+                let inv_w = w0 * v0.inv_w + w1 * v1.inv_w + w2 * v2.inv_w;
+                let color = match texture {
+                    Some(texture) => {
+                        let tex_coord =
+                            (v0.tex_coord * w0 + v1.tex_coord * w1 + v2.tex_coord * w2) / inv_w;
+                        sample(texture, tex_coord)
+                    }
+                    None => Rgba([255, 255, 255, 255]),
+                };
+                if color[3] < self.options.alpha_cutoff {
+                    continue;
+                }
+
+                let mut normal = ((v0.normal * w0 + v1.normal * w1 + v2.normal * w2) / inv_w)
+                    .normalize_or_zero();
+                // Light the back of faces like the front, faces are drawn from both sides.
+                if normal.z < 0.0 {
+                    normal = -normal;
+                }
+                let ambient = self.options.ambient;
+                let shade = ambient + (1.0 - ambient) * normal.dot(self.light).max(0.0);
+
+                self.depth[index] = depth;
+                self.image.put_pixel(
+                    x,
+                    y,
+                    Rgba([
+                        (color[0] as f32 * shade) as u8,
+                        (color[1] as f32 * shade) as u8,
+                        (color[2] as f32 * shade) as u8,
+                        255,
+                    ]),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixed_string::FixedString;
+
+    #[test]
+    fn render_textured_quad() {
+        let vertex = |position: Vec3, tex_coord: Vec2| smf::Vertex {
+            tex_coord,
+            ..smf::Vertex::new(0, position, Vec3::Y)
+        };
+        // A quad in the x-z plane, which is upright in front of the default camera.
+        let mesh = smf::Mesh {
+            name: FixedString::from("quad"),
+            texture_name: FixedString::from("red.bmp"),
+            vertices: vec![
+                vertex(Vec3::new(-1.0, 0.0, -1.0), Vec2::new(0.0, 1.0)),
+                vertex(Vec3::new(1.0, 0.0, -1.0), Vec2::new(1.0, 1.0)),
+                vertex(Vec3::new(1.0, 0.0, 1.0), Vec2::new(1.0, 0.0)),
+                vertex(Vec3::new(-1.0, 0.0, 1.0), Vec2::new(0.0, 0.0)),
+            ],
+            faces: vec![
+                smf::Face {
+                    index: 0,
+                    indices: [0, 1, 2],
+                },
+                smf::Face {
+                    index: 1,
+                    indices: [0, 2, 3],
+                },
+            ],
+        };
+        let model = smf::Model::new(vec![smf::Node {
+            meshes: vec![mesh],
+            ..smf::Node::new("quad", smf::ROOT_PARENT)
+        }]);
+
+        let mut textures = TextureCache::default();
+        textures.insert(
+            "RED.bmp",
+            RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255])),
+        );
+
+        let options = RenderOptions {
+            width: 32,
+            height: 32,
+            supersampling: 1,
+            ..Default::default()
+        };
+        let camera = Camera {
+            yaw: 0.0,
+            pitch: 0.0,
+            ..Default::default()
+        };
+        let image = render(&model, &camera, &textures, &options);
+
+        let center = image.get_pixel(16, 16);
+        assert!(center[0] > 0 && center[1] == 0 && center[2] == 0);
+        assert_eq!(*image.get_pixel(0, 0), options.background);
+
+        // Transparent texels are not drawn.
+        textures.insert("red.bmp", RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 0])));
+        let image = render(&model, &camera, &textures, &options);
+        assert_eq!(*image.get_pixel(16, 16), options.background);
+    }
+
+    #[test]
+    fn textures_by_file() {
+        let root = std::env::temp_dir().join(format!("texture_cache_{}", std::process::id()));
+        for (dir, red) in [("first", 255), ("second", 128)] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            crate::images::write_bmp_file(
+                &mut std::fs::File::create(root.join(dir).join("skin.bmp")).unwrap(),
+                &image::RgbImage::from_pixel(1, 1, image::Rgb([red, 0, 0])),
+            )
+            .unwrap();
+        }
+
+        let model = smf::Model::new(vec![smf::Node {
+            meshes: vec![smf::Mesh {
+                name: FixedString::from("skin"),
+                texture_name: FixedString::from("SKIN.bmp"),
+                vertices: vec![],
+                faces: vec![],
+            }],
+            ..smf::Node::new("model", smf::ROOT_PARENT)
+        }]);
+
+        // Both models use a texture with the same name, from their own directory.
+        let mut resolver = TextureResolver::default();
+        let mut textures = TextureCache::default();
+        for (dir, red) in [("first", 255), ("second", 128), ("first", 255)] {
+            resolver
+                .add_model_roots(root.join(dir).join("model.smf"))
+                .unwrap();
+            assert!(textures.load(&model, &resolver).is_empty());
+            assert_eq!(textures.get("skin.bmp").unwrap().get_pixel(0, 0)[0], red);
+        }
+        assert_eq!(textures.images.len(), 2);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
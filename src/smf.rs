@@ -1,6 +1,10 @@
 use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+use std::collections::HashMap;
 
-use crate::io::Reader;
+use crate::{
+    fixed_string::FixedString,
+    io::{Reader, Writer},
+};
 
 /// This matrix converts from the left handed z-up coordinate system used by SC to the system used
 /// by gltf files which is right handed and y-up.
@@ -20,21 +24,85 @@ pub const CONVERT_NORMAL: Mat4 = Mat4::from_cols(
     Vec4::new(0.0, 0.0, 0.0, 1.0),
 );
 
+const MAGIC: &[u32; 2] = &[0xC131FA1A, 0x1442EDDE];
+
+/// The versions of the SMF format. With the `clap` feature they can be given on the command line as
+/// "1.0" and "1.1".
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum SmfVersion {
+    /// "SMF V1.0"
+    #[cfg_attr(feature = "clap", value(name = "1.0"))]
+    V1_0,
+    /// "SMF V1.1", nodes have an extra field.
+    #[default]
+    #[cfg_attr(feature = "clap", value(name = "1.1"))]
+    V1_1,
+}
+
+impl SmfVersion {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            s if s.starts_with("SMF V1.0") => Some(Self::V1_0),
+            s if s.starts_with("SMF V1.1") => Some(Self::V1_1),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::V1_0 => "SMF V1.0",
+            Self::V1_1 => "SMF V1.1",
+        }
+    }
+}
+
 /// A container for an single model.
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Model {
-    pub name: String,
+    /// The text header in front of the data, without the magic. A header is generated when writing
+    /// if this is empty.
+    pub header: Vec<u8>,
+    pub version: SmfVersion,
+    /// The version field as it was read, including the bytes after the NUL terminator. The field
+    /// is generated from [Model::version] when writing if this is empty or names another version.
+    pub version_field: FixedString,
+    pub name: FixedString,
     pub scale: Vec3,
+    /// Unknown, usually 1.0.
+    pub u0: f32,
+    /// Unknown, usually 1.
+    pub u1: u32,
     pub nodes: Vec<Node>,
 }
 
 impl Model {
+    /// A model with the default version and the usual values for the unknown fields. The header
+    /// and the version field are generated when it is written.
+    pub fn new(nodes: Vec<Node>) -> Self {
+        Self {
+            header: vec![],
+            version: SmfVersion::default(),
+            version_field: FixedString::default(),
+            name: FixedString::default(),
+            scale: Vec3::ONE,
+            u0: 1.0,
+            u1: 1,
+            nodes,
+        }
+    }
+
     pub fn read(r: &mut impl Reader) -> std::io::Result<Self> {
-        const MAGIC: &[u32; 2] = &[0xC131FA1A, 0x1442EDDE];
-        let _ = r.skip_sinister_header_2(MAGIC, 0x4000)?;
+        let start = r.stream_position()?;
+        let header_size = r.skip_sinister_header_2(MAGIC, 0x4000)?;
 
-        let smf_version = smf_version(&r.read_fixed_string(16)?);
-        if smf_version == 0 {
+        let mut header = vec![0_u8; (header_size - std::mem::size_of_val(MAGIC) as u64) as usize];
+        r.seek(std::io::SeekFrom::Start(start))?;
+        r.read_exact(&mut header)?;
+        r.seek(std::io::SeekFrom::Start(start + header_size))?;
+
+        let version_field = r.read_fixed_string(16)?;
+        let Some(version) = SmfVersion::parse(&version_field) else {
             return Err(std::io::Error::other("Invalid SMF version."));
         };
 
@@ -42,29 +110,313 @@ impl Model {
 
         let scale = r.read_vec3()?;
 
-        let _ = r.read_f32()?; // usually == 1.0
-        let _ = r.read_u32()?; // usually == 1
+        let u0 = r.read_f32()?;
+        let u1 = r.read_u32()?;
 
         let node_count = r.read_u32()?;
 
         let mut nodes = Vec::with_capacity(node_count as usize);
         for _ in 0..node_count {
-            nodes.push(Node::read(r, smf_version)?);
+            nodes.push(Node::read(r, version)?);
+        }
+
+        Ok(Self {
+            header,
+            version,
+            version_field,
+            name,
+            scale,
+            u0,
+            u1,
+            nodes,
+        })
+    }
+
+    /// Write the model in the format of [Model::version]. A model that was read is written back
+    /// unchanged.
+    pub fn write(&self, w: &mut impl Writer) -> std::io::Result<()> {
+        if self.header.is_empty() {
+            self.write_header(w)?;
+        } else {
+            w.write_all(&self.header)?;
+        }
+        w.write_u32(MAGIC[0])?;
+        w.write_u32(MAGIC[1])?;
+
+        if SmfVersion::parse(&self.version_field) == Some(self.version) {
+            w.write_fixed_string(&self.version_field, 16)?;
+        } else {
+            w.write_fixed_string(&FixedString::from(self.version.as_str()), 16)?;
+        }
+        w.write_fixed_string(&self.name, 128)?;
+
+        w.write_vec3(self.scale)?;
+
+        w.write_f32(self.u0)?;
+        w.write_u32(self.u1)?;
+
+        w.write_u32(self.nodes.len() as u32)?;
+        for node in self.nodes.iter() {
+            node.write(w, self.version)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_header(&self, w: &mut impl Writer) -> std::io::Result<()> {
+        for line in [
+            "**************************************************************",
+            "** Sinister Model File - Copyright(C) 1999 Sinister Games, Inc",
+            "**",
+            &format!("** SMF Version: {}", self.version.as_str()),
+            &format!("** Model Name: {}", self.name.as_str()),
+            "**************************************************************",
+        ] {
+            w.write_all(line.as_bytes())?;
+            w.write_all(b"\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// The parent name of the root node.
+pub const ROOT_PARENT: &str = "<root>";
+
+/// A problem with the hierarchy of the nodes of a [Model], see [Model::validate_hierarchy].
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum HierarchyError {
+    #[error("The model has no root node.")]
+    NoRoot,
+    #[error("The model has multiple root nodes: {}", .0.join(", "))]
+    MultipleRoots(Vec<String>),
+    #[error("The parent \"{parent}\" of node \"{node}\" was not found.")]
+    MissingParent { node: String, parent: String },
+    #[error("Node \"{0}\" is its own ancestor.")]
+    Cycle(String),
+}
+
+/// Navigating the node hierarchy. Nodes are referenced by their index in [Model::nodes] and
+/// parents are found by name, so the first node with a name is used if there are duplicates.
+impl Model {
+    /// The index of the first node without a parent.
+    pub fn root(&self) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|node| node.parent_name == ROOT_PARENT)
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+
+    pub fn find_by_tree_id(&self, tree_id: u32) -> Option<usize> {
+        self.nodes.iter().position(|node| node.tree_id == tree_id)
+    }
+
+    /// The parent of a node, `None` for the root or if the parent does not exist.
+    pub fn parent(&self, index: usize) -> Option<usize> {
+        let parent_name = &self.nodes.get(index)?.parent_name;
+        if parent_name == ROOT_PARENT {
+            return None;
+        }
+        self.find_by_name(parent_name)
+    }
+
+    /// The parent of every node, see [Model::parent]. The names are looked up once, so this is
+    /// cheaper than calling [Model::parent] for every node.
+    pub fn parents(&self) -> Vec<Option<usize>> {
+        // Like find_by_name, the first node with a name wins.
+        let mut indices = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            indices.entry(node.name.as_str()).or_insert(index);
+        }
+        self.nodes
+            .iter()
+            .map(|node| {
+                if node.parent_name == ROOT_PARENT {
+                    None
+                } else {
+                    indices.get(node.parent_name.as_str()).copied()
+                }
+            })
+            .collect()
+    }
+
+    /// The children of a node, in the order they are stored.
+    pub fn children(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        // Only the first node with a name can be a parent, see Model::parent.
+        let name = self
+            .nodes
+            .get(index)
+            .filter(|node| self.find_by_name(node.name.as_str()) == Some(index))
+            .map(|node| &node.name);
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(move |&(_, node)| {
+                Some(&node.parent_name) == name && node.parent_name != ROOT_PARENT
+            })
+            .map(|(child, _)| child)
+    }
+
+    /// Visit the nodes depth first, starting with the roots. Yields the index and the depth of
+    /// every node, roots have a depth of 0. Nodes that are not reachable from a root, because of a
+    /// missing parent or a cycle, are skipped.
+    pub fn depth_first(&self) -> DepthFirst {
+        let roots = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.parent_name == ROOT_PARENT)
+            .map(|(index, _)| (index, 0))
+            .rev()
+            .collect();
+        let mut children = vec![vec![]; self.nodes.len()];
+        for (child, parent) in self.parents().into_iter().enumerate() {
+            if let Some(parent) = parent {
+                children[parent].push(child);
+            }
+        }
+        DepthFirst {
+            children,
+            stack: roots,
+            visited: vec![false; self.nodes.len()],
+        }
+    }
+
+    /// The transform of a node relative to its parent.
+    pub fn local_transform(&self, index: usize) -> Mat4 {
+        self.nodes
+            .get(index)
+            .map(|node| Mat4::from_rotation_translation(node.rotation, node.position))
+            .unwrap_or(Mat4::IDENTITY)
+    }
+
+    /// The transform of a node in model space. The walk up the hierarchy stops at a missing parent
+    /// or a cycle.
+    pub fn world_transform(&self, index: usize) -> Mat4 {
+        self.ancestors(index)
+            .fold(self.local_transform(index), |world, ancestor| {
+                self.local_transform(ancestor) * world
+            })
+    }
+
+    /// The position of a node in model space, without applying the rotations of its ancestors.
+    /// Tools that keep the vertices unrotated, like the nodes mode of smf2gltf, place the nodes
+    /// with this.
+    pub fn world_position(&self, index: usize) -> Vec3 {
+        let position = self
+            .nodes
+            .get(index)
+            .map(|node| node.position)
+            .unwrap_or(Vec3::ZERO);
+        self.ancestors(index).fold(position, |world, ancestor| {
+            world + self.nodes[ancestor].position
+        })
+    }
+
+    /// The parent, grandparent and so on of a node. Limited to the number of nodes, in case the
+    /// parents form a cycle.
+    fn ancestors(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(self.parent(index), |&ancestor| self.parent(ancestor))
+            .take(self.nodes.len())
+    }
+
+    /// The world transforms of all the nodes, see [Model::world_transform].
+    pub fn world_transforms(&self) -> Vec<Mat4> {
+        (0..self.nodes.len())
+            .map(|index| self.world_transform(index))
+            .collect()
+    }
+
+    /// Check that there is a single root and that every node can be reached from it.
+    pub fn validate_hierarchy(&self) -> Result<(), Vec<HierarchyError>> {
+        let mut errors = vec![];
+
+        let roots = self
+            .nodes
+            .iter()
+            .filter(|node| node.parent_name == ROOT_PARENT)
+            .map(|node| node.name.to_string())
+            .collect::<Vec<_>>();
+        match roots.len() {
+            0 => errors.push(HierarchyError::NoRoot),
+            1 => {}
+            _ => errors.push(HierarchyError::MultipleRoots(roots)),
         }
 
-        Ok(Self { name, scale, nodes })
+        for (index, node) in self.nodes.iter().enumerate() {
+            if node.parent_name == ROOT_PARENT {
+                continue;
+            }
+            if self.find_by_name(&node.parent_name).is_none() {
+                errors.push(HierarchyError::MissingParent {
+                    node: node.name.to_string(),
+                    parent: node.parent_name.to_string(),
+                });
+                continue;
+            }
+
+            let mut ancestor = self.parent(index);
+            for _ in 0..self.nodes.len() {
+                match ancestor {
+                    Some(a) if a == index => {
+                        errors.push(HierarchyError::Cycle(node.name.to_string()));
+                        break;
+                    }
+                    Some(a) => ancestor = self.parent(a),
+                    None => break,
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 }
 
-#[derive(Debug)]
+/// See [Model::depth_first].
+pub struct DepthFirst {
+    /// The children of every node, built once from [Model::parents].
+    children: Vec<Vec<usize>>,
+    stack: Vec<(usize, usize)>,
+    visited: Vec<bool>,
+}
+
+impl Iterator for DepthFirst {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, depth) = loop {
+            let (index, depth) = self.stack.pop()?;
+            if !std::mem::replace(&mut self.visited[index], true) {
+                break (index, depth);
+            }
+        };
+
+        self.stack.extend(
+            self.children[index]
+                .iter()
+                .rev()
+                .map(|&child| (child, depth + 1)),
+        );
+
+        Some((index, depth))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Mesh {
-    pub name: String,
-    pub texture_name: String,
+    pub name: FixedString,
+    pub texture_name: FixedString,
     pub vertices: Vec<Vertex>,
     pub faces: Vec<Face>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Face {
     pub index: u32,
     pub indices: [u32; 3],
@@ -82,6 +434,14 @@ impl Face {
             indices: [i0, i1, i2],
         })
     }
+
+    fn write(&self, w: &mut impl Writer) -> std::io::Result<()> {
+        w.write_u32(self.index)?;
+        for index in self.indices {
+            w.write_u32(index)?;
+        }
+        Ok(())
+    }
 }
 
 impl Mesh {
@@ -109,27 +469,57 @@ impl Mesh {
             faces,
         })
     }
+
+    fn write(&self, w: &mut impl Writer) -> std::io::Result<()> {
+        w.write_fixed_string(&self.name, 128)?;
+        w.write_fixed_string(&self.texture_name, 128)?;
+
+        w.write_u32(self.vertices.len() as u32)?;
+        w.write_u32(self.faces.len() as u32)?;
+
+        for vertex in self.vertices.iter() {
+            vertex.write(w)?;
+        }
+        for face in self.faces.iter() {
+            face.write(w)?;
+        }
+
+        Ok(())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Vertex {
     pub index: u32,
     pub position: Vec3,
+    /// Unknown, usually 0xFFFF_FFFF.
+    pub u1: u32,
+    /// Unknown, usually 0.0.
+    pub u2: f32,
     pub tex_coord: Vec2,
     pub normal: Vec3,
 }
 
 impl Vertex {
+    /// A vertex with the usual values for the unknown fields and texture coordinates at 0.
+    pub fn new(index: u32, position: Vec3, normal: Vec3) -> Self {
+        Self {
+            index,
+            position,
+            u1: 0xFFFF_FFFF,
+            u2: 0.0,
+            tex_coord: Vec2::ZERO,
+            normal,
+        }
+    }
+
     fn read(r: &mut impl Reader) -> std::io::Result<Self> {
         let index = r.read_u32()?;
 
         let position = r.read_vec3()?;
 
-        let u1 = r.read_u32()?; // usually == 0xFFFF_FFFF
-        let u2 = r.read_f32()?; // usually == 0.0
-
-        assert!(u1 == 0xFFFF_FFFF);
-        assert!(u2 == 0.0);
+        let u1 = r.read_u32()?;
+        let u2 = r.read_f32()?;
 
         let tex_coord = r.read_vec2()?;
 
@@ -138,20 +528,79 @@ impl Vertex {
         Ok(Vertex {
             index,
             position,
+            u1,
+            u2,
             tex_coord,
             normal,
         })
     }
+
+    fn write(&self, w: &mut impl Writer) -> std::io::Result<()> {
+        w.write_u32(self.index)?;
+        w.write_vec3(self.position)?;
+        w.write_u32(self.u1)?;
+        w.write_f32(self.u2)?;
+        w.write_vec2(self.tex_coord)?;
+        w.write_vec3(self.normal)
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct BoundingBox {
     pub max: Vec3,
     pub min: Vec3,
+    /// Unknown. The smf tool compares it to the half diagonal of the box with `--histograms`.
     pub u0: f32,
 }
 
 impl BoundingBox {
+    /// The smallest box around `points`. `u0` is set to half the diagonal, which is a guess.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let (min, max) = points.fold((first, first), |(min, max), p| (min.min(p), max.max(p)));
+
+        Some(Self {
+            max,
+            min,
+            u0: (max - min).length() / 2.0,
+        })
+    }
+
+    /// The smallest box around all the vertices of `meshes`.
+    pub fn from_meshes(meshes: &[Mesh]) -> Option<Self> {
+        Self::from_points(
+            meshes
+                .iter()
+                .flat_map(|mesh| mesh.vertices.iter())
+                .map(|vertex| vertex.position),
+        )
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vec3::new(min.x, min.y, min.z),
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+            Vec3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    /// How far `point` is outside the box, 0.0 if it is inside.
+    pub fn distance_outside(&self, point: Vec3) -> f32 {
+        let outside = (self.min - point).max(point - self.max).max(Vec3::ZERO);
+        outside.length()
+    }
+
     fn read(c: &mut impl Reader) -> std::io::Result<Self> {
         let max = c.read_vec3()?;
         let min = c.read_vec3()?;
@@ -159,21 +608,97 @@ impl BoundingBox {
 
         Ok(BoundingBox { max, min, u0 })
     }
+
+    fn write(&self, w: &mut impl Writer) -> std::io::Result<()> {
+        w.write_vec3(self.max)?;
+        w.write_vec3(self.min)?;
+        w.write_f32(self.u0)
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Node {
-    pub name: String,
-    pub parent_name: String,
+    pub name: FixedString,
+    pub parent_name: FixedString,
     pub tree_id: u32,
     pub position: Vec3,
     pub rotation: Quat,
     pub meshes: Vec<Mesh>,
     pub bounding_boxes: Vec<BoundingBox>,
+    /// Unknown, only stored in SMF V1.1 files.
+    pub u0: u32,
+}
+
+/// A mesh with vertices outside the bounding boxes of its node, see
+/// [Node::check_bounding_boxes].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshOutsideBoundingBoxes {
+    /// Index into [Node::meshes].
+    pub mesh: usize,
+    pub vertices_outside: usize,
+    /// The distance of the vertex furthest outside the boxes.
+    pub max_distance: f32,
 }
 
 impl Node {
-    fn read(r: &mut impl Reader, smf_version: u32) -> std::io::Result<Self> {
+    /// A node at the origin of its parent, without meshes or bounding boxes. The parent of the
+    /// root node is [ROOT_PARENT].
+    pub fn new(name: &str, parent_name: &str) -> Self {
+        Self {
+            name: FixedString::from(name),
+            parent_name: FixedString::from(parent_name),
+            tree_id: 0,
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            meshes: vec![],
+            bounding_boxes: vec![],
+            u0: 0,
+        }
+    }
+
+    /// The smallest box around the vertices of the node, in the space of the node.
+    pub fn compute_bounding_box(&self) -> Option<BoundingBox> {
+        BoundingBox::from_meshes(&self.meshes)
+    }
+
+    /// Replace the bounding boxes with the box from [Node::compute_bounding_box].
+    pub fn recompute_bounding_boxes(&mut self) {
+        self.bounding_boxes = self.compute_bounding_box().into_iter().collect();
+    }
+
+    /// The meshes with vertices that are more than `tolerance` outside all of the bounding boxes
+    /// of the node. Nodes without bounding boxes are not checked.
+    pub fn check_bounding_boxes(&self, tolerance: f32) -> Vec<MeshOutsideBoundingBoxes> {
+        if self.bounding_boxes.is_empty() {
+            return vec![];
+        }
+
+        self.meshes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, mesh)| {
+                let distances = mesh
+                    .vertices
+                    .iter()
+                    .map(|vertex| {
+                        self.bounding_boxes
+                            .iter()
+                            .map(|b| b.distance_outside(vertex.position))
+                            .fold(f32::INFINITY, f32::min)
+                    })
+                    .filter(|&distance| distance > tolerance)
+                    .collect::<Vec<_>>();
+
+                (!distances.is_empty()).then(|| MeshOutsideBoundingBoxes {
+                    mesh: index,
+                    vertices_outside: distances.len(),
+                    max_distance: distances.iter().copied().fold(0.0, f32::max),
+                })
+            })
+            .collect()
+    }
+
+    fn read(r: &mut impl Reader, smf_version: SmfVersion) -> std::io::Result<Self> {
         let name = r.read_fixed_string(128)?;
         let parent_name = r.read_fixed_string(128)?;
 
@@ -186,9 +711,11 @@ impl Node {
         let mesh_count = r.read_u32()?;
         let bounding_box_count = r.read_u32()?;
 
-        if smf_version > 1 {
-            let _ = r.read_u32()?;
-        }
+        let u0 = if smf_version == SmfVersion::V1_1 {
+            r.read_u32()?
+        } else {
+            0
+        };
 
         let mut meshes = Vec::with_capacity(mesh_count as usize);
         for _ in 0..mesh_count {
@@ -208,14 +735,232 @@ impl Node {
             rotation,
             meshes,
             bounding_boxes,
+            u0,
         })
     }
+
+    fn write(&self, w: &mut impl Writer, smf_version: SmfVersion) -> std::io::Result<()> {
+        w.write_fixed_string(&self.name, 128)?;
+        w.write_fixed_string(&self.parent_name, 128)?;
+
+        w.write_u32(self.tree_id)?;
+
+        w.write_vec3(self.position)?;
+        for value in self.rotation.to_array() {
+            w.write_f32(value)?;
+        }
+
+        w.write_u32(self.meshes.len() as u32)?;
+        w.write_u32(self.bounding_boxes.len() as u32)?;
+
+        if smf_version == SmfVersion::V1_1 {
+            w.write_u32(self.u0)?;
+        }
+
+        for mesh in self.meshes.iter() {
+            mesh.write(w)?;
+        }
+        for bounding_box in self.bounding_boxes.iter() {
+            bounding_box.write(w)?;
+        }
+
+        Ok(())
+    }
 }
 
-fn smf_version(s: &str) -> u32 {
-    match s {
-        s if s.starts_with("SMF V1.0") => 1,
-        s if s.starts_with("SMF V1.1") => 2,
-        _ => 0,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_model(version: SmfVersion) -> Model {
+        let vertex = |index: u32, x: f32, y: f32| Vertex {
+            u2: index as f32,
+            tex_coord: Vec2::new(x, y),
+            ..Vertex::new(index, Vec3::new(x, y, 0.0), Vec3::Z)
+        };
+        // The extra node field is only stored in V1.1 files.
+        let node_u0 = if version == SmfVersion::V1_1 { 7 } else { 0 };
+
+        Model {
+            version,
+            version_field: FixedString::from(version.as_str()),
+            name: FixedString::from("test"),
+            u1: 2,
+            ..Model::new(vec![
+                Node {
+                    bounding_boxes: vec![BoundingBox {
+                        max: Vec3::ONE,
+                        min: Vec3::ZERO,
+                        u0: 0.5,
+                    }],
+                    u0: node_u0,
+                    ..Node::new("root", ROOT_PARENT)
+                },
+                Node {
+                    tree_id: 1,
+                    position: Vec3::new(1.0, 2.0, 3.0),
+                    rotation: Quat::from_rotation_z(1.0),
+                    meshes: vec![Mesh {
+                        name: FixedString::from("mesh"),
+                        texture_name: FixedString::from("body.bmp"),
+                        vertices: vec![
+                            vertex(0, 0.0, 0.0),
+                            vertex(1, 1.0, 0.0),
+                            vertex(2, 0.0, 1.0),
+                        ],
+                        faces: vec![Face {
+                            index: 0,
+                            indices: [0, 1, 2],
+                        }],
+                    }],
+                    u0: node_u0,
+                    ..Node::new("body", "root")
+                },
+            ])
+        }
+    }
+
+    #[test]
+    fn write_round_trip() {
+        for version in [SmfVersion::V1_0, SmfVersion::V1_1] {
+            let model = test_model(version);
+
+            let mut data = vec![];
+            model.write(&mut data).unwrap();
+            let read = Model::read(&mut std::io::Cursor::new(&data)).unwrap();
+            assert!(read.header.starts_with(b"****"));
+            assert_eq!(
+                Model {
+                    header: vec![],
+                    ..read.clone()
+                },
+                model
+            );
+
+            let mut rewritten = vec![];
+            read.write(&mut rewritten).unwrap();
+            assert_eq!(rewritten, data);
+        }
+    }
+
+    #[test]
+    fn keep_version_padding() {
+        let mut model = test_model(SmfVersion::V1_0);
+        let mut data = vec![];
+        model.write(&mut data).unwrap();
+
+        // Put garbage after the NUL terminator of the version field. The generated header also
+        // contains the version, the field is the last match.
+        let field = data
+            .windows(8)
+            .rposition(|w| w == b"SMF V1.0")
+            .expect("version field");
+        data[field + 9..field + 16].copy_from_slice(b"garbage");
+
+        let read = Model::read(&mut std::io::Cursor::new(&data)).unwrap();
+        assert_eq!(read.version, SmfVersion::V1_0);
+        let mut rewritten = vec![];
+        read.write(&mut rewritten).unwrap();
+        assert_eq!(rewritten, data);
+
+        // Changing the version generates a new field.
+        model = read;
+        model.version = SmfVersion::V1_1;
+        let mut converted = vec![];
+        model.write(&mut converted).unwrap();
+        assert_eq!(&converted[field..field + 16], b"SMF V1.1\0\0\0\0\0\0\0\0");
+    }
+
+    #[test]
+    fn hierarchy() {
+        let mut model = test_model(SmfVersion::V1_1);
+        let mut arm = model.nodes[1].clone();
+        arm.name = FixedString::from("arm");
+        arm.parent_name = FixedString::from("body");
+        arm.tree_id = 5;
+        arm.position = Vec3::X;
+        model.nodes.push(arm);
+
+        assert_eq!(model.root(), Some(0));
+        assert_eq!(model.find_by_name("arm"), Some(2));
+        assert_eq!(model.find_by_tree_id(5), Some(2));
+        assert_eq!(model.parent(2), Some(1));
+        assert_eq!(model.parents(), vec![None, Some(0), Some(1)]);
+        assert_eq!(model.children(0).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(
+            model.depth_first().collect::<Vec<_>>(),
+            vec![(0, 0), (1, 1), (2, 2)]
+        );
+        assert!(model
+            .world_transform(2)
+            .transform_point3(Vec3::ZERO)
+            .abs_diff_eq(
+                Vec3::new(1.0, 2.0, 3.0) + Quat::from_rotation_z(1.0) * Vec3::X,
+                1e-6
+            ));
+        assert_eq!(model.world_position(2), model.nodes[1].position + Vec3::X);
+        assert_eq!(model.validate_hierarchy(), Ok(()));
+
+        model.nodes[1].parent_name = FixedString::from("arm");
+        model.nodes.push(Node {
+            parent_name: FixedString::from("missing"),
+            ..model.nodes[0].clone()
+        });
+        assert_eq!(
+            model.validate_hierarchy(),
+            Err(vec![
+                HierarchyError::Cycle("body".to_string()),
+                HierarchyError::Cycle("arm".to_string()),
+                HierarchyError::MissingParent {
+                    node: "root".to_string(),
+                    parent: "missing".to_string(),
+                },
+            ])
+        );
+        assert_eq!(model.depth_first().collect::<Vec<_>>(), vec![(0, 0)]);
+        // The walk up a cycle stops.
+        assert!(model.world_position(1).is_finite());
+    }
+
+    #[test]
+    fn bounding_boxes() {
+        let mut node = test_model(SmfVersion::V1_1).nodes[1].clone();
+        node.bounding_boxes = vec![BoundingBox {
+            max: Vec3::new(0.5, 1.0, 1.0),
+            min: Vec3::ZERO,
+            u0: 0.0,
+        }];
+
+        assert_eq!(
+            node.check_bounding_boxes(0.01),
+            vec![MeshOutsideBoundingBoxes {
+                mesh: 0,
+                vertices_outside: 1,
+                max_distance: 0.5,
+            }]
+        );
+
+        node.recompute_bounding_boxes();
+        assert_eq!(node.bounding_boxes[0].min, Vec3::ZERO);
+        assert_eq!(node.bounding_boxes[0].max, Vec3::new(1.0, 1.0, 0.0));
+        assert!(node.check_bounding_boxes(0.0).is_empty());
+    }
+
+    #[test]
+    fn unknown_fields_are_written_back() {
+        let mut model = test_model(SmfVersion::V1_1);
+        model.header = b"** Some other header\r\n".to_vec();
+        model.u0 = 0.25;
+        model.nodes[1].meshes[0].vertices[0].u1 = 3;
+
+        let mut data = vec![];
+        model.write(&mut data).unwrap();
+        assert!(data.starts_with(&model.header));
+
+        let read = Model::read(&mut std::io::Cursor::new(&data)).unwrap();
+        assert_eq!(read, model);
+        let mut rewritten = vec![];
+        read.write(&mut rewritten).unwrap();
+        assert_eq!(rewritten, data);
     }
 }
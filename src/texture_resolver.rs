@@ -0,0 +1,442 @@
+//! Find and load the textures referenced by models and configs.
+//!
+//! Textures are referenced by file name only, like `Mesh::texture_name`, and the case of the name
+//! often does not match the file. The resolver indexes the image files in one or more data
+//! directories, including the ones inside .gut archives, and loads them the way the engine does:
+//!
+//! - A .bmp with a .raw file next to it uses the .raw file as its alpha channel.
+//! - A .bmp with a name ending in `_ck` is color keyed, black pixels are transparent.
+//! - .jpg files are always opaque.
+
+use std::path::{Path, PathBuf};
+
+use image::{ImageError, RgbaImage};
+use thiserror::Error;
+
+use crate::{
+    common::{GameHashMap, GameName, GameStr},
+    data_dir::{DataDir, DataDirError, File},
+    images::{combine_bmp_and_raw, load_bmp_file_with_color_key, load_raw_file, ColorKeyRange},
+    io::PathExt,
+};
+
+#[derive(Debug, Error)]
+pub enum TextureError {
+    #[error("Data dir error: {0}")]
+    DataDir(#[from] DataDirError),
+    #[error("Image error: {0}")]
+    Image(#[from] ImageError),
+}
+
+impl From<std::io::Error> for TextureError {
+    fn from(value: std::io::Error) -> Self {
+        Self::DataDir(value.into())
+    }
+}
+
+/// Where the alpha channel of a texture came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TextureAlpha {
+    /// The texture is fully opaque.
+    Opaque,
+    /// The alpha channel was loaded from a .raw file, with its path inside the data directory.
+    Raw(String),
+    /// Pixels in a color key range are transparent. Black for names ending in `_ck`.
+    ColorKey,
+}
+
+/// How the transparency of a .bmp texture is loaded. See [TextureKeying::from_name] for the
+/// keying used by [TextureResolver::resolve].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureKeying {
+    /// Pixels in the range are transparent.
+    pub color_key: Option<ColorKeyRange>,
+    /// Use a .raw file next to the .bmp as the alpha channel, if there is one. It replaces the
+    /// alpha of the color key.
+    pub raw_alpha: bool,
+}
+
+impl TextureKeying {
+    /// The keying the engine uses for textures without a definition: names ending in `_ck` are
+    /// keyed on black, other textures use their .raw file.
+    pub fn from_name(path: &str) -> Self {
+        let color_keyed = is_color_keyed(path);
+        Self {
+            color_key: color_keyed.then_some(ColorKeyRange::BLACK),
+            raw_alpha: !color_keyed,
+        }
+    }
+}
+
+/// A loaded texture and where it was found.
+#[derive(Clone, Debug)]
+pub struct Texture {
+    pub image: RgbaImage,
+    /// The root of the data directory the texture was found in.
+    pub root: PathBuf,
+    /// The path of the image inside the data directory.
+    pub path: String,
+    /// True if the image was stored inside a .gut archive.
+    pub archived: bool,
+    pub alpha: TextureAlpha,
+}
+
+impl Texture {
+    /// The path of the file on disk, if the texture is not stored in an archive.
+    pub fn file_path(&self) -> Option<PathBuf> {
+        (!self.archived).then(|| self.root.join(Path::new(&self.path).with_os_separators()))
+    }
+}
+
+struct Root {
+    path: PathBuf,
+    data_dir: DataDir,
+    /// Maps file names to their paths inside the data directory.
+    files: GameHashMap<GameName, Vec<String>>,
+    /// True if the root was only added by [TextureResolver::add_model_roots].
+    model_root: bool,
+    /// False for the model roots of an earlier model, which are kept so they are not indexed
+    /// again.
+    searched: bool,
+}
+
+/// Finds textures by name in a list of data directories. Roots are searched in the order they were
+/// added. The roots of a model are only searched until the roots of the next model are added, so
+/// models in different directories don't use each other's textures.
+#[derive(Default)]
+pub struct TextureResolver {
+    roots: Vec<Root>,
+}
+
+const IMAGE_EXTENSIONS: &[&str] = &["bmp", "raw", "jpg", "jpeg"];
+
+impl TextureResolver {
+    /// Create a resolver for a model. Searches the directory of the model, and the "Data"
+    /// directory it is in, if any.
+    pub fn for_model(model_path: impl AsRef<Path>) -> Result<Self, DataDirError> {
+        let mut resolver = Self::default();
+        resolver.add_model_roots(model_path)?;
+        Ok(resolver)
+    }
+
+    /// Add the roots used by [TextureResolver::for_model], replacing the roots of the previous
+    /// model.
+    pub fn add_model_roots(&mut self, model_path: impl AsRef<Path>) -> Result<(), DataDirError> {
+        for root in self.roots.iter_mut().filter(|root| root.model_root) {
+            root.searched = false;
+        }
+
+        let model_dir = match model_path.as_ref().parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        self.add(model_dir, true)?;
+
+        let model_dir = model_dir
+            .canonicalize()
+            .unwrap_or_else(|_| model_dir.to_path_buf());
+        let data_dir = model_dir.ancestors().find(|dir| {
+            dir.file_name()
+                .map(|name| name.eq_ignore_ascii_case("data"))
+                .unwrap_or(false)
+        });
+        if let Some(data_dir) = data_dir {
+            self.add(data_dir, true)?;
+        }
+
+        Ok(())
+    }
+
+    /// Index all the images in a directory, including the ones in .gut archives. Adding the same
+    /// directory twice does nothing. Files that can not be read are skipped with a warning, only
+    /// an unreadable root is an error.
+    pub fn add_root(&mut self, root: impl AsRef<Path>) -> Result<(), DataDirError> {
+        self.add(root.as_ref(), false)
+    }
+
+    fn add(&mut self, root: &Path, model_root: bool) -> Result<(), DataDirError> {
+        if let Some(existing) = self.roots.iter_mut().find(|r| r.path == root) {
+            existing.model_root &= model_root;
+            existing.searched = true;
+            return Ok(());
+        }
+        let root = root.to_path_buf();
+
+        let data_dir = DataDir::new(&root);
+
+        let mut files = GameHashMap::<GameName, Vec<String>>::default();
+        let walk = data_dir.walk()?;
+        for (path, err) in walk.errors.iter() {
+            eprintln!("Could not read {}: {}", path, err);
+        }
+        for path in walk.files {
+            if !has_extension(&path, IMAGE_EXTENSIONS) {
+                continue;
+            }
+            files
+                .entry(GameName::from(file_name(&path)))
+                .or_default()
+                .push(path);
+        }
+
+        self.roots.push(Root {
+            data_dir,
+            path: root,
+            files,
+            model_root,
+            searched: true,
+        });
+
+        Ok(())
+    }
+
+    /// Returns true if there is an image with the exact file name, ignoring case.
+    pub fn contains(&self, name: &str) -> bool {
+        let name = GameStr::new(file_name(name));
+        self.searched_roots()
+            .any(|root| root.files.contains_key(name))
+    }
+
+    /// Find and load a texture by name. Any directories in the name are ignored. If there is no
+    /// file with the exact name, a file with the same stem and another image extension is used.
+    pub fn resolve(&self, name: &str) -> Result<Option<Texture>, TextureError> {
+        match self.find_in_roots(name) {
+            Some((root, path)) => root.load(path, TextureKeying::from_name(path)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Like [TextureResolver::resolve], but with the keying given by a definition, like the color
+    /// key range of a SPRITE3D, instead of the keying of the name.
+    pub fn resolve_with_keying(
+        &self,
+        name: &str,
+        keying: TextureKeying,
+    ) -> Result<Option<Texture>, TextureError> {
+        match self.find_in_roots(name) {
+            Some((root, path)) => root.load(path, keying).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Find a texture like [TextureResolver::resolve], without loading it. Returns the path of the
+    /// image inside its data directory.
+    pub fn find(&self, name: &str) -> Option<&str> {
+        self.find_in_roots(name).map(|(_, path)| path)
+    }
+
+    /// Like [TextureResolver::find], but also returns the root of the data directory, which is
+    /// [Texture::root] once the texture is loaded.
+    pub fn find_with_root(&self, name: &str) -> Option<(&Path, &str)> {
+        self.find_in_roots(name)
+            .map(|(root, path)| (root.path.as_path(), path))
+    }
+
+    fn searched_roots(&self) -> impl Iterator<Item = &Root> {
+        self.roots.iter().filter(|root| root.searched)
+    }
+
+    fn find_in_roots(&self, name: &str) -> Option<(&Root, &str)> {
+        let name = file_name(name);
+        let stem = name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(name);
+
+        let candidates = std::iter::once(name.to_string()).chain(
+            ["bmp", "jpg", "jpeg"]
+                .iter()
+                .map(|ext| format!("{}.{}", stem, ext)),
+        );
+
+        for candidate in candidates {
+            for root in self.searched_roots() {
+                let Some(path) = root
+                    .files
+                    .get(GameStr::new(&candidate))
+                    .and_then(|paths| paths.first())
+                else {
+                    continue;
+                };
+
+                if has_extension(path, &["raw"]) {
+                    continue;
+                }
+
+                return Some((root, path));
+            }
+        }
+
+        None
+    }
+}
+
+impl Root {
+    fn load(&self, path: &str, keying: TextureKeying) -> Result<Texture, TextureError> {
+        let mut file = self.data_dir.open(path)?;
+        let archived = matches!(file, File::Archived { .. });
+
+        let (image, alpha) = if has_extension(path, &["jpg", "jpeg"]) {
+            // Archived files can only be read in exact sizes.
+            let mut data = vec![0_u8; file.size()? as usize];
+            std::io::Read::read_exact(&mut file, &mut data)?;
+            let image = image::load_from_memory_with_format(&data, image::ImageFormat::Jpeg)?;
+            (image.to_rgba8(), TextureAlpha::Opaque)
+        } else {
+            let bmp = load_bmp_file_with_color_key(&mut file, keying.color_key)?;
+
+            match self.sibling_raw(path) {
+                Some(raw_path) if keying.raw_alpha => {
+                    let mut raw_file = self.data_dir.open(raw_path)?;
+                    let raw = load_raw_file(&mut raw_file, bmp.width(), bmp.height())?;
+                    (
+                        combine_bmp_and_raw(&bmp, &raw),
+                        TextureAlpha::Raw(raw_path.to_string()),
+                    )
+                }
+                _ if keying.color_key.is_some() => (bmp, TextureAlpha::ColorKey),
+                _ => (bmp, TextureAlpha::Opaque),
+            }
+        };
+
+        Ok(Texture {
+            image,
+            root: self.path.clone(),
+            path: path.to_string(),
+            archived,
+            alpha,
+        })
+    }
+
+    /// Find the .raw file in the same directory as the image.
+    fn sibling_raw(&self, path: &str) -> Option<&str> {
+        let (dir, name) = path.rsplit_once('\\').unwrap_or(("", path));
+        let stem = name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(name);
+        let raw_name = format!("{}.raw", stem);
+
+        self.files
+            .get(GameStr::new(&raw_name))?
+            .iter()
+            .find(|raw_path| {
+                let raw_dir = raw_path.rsplit_once('\\').map(|(dir, _)| dir).unwrap_or("");
+                raw_dir.eq_ignore_ascii_case(dir)
+            })
+            .map(String::as_str)
+    }
+}
+
+/// Returns true if the name of an image ends in `_ck`, which makes black pixels transparent.
+pub fn is_color_keyed(path: &str) -> bool {
+    file_name(path)
+        .rsplit_once('.')
+        .map(|(stem, _)| stem.to_ascii_lowercase().ends_with("_ck"))
+        .unwrap_or(false)
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(['\\', '/']).next().unwrap_or(path)
+}
+
+fn has_extension(path: &str, extensions: &[&str]) -> bool {
+    path.rsplit_once('.')
+        .map(|(_, ext)| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::images::{write_bmp_file, write_raw_file};
+
+    #[test]
+    fn resolve_textures() {
+        let root = std::env::temp_dir().join(format!("texture_resolver_{}", std::process::id()));
+        let textures = root.join("Textures");
+        std::fs::create_dir_all(&textures).unwrap();
+
+        let rgb = image::RgbImage::from_fn(2, 2, |x, _| image::Rgb([x as u8 * 255, 0, 0]));
+        let alpha = image::GrayImage::from_pixel(2, 2, image::Luma([128]));
+        for name in ["Wall.BMP", "fence_ck.bmp"] {
+            write_bmp_file(
+                &mut std::fs::File::create(textures.join(name)).unwrap(),
+                &rgb,
+            )
+            .unwrap();
+        }
+        write_raw_file(
+            &mut std::fs::File::create(textures.join("wall.raw")).unwrap(),
+            &alpha,
+        )
+        .unwrap();
+
+        let mut resolver = TextureResolver::default();
+        resolver.add_root(&root).unwrap();
+
+        let wall = resolver.resolve("WALL.bmp").unwrap().unwrap();
+        assert_eq!(wall.path, "Textures\\Wall.BMP");
+        assert_eq!(
+            wall.alpha,
+            TextureAlpha::Raw("Textures\\wall.raw".to_string())
+        );
+        assert_eq!(wall.image.get_pixel(1, 0).0, [255, 0, 0, 128]);
+
+        let fence = resolver.resolve("fence_ck").unwrap().unwrap();
+        assert_eq!(fence.alpha, TextureAlpha::ColorKey);
+        assert_eq!(fence.image.get_pixel(0, 0).0[3], 0);
+        assert_eq!(fence.image.get_pixel(1, 0).0[3], 255);
+
+        assert!(resolver.resolve("missing.bmp").unwrap().is_none());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn model_roots_are_replaced() {
+        let root = std::env::temp_dir().join(format!("model_roots_{}", std::process::id()));
+        let extra = root.join("extra");
+        for (dir, red) in [("first", 255), ("second", 128)] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            write_bmp_file(
+                &mut std::fs::File::create(root.join(dir).join("skin.bmp")).unwrap(),
+                &image::RgbImage::from_pixel(1, 1, image::Rgb([red, 0, 0])),
+            )
+            .unwrap();
+        }
+        std::fs::create_dir_all(&extra).unwrap();
+        write_bmp_file(
+            &mut std::fs::File::create(extra.join("extra.bmp")).unwrap(),
+            &image::RgbImage::new(1, 1),
+        )
+        .unwrap();
+
+        let mut resolver = TextureResolver::default();
+        resolver.add_root(&extra).unwrap();
+        let red = |resolver: &TextureResolver| {
+            resolver
+                .resolve("skin.bmp")
+                .unwrap()
+                .unwrap()
+                .image
+                .get_pixel(0, 0)
+                .0[0]
+        };
+
+        resolver
+            .add_model_roots(root.join("first").join("a.smf"))
+            .unwrap();
+        assert_eq!(red(&resolver), 255);
+        resolver
+            .add_model_roots(root.join("second").join("b.smf"))
+            .unwrap();
+        assert_eq!(red(&resolver), 128);
+        assert_eq!(
+            resolver.find_with_root("skin.bmp"),
+            Some((root.join("second").as_path(), "skin.bmp"))
+        );
+        resolver
+            .add_model_roots(root.join("first").join("c.smf"))
+            .unwrap();
+        assert_eq!(red(&resolver), 255);
+        // Roots added with add_root are always searched.
+        assert!(resolver.contains("extra.bmp"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+use clap::{Parser, ValueEnum};
+use image::{codecs::gif::GifEncoder, Delay, Frame, ImageResult, RgbaImage};
+use shadow_company_tools::{data_dir::DataDir, texture_resolver::TextureResolver};
+use shadow_company_tools_configs::{ImageDefs, SpriteSheet};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Animated GIF. Alpha is either fully transparent or fully opaque.
+    Gif,
+    /// Animated PNG, keeps the full alpha channel.
+    Apng,
+}
+
+#[derive(Parser)]
+struct Opts {
+    /// Path to the "<Shadow Company>\Data" directory.
+    data_dir: PathBuf,
+    /// Directory to write the animations to.
+    #[arg(short, long, default_value = "animations")]
+    output: PathBuf,
+    /// The format of the animations.
+    #[arg(short, long, value_enum, default_value_t = Format::Gif)]
+    format: Format,
+    /// Only export the animations with these names.
+    names: Vec<String>,
+}
+
+/// Used when an animation does not specify a frame rate.
+const DEFAULT_FRAME_DURATION: Duration = Duration::from_millis(100);
+
+fn main() {
+    let opts = Opts::parse();
+
+    let data_dir = DataDir::new(&opts.data_dir);
+    let image_defs = match ImageDefs::load(&data_dir) {
+        Ok(image_defs) => image_defs,
+        Err(err) => {
+            eprintln!("Could not read image_defs.txt: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(err) = std::fs::create_dir_all(&opts.output) {
+        eprintln!("Could not create {}. {}", opts.output.display(), err);
+        std::process::exit(1);
+    }
+
+    let mut resolver = TextureResolver::default();
+    if let Err(err) = resolver.add_root(&opts.data_dir) {
+        eprintln!("Could not read {}. {}", opts.data_dir.display(), err);
+        std::process::exit(1);
+    }
+
+    // ANIMSPRITE and ANIMSPRITE3D definitions can have the same name, so they are written to
+    // separate directories.
+    let sprites = image_defs
+        .anim_sprites
+        .iter()
+        .map(|sprite| ("animsprite", sprite))
+        .chain(
+            image_defs
+                .anim_sprite_3ds
+                .iter()
+                .map(|sprite| ("animsprite3d", sprite)),
+        )
+        .filter(|(_, sprite)| {
+            opts.names.is_empty()
+                || opts
+                    .names
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(&sprite.name))
+        });
+
+    for (kind, sprite) in sprites {
+        let sheet = match SpriteSheet::from_anim_sprite(&resolver, sprite) {
+            Ok(sheet) => sheet,
+            Err(err) => {
+                eprintln!("Could not load texture for {}. {}", sprite.name, err);
+                continue;
+            }
+        };
+
+        let sequence = sprite.frame_sequence();
+        if sequence.is_empty() {
+            eprintln!("{} has no frames.", sprite.name);
+            continue;
+        }
+
+        let frames = sheet.animation(&sequence);
+        let duration = sprite.frame_duration().unwrap_or(DEFAULT_FRAME_DURATION);
+
+        let dir = opts.output.join(kind);
+        if let Err(err) = std::fs::create_dir_all(&dir) {
+            eprintln!("Could not create {}. {}", dir.display(), err);
+            continue;
+        }
+        // Names can contain dots, so the extension is appended instead of replaced.
+        let path = dir.join(format!(
+            "{}.{}",
+            sprite.name,
+            match opts.format {
+                Format::Gif => "gif",
+                Format::Apng => "png",
+            }
+        ));
+        let result = match opts.format {
+            Format::Gif => write_gif(&path, &frames, duration),
+            Format::Apng => write_apng(&path, &frames, duration),
+        };
+
+        match result {
+            Ok(()) => println!(
+                "Generated {} ({} frames, {} ms per frame)",
+                path.display(),
+                frames.len(),
+                duration.as_millis()
+            ),
+            Err(err) => eprintln!("Could not write {}. {}", path.display(), err),
+        }
+    }
+}
+
+fn write_gif(path: &Path, frames: &[RgbaImage], duration: Duration) -> ImageResult<()> {
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut encoder = GifEncoder::new(file);
+    encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+
+    let delay = Delay::from_saturating_duration(duration);
+    encoder.encode_frames(
+        frames
+            .iter()
+            .map(|frame| Frame::from_parts(frame.clone(), 0, 0, delay)),
+    )
+}
+
+fn write_apng(path: &Path, frames: &[RgbaImage], duration: Duration) -> ImageResult<()> {
+    let to_image_error = |err: png::EncodingError| {
+        image::ImageError::Encoding(image::error::EncodingError::new(
+            image::ImageFormat::Png.into(),
+            err,
+        ))
+    };
+
+    let (width, height) = frames[0].dimensions();
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .set_animated(frames.len() as u32, 0)
+        .map_err(to_image_error)?;
+    // The delay is stored as a fraction of a second.
+    encoder
+        .set_frame_delay(duration.as_millis().min(u16::MAX as u128) as u16, 1000)
+        .map_err(to_image_error)?;
+
+    let mut writer = encoder.write_header().map_err(to_image_error)?;
+    for frame in frames {
+        writer
+            .write_image_data(frame.as_raw())
+            .map_err(to_image_error)?;
+    }
+    writer.finish().map_err(to_image_error)
+}
@@ -0,0 +1,267 @@
+use clap::Parser;
+use shadow_company_tools::{
+    ase::{self, CONVERT_ASE},
+    fixed_string::FixedString,
+    mesh_processing::{self, ProcessArgs},
+    smf::{self, SmfVersion},
+    Quat, Vec2, Vec3,
+};
+use std::{collections::HashMap, io::Write, path::PathBuf};
+
+#[derive(Parser)]
+struct Opts {
+    /// Path to the .ase file to convert.
+    path: PathBuf,
+    /// Where to write the .smf file. Defaults to the path of the input with a .smf extension.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// The SMF version to write.
+    #[arg(long, value_enum, default_value = "1.1")]
+    smf_version: SmfVersion,
+    #[command(flatten)]
+    process: ProcessArgs,
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+fn main() {
+    let opts = Opts::parse();
+
+    let scene = match std::fs::File::open(&opts.path)
+        .map_err(ase::AseError::from)
+        .and_then(|mut file| ase::Scene::read(&mut file))
+    {
+        Ok(scene) => scene,
+        Err(err) => {
+            eprintln!("Could not read {}. {}", opts.path.display(), err);
+            std::process::exit(1);
+        }
+    };
+
+    let model = ase_to_smf(&scene, &opts);
+
+    let output = opts
+        .output
+        .clone()
+        .unwrap_or_else(|| opts.path.with_extension("smf"));
+    // Dropping a BufWriter ignores errors, so it is flushed explicitly.
+    let result = std::fs::File::create(&output).and_then(|file| {
+        let mut writer = std::io::BufWriter::new(file);
+        model.write(&mut writer)?;
+        writer.flush()
+    });
+    match result {
+        Ok(()) => println!(
+            "Generated {} ({} nodes, {} meshes)",
+            output.display(),
+            model.nodes.len(),
+            model.nodes.iter().map(|n| n.meshes.len()).sum::<usize>()
+        ),
+        Err(err) => {
+            eprintln!("Could not write {}. {}", output.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn ase_to_smf(scene: &ase::Scene, opts: &Opts) -> smf::Model {
+    let name = if scene.name.is_empty() {
+        opts.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    } else {
+        // The scene file name is usually the .max file.
+        scene
+            .name
+            .rsplit(['\\', '/'])
+            .next()
+            .map(|name| name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(name))
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let objects = scene
+        .objects
+        .iter()
+        .map(|object| (object.name.as_str(), object))
+        .collect::<HashMap<_, _>>();
+    let parent = |object: &ase::Object| {
+        object
+            .parent
+            .as_deref()
+            .and_then(|parent| objects.get(parent).copied())
+    };
+
+    // SMF models have a single root node.
+    let roots = scene
+        .objects
+        .iter()
+        .filter(|object| parent(object).is_none())
+        .count();
+
+    let mut nodes = vec![];
+    if roots != 1 {
+        nodes.push(smf::Node {
+            name: FixedString::from(name.as_str()),
+            parent_name: FixedString::from(smf::ROOT_PARENT),
+            tree_id: 0,
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            meshes: vec![],
+            bounding_boxes: vec![],
+            u0: 0,
+        });
+    }
+
+    for object in scene.objects.iter() {
+        let translation = object.transform.w_axis.truncate();
+        let (parent_name, parent_translation) = match parent(object) {
+            Some(parent) => (parent.name.as_str(), parent.transform.w_axis.truncate()),
+            None if roots == 1 => (smf::ROOT_PARENT, Vec3::ZERO),
+            None => (name.as_str(), Vec3::ZERO),
+        };
+        if opts.verbose {
+            println!("Node {} (parent: {})", object.name, parent_name);
+        }
+
+        let meshes = match object.mesh {
+            Some(ref mesh) => {
+                let material = object
+                    .material_ref
+                    .and_then(|index| scene.materials.get(index));
+                convert_mesh(mesh, material, &object.name, translation, opts)
+            }
+            None => vec![],
+        };
+
+        nodes.push(smf::Node {
+            name: FixedString::from(object.name.as_str()),
+            parent_name: FixedString::from(parent_name),
+            tree_id: nodes.len() as u32,
+            position: CONVERT_ASE.transform_vector3(translation - parent_translation),
+            // The vertices keep their world rotation, so the nodes are not rotated, like gltf2smf.
+            rotation: Quat::IDENTITY,
+            bounding_boxes: smf::BoundingBox::from_meshes(&meshes).into_iter().collect(),
+            meshes,
+            u0: 0,
+        });
+    }
+
+    smf::Model {
+        header: vec![],
+        version: opts.smf_version,
+        version_field: FixedString::default(),
+        name: FixedString::from(name.as_str()),
+        scale: Vec3::ONE,
+        u0: 1.0,
+        u1: 1,
+        nodes,
+    }
+}
+
+/// Split an ASE mesh into an SMF mesh for every material used by its faces. ASE faces index the
+/// positions, texture coordinates and normals separately, so every unique combination becomes an
+/// SMF vertex. The vertices are made relative to `translation`, the position of the node.
+fn convert_mesh(
+    mesh: &ase::Mesh,
+    material: Option<&ase::Material>,
+    object_name: &str,
+    translation: Vec3,
+    opts: &Opts,
+) -> Vec<smf::Mesh> {
+    let mut groups: Vec<(&ase::Material, Vec<usize>)> = vec![];
+    let default_material = ase::Material {
+        name: object_name.to_string(),
+        ..Default::default()
+    };
+    for (index, face) in mesh.faces.iter().enumerate() {
+        let material = material
+            .map(|material| material.for_material_id(face.material_id))
+            .unwrap_or(&default_material);
+        match groups.iter_mut().find(|(m, _)| std::ptr::eq(*m, material)) {
+            Some((_, faces)) => faces.push(index),
+            None => groups.push((material, vec![index])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(material, faces)| {
+            let mut vertices = vec![];
+            let mut indices = HashMap::new();
+            let has_normals = !mesh.normals.is_empty();
+
+            let smf_faces = faces
+                .iter()
+                .enumerate()
+                .map(|(face_index, &index)| {
+                    let face = &mesh.faces[index];
+                    let tex_face = mesh.tex_faces.get(index);
+                    let normals = mesh.normals.get(index);
+
+                    let corners = [0, 1, 2].map(|corner| {
+                        let position = face.indices[corner];
+                        let tex_index = tex_face.map(|tex_face| tex_face[corner]);
+                        let normal = normals.map(|normals| normals[corner]).unwrap_or(Vec3::ZERO);
+
+                        let key = (position, tex_index, normal.to_array().map(f32::to_bits));
+                        *indices.entry(key).or_insert_with(|| {
+                            let tex_vertex = tex_index
+                                .and_then(|i| mesh.tex_vertices.get(i as usize))
+                                .copied()
+                                .unwrap_or(Vec3::ZERO);
+                            let world = mesh
+                                .vertices
+                                .get(position as usize)
+                                .copied()
+                                .unwrap_or(Vec3::ZERO);
+                            vertices.push(smf::Vertex {
+                                index: vertices.len() as u32,
+                                position: CONVERT_ASE.transform_vector3(world - translation),
+                                u1: 0xFFFF_FFFF,
+                                u2: 0.0,
+                                // 3D Studio MAX has the origin of the texture in the bottom left
+                                // corner.
+                                tex_coord: Vec2::new(tex_vertex.x, 1.0 - tex_vertex.y),
+                                normal: -CONVERT_ASE.transform_vector3(normal),
+                            });
+                            vertices.len() as u32 - 1
+                        })
+                    });
+
+                    // See ase::CONVERT_ASE, the winding is reversed.
+                    smf::Face {
+                        index: face_index as u32,
+                        indices: [corners[2], corners[1], corners[0]],
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let mut smf_mesh = smf::Mesh {
+                name: FixedString::from(material.name.as_str()),
+                texture_name: FixedString::from(texture_name(material).as_str()),
+                vertices,
+                faces: smf_faces,
+            };
+
+            let mut process_options = opts.process.process_options();
+            if !has_normals && process_options.smoothing_angle.is_none() {
+                process_options.smoothing_angle = Some(std::f32::consts::PI);
+            }
+            mesh_processing::process(&mut smf_mesh, &process_options);
+
+            smf_mesh
+        })
+        .collect()
+}
+
+/// SMF textures are referenced by their file name, without the directory 3D Studio MAX stores.
+fn texture_name(material: &ase::Material) -> String {
+    material
+        .bitmap
+        .as_deref()
+        .and_then(|bitmap| bitmap.rsplit(['\\', '/']).next())
+        .unwrap_or_default()
+        .to_string()
+}
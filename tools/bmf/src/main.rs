@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
 use clap::Parser;
+use shadow_company_tools::hash_dictionary::HashDictionary;
 
 fn motion_state_name(state: u32) -> &'static str {
     match state {
@@ -21,6 +22,10 @@ struct Opts {
     /// Print out each key frame and its data.
     #[clap(short = 'v', long = "verbose")]
     verbose: bool,
+    /// A hash dictionary file, or a data directory to collect names from, used to print the names
+    /// of known hashes.
+    #[clap(short = 'd', long = "dictionary")]
+    dictionary: Option<PathBuf>,
 }
 
 fn main() {
@@ -31,6 +36,17 @@ fn main() {
         std::process::exit(1)
     }
 
+    let dictionary = match opts.dictionary {
+        Some(ref path) => match HashDictionary::open(path) {
+            Ok(dictionary) => dictionary,
+            Err(err) => {
+                eprintln!("Could not load hash dictionary: {}", err);
+                std::process::exit(1)
+            }
+        },
+        None => HashDictionary::default(),
+    };
+
     let paths: Vec<PathBuf> = if opts.path.is_dir() {
         walkdir::WalkDir::new(&opts.path)
             .into_iter()
@@ -51,7 +67,11 @@ fn main() {
         let motion =
             shadow_company_tools::bmf::Motion::read(&mut file).expect("Could not read motion.");
 
-        println!("Motion: {} (0x{:08X})", motion.name, motion.hash);
+        println!(
+            "Motion: {} ({})",
+            motion.name,
+            dictionary.describe(motion.hash)
+        );
         let from_state_name = motion_state_name(motion.from_state);
         let to_state_name = motion_state_name(motion.to_state);
         println!(
@@ -51,6 +51,12 @@ fn main() {
         &mut std::fs::File::open(&opts.smf_path).expect("Could not open .smf file."),
     )
     .expect("Could not parse .smf file.");
+    if let Err(errors) = smf.validate_hierarchy() {
+        for error in errors {
+            eprintln!("{}", error);
+        }
+        std::process::exit(1);
+    }
 
     let bmf = bmf::Motion::read(
         &mut std::fs::File::open(&opts.bmf_path).expect("Could not open .bmf file."),
@@ -61,11 +67,10 @@ fn main() {
 
     let mut root = Root::default();
 
-    assert_eq!(smf.nodes[0].parent_name, "<root>");
     let root_index = add_node(
         &mut root,
-        smf.nodes.as_slice(),
-        0,
+        &smf,
+        smf.root().expect("The model has no root node."),
         &mut bone_lookup,
         opts.scale,
     );
@@ -75,7 +80,7 @@ fn main() {
     root.push(Scene {
         extensions: None,
         extras: None,
-        name: Some(smf.name.clone()),
+        name: Some(smf.name.to_string()),
         nodes: vec![root_index],
     });
 
@@ -88,18 +93,16 @@ fn main() {
 
 fn add_node(
     root: &mut Root,
-    nodes: &[smf::Node],
+    model: &smf::Model,
     node_index: usize,
     bone_lookup: &mut HashMap<u32, Index<Node>>,
     scale: f32,
 ) -> Index<Node> {
-    let node = &nodes[node_index];
+    let node = &model.nodes[node_index];
 
-    let children = nodes
-        .iter()
-        .enumerate()
-        .filter(|(_, n)| n.parent_name == node.name)
-        .map(|(i, _)| add_node(root, nodes, i, bone_lookup, scale))
+    let children = model
+        .children(node_index)
+        .map(|i| add_node(root, model, i, bone_lookup, scale))
         .collect();
 
     let translation = convert_position(node.position, scale).to_array();
@@ -110,7 +113,7 @@ fn add_node(
         extras: None,
         matrix: None,
         mesh: None,
-        name: Some(node.name.clone()),
+        name: Some(node.name.to_string()),
         rotation: None,
         scale: None,
         translation: Some(translation),
@@ -134,7 +137,7 @@ fn add_animation(
         extensions: None,
         extras: None,
         channels: vec![],
-        name: Some(motion.name.clone()),
+        name: Some(motion.name.to_string()),
         samplers: vec![],
     };
 
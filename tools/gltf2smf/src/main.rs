@@ -0,0 +1,644 @@
+use clap::Parser;
+use gltf_json::{self as json, validation::Checked::Valid};
+use serde::Deserialize;
+use shadow_company_tools::{
+    fixed_string::FixedString,
+    mesh_processing::{self, ProcessArgs},
+    smf::{self, SmfVersion, CONVERT, CONVERT_NORMAL},
+    Mat4, Quat, Vec2, Vec3,
+};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Parser)]
+struct Opts {
+    /// Path to the .gltf or .glb file to convert.
+    path: PathBuf,
+    /// Where to write the .smf file. Defaults to the path of the input with a .smf extension.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// The scale the model was exported with, see smf2gltf. Positions are divided by it.
+    #[arg(short, long, default_value = "1.0")]
+    scale: f32,
+    /// The SMF version to write. Defaults to the version stored by smf2gltf, or V1.1.
+    #[arg(long, value_enum)]
+    smf_version: Option<SmfVersion>,
+    /// Calculate the bounding boxes from the vertices, even if the nodes have the bounding boxes
+    /// stored by smf2gltf.
+    #[arg(long)]
+    recompute_bounding_boxes: bool,
+    #[command(flatten)]
+    process: ProcessArgs,
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+/// The fields smf2gltf stores in the extras of the scene.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct SceneExtras {
+    smf_version: Option<String>,
+    scale: Option<[f32; 3]>,
+}
+
+/// The fields smf2gltf stores in the extras of the nodes.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct NodeExtras {
+    tree_id: Option<u32>,
+    rotation: Option<[f32; 4]>,
+    bounding_boxes: Option<Vec<BoundingBoxExtras>>,
+    /// Set on the collision boxes exported by smf2gltf with --collision.
+    collision: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BoundingBoxExtras {
+    min: [f32; 3],
+    max: [f32; 3],
+    u0: f32,
+}
+
+fn main() {
+    let opts = Opts::parse();
+
+    let gltf = match Gltf::open(&opts.path) {
+        Ok(gltf) => gltf,
+        Err(err) => {
+            eprintln!("Could not read {}. {}", opts.path.display(), err);
+            std::process::exit(1);
+        }
+    };
+
+    let model = match gltf_to_smf(&gltf, &opts) {
+        Ok(model) => model,
+        Err(err) => {
+            eprintln!("Could not convert {}. {}", opts.path.display(), err);
+            std::process::exit(1);
+        }
+    };
+
+    let output = opts
+        .output
+        .clone()
+        .unwrap_or_else(|| opts.path.with_extension("smf"));
+    // Dropping a BufWriter ignores errors, so it is flushed explicitly.
+    let result = std::fs::File::create(&output).and_then(|file| {
+        let mut writer = std::io::BufWriter::new(file);
+        model.write(&mut writer)?;
+        writer.flush()
+    });
+    match result {
+        Ok(()) => println!(
+            "Generated {} ({} nodes, {} meshes)",
+            output.display(),
+            model.nodes.len(),
+            model.nodes.iter().map(|n| n.meshes.len()).sum::<usize>()
+        ),
+        Err(err) => {
+            eprintln!("Could not write {}. {}", output.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// A parsed glTF file with the contents of its buffers.
+struct Gltf {
+    root: json::Root,
+    buffers: Vec<Vec<u8>>,
+}
+
+impl Gltf {
+    fn open(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|err| err.to_string())?;
+
+        // .glb files have a JSON chunk, followed by an optional binary chunk.
+        let (json_data, mut bin) = if data.starts_with(b"glTF") {
+            let mut chunks = vec![];
+            let mut offset = 12;
+            while offset + 8 <= data.len() {
+                let length = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+                let start = offset + 8;
+                let end = (start + length as usize).min(data.len());
+                chunks.push(&data[start..end]);
+                offset = end;
+            }
+            let json_data = chunks.first().ok_or("Missing JSON chunk.")?.to_vec();
+            (json_data, chunks.get(1).map(|bin| bin.to_vec()))
+        } else {
+            (data, None)
+        };
+
+        let root = json::Root::from_slice(&json_data).map_err(|err| err.to_string())?;
+
+        let mut buffers = Vec::with_capacity(root.buffers.len());
+        for buffer in root.buffers.iter() {
+            let data = match buffer.uri {
+                None => bin.take().ok_or("Buffer without data.")?,
+                Some(ref uri) if uri.starts_with("data:") => {
+                    use base64::Engine;
+                    let (_, encoded) = uri.split_once(',').ok_or("Invalid data uri.")?;
+                    base64::engine::general_purpose::STANDARD
+                        .decode(encoded)
+                        .map_err(|err| err.to_string())?
+                }
+                Some(ref uri) => {
+                    let buffer_path = path.parent().unwrap_or(Path::new(".")).join(uri);
+                    std::fs::read(&buffer_path)
+                        .map_err(|err| format!("{}: {}", buffer_path.display(), err))?
+                }
+            };
+            buffers.push(data);
+        }
+
+        Ok(Self { root, buffers })
+    }
+
+    /// Read the elements of an accessor. Normalized integers are converted to 0.0..1.0.
+    fn read_values(&self, index: json::Index<json::Accessor>) -> Result<Vec<Vec<f64>>, String> {
+        use json::accessor::ComponentType;
+
+        let accessor = self.root.get(index).ok_or("Invalid accessor.")?;
+        let (Valid(component_type), Valid(type_)) = (&accessor.component_type, &accessor.type_)
+        else {
+            return Err("Invalid accessor type.".to_string());
+        };
+        let component_type = component_type.0;
+        let components = type_.multiplicity();
+        let component_size = component_type.size();
+
+        let count = accessor.count.0 as usize;
+        let Some(view_index) = accessor.buffer_view else {
+            return Ok(vec![vec![0.0; components]; count]);
+        };
+        let view = self.root.get(view_index).ok_or("Invalid buffer view.")?;
+        let buffer = self
+            .buffers
+            .get(view.buffer.value())
+            .ok_or("Invalid buffer.")?;
+
+        let stride = view
+            .byte_stride
+            .map(|stride| stride.0)
+            .unwrap_or(components * component_size);
+        let start = view.byte_offset.map(|o| o.0 as usize).unwrap_or(0)
+            + accessor.byte_offset.map(|o| o.0 as usize).unwrap_or(0);
+
+        let (max, signed) = match component_type {
+            ComponentType::I8 => (i8::MAX as f64, true),
+            ComponentType::U8 => (u8::MAX as f64, false),
+            ComponentType::I16 => (i16::MAX as f64, true),
+            ComponentType::U16 => (u16::MAX as f64, false),
+            ComponentType::U32 | ComponentType::F32 => (1.0, false),
+        };
+        let normalize = |value: f64| {
+            if !accessor.normalized {
+                value
+            } else if signed {
+                (value / max).max(-1.0)
+            } else {
+                value / max
+            }
+        };
+
+        (0..count)
+            .map(|i| {
+                (0..components)
+                    .map(|c| {
+                        let offset = start + i * stride + c * component_size;
+                        let bytes = buffer
+                            .get(offset..offset + component_size)
+                            .ok_or("Accessor out of bounds.")?;
+                        Ok(match component_type {
+                            ComponentType::F32 => {
+                                f32::from_le_bytes(bytes.try_into().unwrap()) as f64
+                            }
+                            ComponentType::U32 => {
+                                u32::from_le_bytes(bytes.try_into().unwrap()) as f64
+                            }
+                            ComponentType::U16 => {
+                                normalize(u16::from_le_bytes(bytes.try_into().unwrap()) as f64)
+                            }
+                            ComponentType::I16 => {
+                                normalize(i16::from_le_bytes(bytes.try_into().unwrap()) as f64)
+                            }
+                            ComponentType::U8 => normalize(bytes[0] as f64),
+                            ComponentType::I8 => normalize(bytes[0] as i8 as f64),
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn read_floats(&self, index: json::Index<json::Accessor>) -> Result<Vec<Vec<f32>>, String> {
+        Ok(self
+            .read_values(index)?
+            .into_iter()
+            .map(|v| v.into_iter().map(|c| c as f32).collect())
+            .collect())
+    }
+
+    fn read_indices(&self, index: json::Index<json::Accessor>) -> Result<Vec<u32>, String> {
+        Ok(self
+            .read_values(index)?
+            .into_iter()
+            .map(|v| v[0] as u32)
+            .collect())
+    }
+
+    /// The name of the texture used by a material. smf2gltf stores the original texture name as
+    /// the name of the material. Otherwise the name of the image is used, with a .bmp extension.
+    fn texture_name(&self, material: Option<json::Index<json::Material>>) -> String {
+        let Some(material) = material.and_then(|index| self.root.get(index)) else {
+            return String::new();
+        };
+
+        if let Some(ref name) = material.name {
+            if name.contains('.') {
+                return name.clone();
+            }
+        }
+
+        let image = material
+            .pbr_metallic_roughness
+            .base_color_texture
+            .as_ref()
+            .and_then(|info| self.root.get(info.index))
+            .and_then(|texture| self.root.get(texture.source));
+        let image_name = image.and_then(|image| {
+            image
+                .uri
+                .as_ref()
+                .filter(|uri| !uri.starts_with("data:"))
+                .or(image.name.as_ref())
+        });
+
+        let name = image_name.or(material.name.as_ref());
+        match name {
+            Some(name) => {
+                let file_name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+                let stem = file_name
+                    .rsplit_once('.')
+                    .map(|(stem, _)| stem)
+                    .unwrap_or(file_name);
+                format!("{}.bmp", stem)
+            }
+            None => String::new(),
+        }
+    }
+}
+
+fn node_matrix(node: &json::Node) -> Mat4 {
+    if let Some(matrix) = node.matrix {
+        return Mat4::from_cols_array(&matrix);
+    }
+    Mat4::from_scale_rotation_translation(
+        node.scale.map(Vec3::from).unwrap_or(Vec3::ONE),
+        node.rotation
+            .map(|r| Quat::from_array(r.0))
+            .unwrap_or(Quat::IDENTITY),
+        node.translation.map(Vec3::from).unwrap_or(Vec3::ZERO),
+    )
+}
+
+fn extras<T: Default + for<'a> Deserialize<'a>>(extras: &json::Extras) -> T {
+    extras
+        .as_ref()
+        .and_then(|raw| serde_json::from_str(raw.get()).ok())
+        .unwrap_or_default()
+}
+
+/// smf2gltf attaches the meshes of an SMF node to child nodes without children or a transform.
+fn is_mesh_node(node: &json::Node) -> bool {
+    node.mesh.is_some()
+        && node.children.as_ref().map(Vec::is_empty).unwrap_or(true)
+        && node_matrix(node) == Mat4::IDENTITY
+}
+
+/// The collision boxes exported by smf2gltf are only a preview of the bounding boxes, which are
+/// read from the extras of their node.
+fn is_collision_node(node: &json::Node) -> bool {
+    extras::<NodeExtras>(&node.extras).collision.is_some()
+}
+
+struct Converter<'a> {
+    gltf: &'a Gltf,
+    opts: &'a Opts,
+    /// Converts glTF positions to SMF positions.
+    to_smf: Mat4,
+    to_smf_normal: Mat4,
+    nodes: Vec<smf::Node>,
+}
+
+fn gltf_to_smf(gltf: &Gltf, opts: &Opts) -> Result<smf::Model, String> {
+    let scene_index = gltf
+        .root
+        .scene
+        .or_else(|| (!gltf.root.scenes.is_empty()).then(|| json::Index::new(0)));
+    let scene = scene_index.and_then(|index| gltf.root.get(index));
+
+    let roots = match scene {
+        Some(scene) => scene.nodes.clone(),
+        // Without a scene, use all the nodes that are not a child of another node.
+        None => {
+            let children = gltf
+                .root
+                .nodes
+                .iter()
+                .flat_map(|node| node.children.iter().flatten())
+                .map(|index| index.value())
+                .collect::<Vec<_>>();
+            (0..gltf.root.nodes.len())
+                .filter(|index| !children.contains(index))
+                .map(|index| json::Index::new(index as u32))
+                .collect()
+        }
+    };
+
+    let scene_extras: SceneExtras = scene.map(|s| extras(&s.extras)).unwrap_or_default();
+    let name = scene
+        .and_then(|scene| scene.name.clone())
+        .or_else(|| {
+            opts.path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+        })
+        .unwrap_or_default();
+
+    let version = match opts.smf_version {
+        Some(version) => version,
+        None => match scene_extras.smf_version.as_deref() {
+            Some("SMF V1.0") => SmfVersion::V1_0,
+            _ => SmfVersion::V1_1,
+        },
+    };
+
+    let to_smf = Mat4::from_scale(Vec3::splat(1.0 / opts.scale)) * CONVERT.inverse();
+    let mut converter = Converter {
+        gltf,
+        opts,
+        to_smf,
+        to_smf_normal: CONVERT_NORMAL.inverse(),
+        nodes: vec![],
+    };
+
+    // SMF models have a single root node.
+    let is_smf_node = |index: &json::Index<json::Node>| {
+        gltf.root
+            .get(*index)
+            .map(|node| !is_mesh_node(node))
+            .unwrap_or(false)
+    };
+    match roots.as_slice() {
+        [root] if is_smf_node(root) => {
+            converter.add_node(*root, "<root>", Mat4::IDENTITY)?;
+        }
+        _ => {
+            converter.nodes.push(smf::Node {
+                name: FixedString::from(name.as_str()),
+                parent_name: FixedString::from("<root>"),
+                tree_id: 0,
+                position: Vec3::ZERO,
+                rotation: Quat::IDENTITY,
+                meshes: vec![],
+                bounding_boxes: vec![],
+                u0: 0,
+            });
+            for root in roots.iter() {
+                converter.add_node(*root, &name, Mat4::IDENTITY)?;
+            }
+        }
+    }
+
+    Ok(smf::Model {
+        header: vec![],
+        version,
+        version_field: FixedString::default(),
+        name: FixedString::from(name.as_str()),
+        scale: scene_extras.scale.map(Vec3::from).unwrap_or(Vec3::ONE),
+        u0: 1.0,
+        u1: 1,
+        nodes: converter.nodes,
+    })
+}
+
+impl Converter<'_> {
+    /// Add a glTF node and its children. SMF nodes only have a position, so the rotation and scale
+    /// of the glTF nodes are applied to the vertices.
+    fn add_node(
+        &mut self,
+        index: json::Index<json::Node>,
+        parent_name: &str,
+        parent_world: Mat4,
+    ) -> Result<(), String> {
+        let node = self.gltf.root.get(index).ok_or("Invalid node.")?;
+        let world = parent_world * node_matrix(node);
+
+        let name = node
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("node_{}", index.value()));
+        if self.opts.verbose {
+            println!("Node {} (parent: {})", name, parent_name);
+        }
+
+        let node_extras: NodeExtras = extras(&node.extras);
+
+        let (_, _, parent_translation) = parent_world.to_scale_rotation_translation();
+        let (_, _, translation) = world.to_scale_rotation_translation();
+        let position = self
+            .to_smf
+            .transform_vector3(translation - parent_translation);
+
+        // Vertices are stored relative to the position of the node.
+        let to_local = Mat4::from_translation(-translation);
+
+        let mut meshes = vec![];
+        if let Some(mesh) = node.mesh {
+            meshes.extend(self.convert_mesh(mesh, to_local * world)?);
+        }
+        let children = node.children.clone().unwrap_or_default();
+        for child_index in children.iter() {
+            let child = self.gltf.root.get(*child_index).ok_or("Invalid node.")?;
+            if is_mesh_node(child) {
+                meshes.extend(self.convert_mesh(child.mesh.unwrap(), to_local * world)?);
+            }
+        }
+
+        let bounding_boxes = match node_extras.bounding_boxes {
+            Some(bounding_boxes) if !self.opts.recompute_bounding_boxes => bounding_boxes
+                .into_iter()
+                .map(|b| smf::BoundingBox {
+                    max: Vec3::from(b.max),
+                    min: Vec3::from(b.min),
+                    u0: b.u0,
+                })
+                .collect(),
+            _ => smf::BoundingBox::from_meshes(&meshes).into_iter().collect(),
+        };
+
+        let tree_id = node_extras.tree_id.unwrap_or(self.nodes.len() as u32);
+        self.nodes.push(smf::Node {
+            name: FixedString::from(name.as_str()),
+            parent_name: FixedString::from(parent_name),
+            tree_id,
+            position,
+            rotation: node_extras
+                .rotation
+                .map(Quat::from_array)
+                .unwrap_or(Quat::IDENTITY),
+            meshes,
+            bounding_boxes,
+            u0: 0,
+        });
+
+        for child_index in children {
+            let child = self.gltf.root.get(child_index).ok_or("Invalid node.")?;
+            if !is_mesh_node(child) && !is_collision_node(child) {
+                self.add_node(child_index, &name, world)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert the primitives of a mesh to SMF meshes. `transform` is applied to the glTF
+    /// vertices before converting them to SMF coordinates.
+    fn convert_mesh(
+        &self,
+        index: json::Index<json::Mesh>,
+        transform: Mat4,
+    ) -> Result<Vec<smf::Mesh>, String> {
+        let mesh = self.gltf.root.get(index).ok_or("Invalid mesh.")?;
+        let mesh_name = mesh
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("mesh_{}", index.value()));
+
+        let normal_transform = transform.inverse().transpose();
+
+        let mut meshes = vec![];
+        for (primitive_index, primitive) in mesh.primitives.iter().enumerate() {
+            if primitive.mode != Valid(json::mesh::Mode::Triangles) {
+                eprintln!(
+                    "Warning: Skipping {} primitive {}, only triangles are supported.",
+                    mesh_name, primitive_index
+                );
+                continue;
+            }
+
+            let attribute = |semantic| primitive.attributes.get(&Valid(semantic)).copied();
+
+            let Some(positions) = attribute(json::mesh::Semantic::Positions) else {
+                continue;
+            };
+            let positions = self.gltf.read_floats(positions)?;
+            let normals = attribute(json::mesh::Semantic::Normals)
+                .map(|index| self.gltf.read_floats(index))
+                .transpose()?;
+            let tex_coords = attribute(json::mesh::Semantic::TexCoords(0))
+                .map(|index| self.gltf.read_floats(index))
+                .transpose()?;
+
+            let indices = match primitive.indices {
+                Some(indices) => self.gltf.read_indices(indices)?,
+                None => (0..positions.len() as u32).collect(),
+            };
+
+            // The attributes must have an element for every position.
+            let attribute_counts = [("NORMAL", &normals), ("TEXCOORD_0", &tex_coords)];
+            for (semantic, values) in attribute_counts {
+                if let Some(count) = values.as_ref().map(Vec::len) {
+                    if count < positions.len() {
+                        return Err(format!(
+                            "{} primitive {} has {} {} elements for {} positions.",
+                            mesh_name,
+                            primitive_index,
+                            count,
+                            semantic,
+                            positions.len()
+                        ));
+                    }
+                }
+            }
+            if let Some(index) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+                return Err(format!(
+                    "{} primitive {} uses vertex {}, but has {} vertices.",
+                    mesh_name,
+                    primitive_index,
+                    index,
+                    positions.len()
+                ));
+            }
+
+            let vertices = positions
+                .iter()
+                .enumerate()
+                .map(|(i, position)| {
+                    let position = transform.transform_point3(Vec3::from_slice(position));
+                    let normal = normals
+                        .as_ref()
+                        .map(|normals| {
+                            let normal = Vec3::from_slice(&normals[i]);
+                            normal_transform
+                                .transform_vector3(normal)
+                                .normalize_or_zero()
+                        })
+                        .unwrap_or(Vec3::ZERO);
+                    let tex_coord = tex_coords
+                        .as_ref()
+                        .map(|uvs| Vec2::from_slice(&uvs[i]))
+                        .unwrap_or(Vec2::ZERO);
+
+                    smf::Vertex {
+                        index: i as u32,
+                        position: self.to_smf.project_point3(position),
+                        u1: 0xFFFF_FFFF,
+                        u2: 0.0,
+                        tex_coord,
+                        // SMF normals point the other way, see smf2gltf.
+                        normal: -self.to_smf_normal.project_point3(normal),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            // smf2gltf reverses the winding of the faces, so reverse it back.
+            let faces = indices
+                .chunks_exact(3)
+                .enumerate()
+                .map(|(i, face)| smf::Face {
+                    index: i as u32,
+                    indices: [face[2], face[1], face[0]],
+                })
+                .collect::<Vec<_>>();
+
+            let name = if primitive_index == 0 {
+                mesh_name.clone()
+            } else {
+                format!("{}_{}", mesh_name, primitive_index)
+            };
+
+            let mut smf_mesh = smf::Mesh {
+                name: FixedString::from(name.as_str()),
+                texture_name: FixedString::from(
+                    self.gltf.texture_name(primitive.material).as_str(),
+                ),
+                vertices,
+                faces,
+            };
+
+            let mut process_options = self.opts.process.process_options();
+            if normals.is_none() && process_options.smoothing_angle.is_none() {
+                process_options.smoothing_angle = Some(std::f32::consts::PI);
+            }
+            mesh_processing::process(&mut smf_mesh, &process_options);
+
+            meshes.push(smf_mesh);
+        }
+
+        Ok(meshes)
+    }
+}
@@ -0,0 +1,108 @@
+//! Converts a model with smf2gltf and back with gltf2smf, and checks that nothing changed.
+
+use clap::Parser;
+use shadow_company_tools::{
+    fixed_string::FixedString,
+    smf::{self, SmfVersion},
+    texture_resolver::TextureResolver,
+    Quat, Vec2, Vec3,
+};
+use std::path::Path;
+
+fn test_model() -> smf::Model {
+    let vertex = |index: u32, position: Vec3, normal: Vec3| smf::Vertex {
+        tex_coord: Vec2::new(position.x, position.y),
+        ..smf::Vertex::new(index, position, normal)
+    };
+    let mesh = |name: &str, texture_name: &str| smf::Mesh {
+        name: FixedString::from(name),
+        texture_name: FixedString::from(texture_name),
+        vertices: vec![
+            vertex(0, Vec3::ZERO, Vec3::Z),
+            vertex(1, Vec3::X, Vec3::Z),
+            vertex(2, Vec3::Y, Vec3::new(0.0, 0.6, 0.8)),
+            vertex(3, Vec3::new(1.0, 1.0, 0.5), Vec3::X),
+        ],
+        faces: vec![
+            smf::Face {
+                index: 0,
+                indices: [0, 1, 2],
+            },
+            smf::Face {
+                index: 1,
+                indices: [2, 1, 3],
+            },
+        ],
+    };
+    let node = |name: &str, parent_name: &str, tree_id: u32| smf::Node {
+        tree_id,
+        ..smf::Node::new(name, parent_name)
+    };
+
+    let mut body = node("body", "root", 3);
+    body.position = Vec3::new(1.0, 2.0, 3.0);
+    body.meshes = vec![mesh("body", "body.bmp"), mesh("straps", "straps_ck.bmp")];
+    body.bounding_boxes = vec![smf::BoundingBox {
+        max: Vec3::new(1.0, 1.0, 0.5),
+        min: Vec3::ZERO,
+        u0: 0.75,
+    }];
+
+    let mut head = node("head", "body", 7);
+    head.position = Vec3::new(0.0, 0.0, 2.0);
+    head.rotation = Quat::from_rotation_z(0.5);
+    head.meshes = vec![mesh("head", "head.bmp")];
+
+    smf::Model {
+        version: SmfVersion::V1_0,
+        name: FixedString::from("round_trip"),
+        scale: Vec3::new(1.0, 2.0, 1.0),
+        ..smf::Model::new(vec![node("root", smf::ROOT_PARENT, 0), body, head])
+    }
+}
+
+fn run(program: &Path, args: &[&Path]) {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{} failed: {}",
+        program.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn smf_to_gltf_and_back() {
+    let dir = std::env::temp_dir().join(format!("gltf2smf_round_trip_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let smf_path = dir.join("model.smf");
+    let gltf_path = dir.join("model.gltf");
+    let output_path = dir.join("output.smf");
+
+    let model = test_model();
+    model
+        .write(&mut std::fs::File::create(&smf_path).unwrap())
+        .unwrap();
+
+    let opts = smf2gltf::Opts::parse_from([Path::new("smf2gltf"), &smf_path]);
+    smf2gltf::convert(&smf_path, &TextureResolver::default(), &opts).unwrap();
+    run(
+        Path::new(env!("CARGO_BIN_EXE_gltf2smf")),
+        &[&gltf_path, Path::new("--output"), &output_path],
+    );
+
+    let converted = smf::Model::read(&mut std::fs::File::open(&output_path).unwrap()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        smf::Model {
+            header: vec![],
+            version_field: FixedString::default(),
+            ..converted
+        },
+        model
+    );
+}
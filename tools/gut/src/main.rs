@@ -1,5 +1,5 @@
 use clap::{Parser, Subcommand};
-use shadow_company_tools::gut::GutFile;
+use shadow_company_tools::{gut::GutFile, hash_dictionary::HashDictionary};
 use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
@@ -14,6 +14,10 @@ enum Commands {
     List {
         /// path the .gut file you want to operate on
         path: PathBuf,
+        /// A hash dictionary file, or a data directory to collect names from, used to print the
+        /// names of known hashes.
+        #[arg(short, long)]
+        dictionary: Option<PathBuf>,
     },
     /// Extract the contents of a .gut file.
     Extract {
@@ -28,12 +32,17 @@ fn main() {
     let opts = Opts::parse();
 
     match opts.command {
-        Commands::List { path } => list(path),
+        Commands::List { path, dictionary } => list(path, dictionary),
         Commands::Extract { path, out_dir } => extract(path, out_dir),
     }
 }
 
-fn list(path: impl AsRef<Path>) {
+fn list(path: impl AsRef<Path>, dictionary: Option<PathBuf>) {
+    let dictionary = match dictionary {
+        Some(path) => HashDictionary::open(path).expect("Could not load hash dictionary."),
+        None => HashDictionary::default(),
+    };
+
     let mut file = std::fs::File::open(path.as_ref()).unwrap();
     let gut_file = GutFile::open(&mut file).unwrap();
 
@@ -41,7 +50,8 @@ fn list(path: impl AsRef<Path>) {
 
     for entry in gut_file.entries() {
         println!(
-            "{} ({} bytes{})",
+            "{} {} ({} bytes{})",
+            dictionary.describe(entry.hash),
             entry.name,
             entry.size,
             if !entry.is_plain_text {
@@ -1,16 +1,421 @@
-use clap::Parser;
-use shadow_company_tools::common::hash;
+use clap::{Args, Parser, Subcommand};
+use rayon::prelude::*;
+use shadow_company_tools::{
+    common::{decode_char, encode_char, hash},
+    fixed_string::{decode_windows_1252, encode_windows_1252},
+    hash_dictionary::HashDictionary,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Opts {
+    #[command(subcommand)]
+    command: Option<Commands>,
+    /// Strings to hash.
     inputs: Vec<String>,
+    /// Collect all the names from a data directory into a hash dictionary.
+    #[arg(long)]
+    collect: Option<PathBuf>,
+    /// Where to write the collected hash dictionary. Prints to stdout if not specified.
+    #[arg(short, long, requires = "collect")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Search the wordlists for names that hash to the target hashes.
+    Dictionary {
+        #[command(flatten)]
+        attack: AttackOpts,
+    },
+    /// Search for names built by joining wordlist segments with separators.
+    Pattern {
+        #[command(flatten)]
+        attack: AttackOpts,
+        /// The maximum number of wordlist segments in a name. The names with the most segments
+        /// are split in two halves and the first half is kept in memory, so keep this low for
+        /// large wordlists.
+        #[arg(short, long, default_value_t = 2)]
+        max_segments: usize,
+    },
+}
+
+#[derive(Args)]
+struct AttackOpts {
+    /// Hashes to search for, in hex.
+    targets: Vec<String>,
+    /// A file with hashes to search for, one per line. Only the first word of each line is used.
+    #[arg(short, long)]
+    targets_file: Option<PathBuf>,
+    /// Files with one word per line. Hash dictionary files are accepted as well.
+    #[arg(short, long, required = true)]
+    wordlist: Vec<PathBuf>,
+    /// Separators placed between segments and in front of numeric suffixes.
+    #[arg(short, long, value_delimiter = ',', default_value = "_")]
+    separators: Vec<String>,
+    /// Also try numeric suffixes from 0 up to and including this number, with and without zero
+    /// padding.
+    #[arg(short, long)]
+    numbers: Option<u32>,
+    /// Extensions to try at the end of each name, e.g. ".bmf".
+    #[arg(short, long, value_delimiter = ',')]
+    extensions: Vec<String>,
+    /// Keep searching after a name was found for a target.
+    #[arg(short, long)]
+    all: bool,
 }
 
 fn main() {
     let opts = Opts::parse();
 
-    opts.inputs
+    match opts.command {
+        Some(Commands::Dictionary { attack }) => run_attack(&attack, 1),
+        Some(Commands::Pattern {
+            attack,
+            max_segments,
+        }) => run_attack(&attack, max_segments),
+        None => {
+            opts.inputs
+                .iter()
+                .map(|i| (i, hash(&encode_windows_1252(i))))
+                .for_each(|(input, hash)| println!("{:08X}  {}", hash, input));
+
+            if let Some(data_dir) = opts.collect {
+                collect(data_dir, opts.output);
+            }
+        }
+    }
+}
+
+fn collect(data_dir: PathBuf, output: Option<PathBuf>) {
+    let mut dictionary = HashDictionary::default();
+    if let Err(err) = dictionary.add_data_dir(&data_dir) {
+        eprintln!("Could not collect names: {}", err);
+        std::process::exit(1);
+    }
+
+    let result = match output {
+        Some(path) => std::fs::File::create(path).and_then(|file| dictionary.save(file)),
+        None => dictionary.save(std::io::stdout().lock()),
+    };
+    if let Err(err) = result {
+        eprintln!("Could not write hash dictionary: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run_attack(opts: &AttackOpts, max_segments: usize) {
+    let targets = match read_targets(opts) {
+        Ok(targets) if !targets.is_empty() => targets,
+        Ok(_) => {
+            eprintln!("No target hashes specified.");
+            std::process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let words = match read_words(&opts.wordlist) {
+        Ok(words) => words,
+        Err(err) => {
+            eprintln!("Could not read wordlist: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let separators = opts
+        .separators
+        .iter()
+        .map(|s| encode_windows_1252(&s.to_ascii_lowercase()))
+        .collect::<Vec<_>>();
+    let suffixes = build_suffixes(opts.numbers, &separators, &opts.extensions);
+
+    let search = Search::new(targets, opts.all);
+    for segments in 1..=max_segments.max(1) {
+        if search.is_done() {
+            break;
+        }
+        search.run(&words, &separators, &suffixes, segments);
+    }
+
+    for (target, found) in search.targets.iter().zip(search.found.iter()) {
+        if !found.load(Ordering::Relaxed) {
+            eprintln!("Not found: {:08X}", target);
+        }
+    }
+}
+
+fn parse_hash(s: &str) -> Result<u32, String> {
+    let hex = s.trim_start_matches("0x").trim_start_matches("0X");
+    u32::from_str_radix(hex, 16).map_err(|_| format!("Invalid hash: {}", s))
+}
+
+fn read_targets(opts: &AttackOpts) -> Result<Vec<u32>, String> {
+    let mut targets = opts
+        .targets
+        .iter()
+        .map(|t| parse_hash(t))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(ref path) = opts.targets_file {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        for line in text.lines() {
+            let Some(first) = line.split_whitespace().next() else {
+                continue;
+            };
+            if first.starts_with(';') {
+                continue;
+            }
+            targets.push(parse_hash(first)?);
+        }
+    }
+
+    targets.sort_unstable();
+    targets.dedup();
+
+    Ok(targets)
+}
+
+fn read_words(paths: &[PathBuf]) -> std::io::Result<Vec<Vec<u8>>> {
+    let mut words = HashSet::new();
+    for path in paths {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let dictionary = HashDictionary::load(file)?;
+        words.extend(
+            dictionary
+                .iter()
+                .map(|(_, name)| encode_windows_1252(&name.to_ascii_lowercase())),
+        );
+    }
+
+    let mut words = words.into_iter().collect::<Vec<_>>();
+    words.sort_unstable();
+    Ok(words)
+}
+
+/// Build every combination of numeric suffix and extension that should be appended to a name.
+fn build_suffixes(
+    numbers: Option<u32>,
+    separators: &[Vec<u8>],
+    extensions: &[String],
+) -> Vec<Vec<u8>> {
+    let mut number_suffixes = vec![String::new()];
+    if let Some(max) = numbers {
+        let width = max.to_string().len();
+        let mut numbers = HashSet::new();
+        for n in 0..=max {
+            numbers.insert(n.to_string());
+            numbers.insert(format!("{:0width$}", n));
+        }
+
+        for number in numbers {
+            number_suffixes.push(number.clone());
+            for separator in separators {
+                number_suffixes.push(format!("{}{}", decode_windows_1252(separator), number));
+            }
+        }
+    }
+
+    let mut extensions = extensions
         .iter()
-        .map(|i| (i, hash(i.as_bytes())))
-        .for_each(|(input, hash)| println!("{:08X}  {}", hash, input));
+        .map(|e| e.to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    extensions.insert(0, String::new());
+
+    let mut suffixes = Vec::with_capacity(number_suffixes.len() * extensions.len());
+    for number in number_suffixes.iter() {
+        for extension in extensions.iter() {
+            suffixes.push(format!("{}{}", number, extension).into_bytes());
+        }
+    }
+    suffixes
+}
+
+/// The hash consists of two 16-bit lanes. Characters at even positions update the first lane and
+/// characters at odd positions update the second one.
+type Lanes = [u16; 2];
+
+fn lanes_of(hash: u32) -> Lanes {
+    [(hash >> 16) as u16, hash as u16]
+}
+
+/// Encode `s`, of which the first character is at position `start` in the full name.
+fn forward(mut lanes: Lanes, start: usize, s: &[u8]) -> Lanes {
+    for (i, ch) in s.iter().enumerate() {
+        encode_char(&mut lanes[(start + i) & 1], *ch);
+    }
+    lanes
+}
+
+/// Undo the encoding of `s`, of which the first character is at position `start` in the full
+/// name.
+fn backward(mut lanes: Lanes, start: usize, s: &[u8]) -> Lanes {
+    for (i, ch) in s.iter().enumerate().rev() {
+        decode_char(&mut lanes[(start + i) & 1], *ch);
+    }
+    lanes
+}
+
+struct Search {
+    targets: Vec<u32>,
+    found: Vec<AtomicBool>,
+    remaining: AtomicUsize,
+    all: bool,
+    reported: Mutex<HashSet<(u32, Vec<u8>)>>,
+}
+
+/// Names that make up the first half of a candidate, keyed by the parity of their length and the
+/// hash lanes after encoding them.
+type HeadMap = HashMap<(usize, Lanes), Vec<Vec<u8>>>;
+
+impl Search {
+    fn new(targets: Vec<u32>, all: bool) -> Self {
+        Self {
+            found: targets.iter().map(|_| AtomicBool::new(false)).collect(),
+            remaining: AtomicUsize::new(targets.len()),
+            targets,
+            all,
+            reported: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        !self.all && self.remaining.load(Ordering::Relaxed) == 0
+    }
+
+    fn is_active(&self, target_index: usize) -> bool {
+        self.all || !self.found[target_index].load(Ordering::Relaxed)
+    }
+
+    fn report(&self, target_index: usize, name: Vec<u8>) {
+        let target = self.targets[target_index];
+        debug_assert_eq!(hash(&name), target);
+
+        if !self.reported.lock().unwrap().insert((target, name.clone())) {
+            return;
+        }
+
+        if !self.found[target_index].swap(true, Ordering::Relaxed) {
+            self.remaining.fetch_sub(1, Ordering::Relaxed);
+        } else if !self.all {
+            return;
+        }
+
+        println!("{:08X}  {}", target, decode_windows_1252(&name));
+    }
+
+    /// Search all names with exactly `segments` words. The names are split into a head with the
+    /// first half of the words and a tail with the rest. The lanes after the head are stored in a
+    /// map, and for every tail the lanes are decoded backwards from each target. A match in the
+    /// middle means that the head followed by the tail hashes to the target.
+    fn run(
+        &self,
+        words: &[Vec<u8>],
+        separators: &[Vec<u8>],
+        suffixes: &[Vec<u8>],
+        segments: usize,
+    ) {
+        let head_segments = segments / 2;
+        let tail_segments = segments - head_segments;
+
+        let mut heads = HeadMap::new();
+        let mut add_head = |head: Vec<u8>| {
+            let lanes = forward([0xFFFF; 2], 0, &head);
+            heads.entry((head.len() & 1, lanes)).or_default().push(head);
+        };
+        if head_segments == 0 {
+            add_head(vec![]);
+        } else {
+            for_each_join(words, separators, head_segments, &mut vec![], &mut add_head);
+        }
+
+        let head_separators = if head_segments == 0 {
+            vec![vec![]]
+        } else {
+            separators.to_vec()
+        };
+
+        head_separators.par_iter().for_each(|head_separator| {
+            words.par_iter().for_each(|first| {
+                if self.is_done() {
+                    return;
+                }
+
+                let mut tail = head_separator.clone();
+                tail.extend_from_slice(first);
+                for_each_join(
+                    words,
+                    separators,
+                    tail_segments - 1,
+                    &mut tail,
+                    &mut |tail: Vec<u8>| self.check_tail(&heads, tail, suffixes),
+                );
+            });
+        });
+    }
+
+    fn check_tail(&self, heads: &HeadMap, mut tail: Vec<u8>, suffixes: &[Vec<u8>]) {
+        let len = tail.len();
+        for suffix in suffixes {
+            tail.truncate(len);
+            tail.extend_from_slice(suffix);
+
+            for (target_index, target) in self.targets.iter().enumerate() {
+                if !self.is_active(target_index) {
+                    continue;
+                }
+
+                for parity in 0..2 {
+                    let lanes = backward(lanes_of(*target), parity, &tail);
+                    let Some(matches) = heads.get(&(parity, lanes)) else {
+                        continue;
+                    };
+                    for head in matches {
+                        let mut name = head.clone();
+                        name.extend_from_slice(&tail);
+                        self.report(target_index, name);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Call `f` with `prefix` followed by every combination of `count` words, each preceded by a
+/// separator.
+fn for_each_join(
+    words: &[Vec<u8>],
+    separators: &[Vec<u8>],
+    count: usize,
+    prefix: &mut Vec<u8>,
+    f: &mut impl FnMut(Vec<u8>),
+) {
+    if count == 0 {
+        f(prefix.clone());
+        return;
+    }
+
+    let len = prefix.len();
+    // The first word of a head has no separator in front of it.
+    let joiners: &[Vec<u8>] = if len == 0 { &[vec![]] } else { separators };
+    for word in words {
+        for joiner in joiners {
+            prefix.truncate(len);
+            prefix.extend_from_slice(joiner);
+            prefix.extend_from_slice(word);
+            for_each_join(words, separators, count - 1, prefix, f);
+        }
+    }
+    prefix.truncate(len);
 }
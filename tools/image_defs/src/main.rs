@@ -33,7 +33,14 @@ fn main() {
 
     println!("3D sprites:");
     image_defs.sprite_3ds.iter().for_each(|sprite| {
-        println!("  - {}, {}", sprite.name, sprite.texture_name);
+        print!("  - {}, {}", sprite.name, sprite.texture_name);
+        if sprite.alpha != 0 {
+            print!(", alpha");
+        }
+        if let Some(color_key) = sprite.color_key() {
+            print!(", color key {:?}..={:?}", color_key.low, color_key.high);
+        }
+        println!();
     });
     println!();
 
@@ -0,0 +1,399 @@
+use clap::Parser;
+use image::{buffer::ConvertBuffer, ImageError, ImageResult, RgbaImage};
+use rayon::prelude::*;
+use serde::Serialize;
+use shadow_company_tools::{
+    data_dir::{DataDir, File},
+    images::{
+        combine_bmp_and_raw, detect_raw_size, load_bmp_file_with_color_key, load_pcx_file,
+        load_raw_file, ColorKeyRange, PcxImage, RawSizeSource,
+    },
+    io::PathExt,
+    texture_resolver::is_color_keyed,
+};
+use shadow_company_tools_configs::ImageDefs;
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Parser)]
+struct Opts {
+    /// Path to the "<Shadow Company>\Data" directory.
+    data_dir: PathBuf,
+    /// Directory to write the .png files and manifest.json to.
+    #[arg(short, long, default_value = "png")]
+    output: PathBuf,
+    /// Only convert the images with a path starting with one of these, e.g. "textures\interface".
+    filters: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Manifest {
+    images: Vec<ManifestImage>,
+    failures: Vec<ManifestFailure>,
+}
+
+#[derive(Serialize)]
+struct ManifestImage {
+    /// The path of the image inside the data directory.
+    source: String,
+    /// True if the image was stored inside a .gut archive.
+    archived: bool,
+    format: SourceFormat,
+    /// The .png file, relative to manifest.json.
+    output: String,
+    width: u32,
+    height: u32,
+    alpha: Alpha,
+}
+
+#[derive(Serialize)]
+struct ManifestFailure {
+    source: String,
+    error: String,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum SourceFormat {
+    Bmp,
+    Raw,
+    Pcx,
+    Jpeg,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Alpha {
+    /// The image is fully opaque.
+    Opaque,
+    /// The alpha channel was loaded from a .raw file.
+    Raw { path: String },
+    /// Pixels inside the range are transparent.
+    ColorKey { low: [u8; 3], high: [u8; 3] },
+    /// A standalone .raw file, stored as a grayscale image. `detected` is false if the size was
+    /// declared in image_defs.txt.
+    Grayscale { detected: bool },
+    /// An 8-bit .pcx file, converted with its palette.
+    Palette,
+}
+
+/// Everything needed to convert a single image, shared between the threads.
+struct Converter {
+    data_dir: DataDir,
+    image_defs: Option<ImageDefs>,
+    /// Maps the lowercase path of every file to its path in the data directory.
+    files: HashMap<String, String>,
+    output: PathBuf,
+}
+
+fn main() {
+    let opts = Opts::parse();
+
+    let data_dir = DataDir::new(&opts.data_dir);
+    let walk = match data_dir.walk() {
+        Ok(walk) => walk,
+        Err(err) => {
+            eprintln!("Could not read {}. {}", opts.data_dir.display(), err);
+            std::process::exit(1);
+        }
+    };
+    let paths = walk.files;
+
+    // Directories and archives that could not be read are reported as failures.
+    let walk_failures = walk
+        .errors
+        .into_iter()
+        .map(|(path, err)| {
+            eprintln!("Could not read {}. {}", path, err);
+            ManifestFailure {
+                source: path,
+                error: err.to_string(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let image_defs = match ImageDefs::load(&data_dir) {
+        Ok(image_defs) => Some(image_defs),
+        Err(err) => {
+            eprintln!(
+                "Could not read image_defs.txt, sizes will be detected. {}",
+                err
+            );
+            None
+        }
+    };
+
+    let converter = Converter {
+        data_dir,
+        image_defs,
+        files: paths
+            .iter()
+            .map(|path| (path.to_ascii_lowercase(), path.clone()))
+            .collect(),
+        output: opts.output.clone(),
+    };
+
+    // Files inside .gut archives can also exist as loose files, only convert them once.
+    let mut seen = HashSet::new();
+    let sources = paths
+        .iter()
+        .filter(|path| seen.insert(path.to_ascii_lowercase()))
+        .filter(|path| {
+            opts.filters.is_empty()
+                || opts.filters.iter().any(|filter| {
+                    let filter = filter.replace('/', "\\").to_ascii_lowercase();
+                    path.to_ascii_lowercase().starts_with(&filter)
+                })
+        })
+        .filter_map(|path| {
+            let format = source_format(path)?;
+            // .raw files next to a .bmp are the alpha channel of the .bmp.
+            if matches!(format, SourceFormat::Raw) && converter.sibling(path, "bmp").is_some() {
+                return None;
+            }
+            Some((path.as_str(), format))
+        })
+        .collect::<Vec<_>>();
+    let outputs = output_paths(&sources.iter().map(|(path, _)| *path).collect::<Vec<_>>());
+
+    println!("Converting {} images...", sources.len());
+
+    let results = sources
+        .par_iter()
+        .zip(outputs.par_iter())
+        .map(|(&(path, format), output)| {
+            converter.convert(path, format, output).map_err(|err| {
+                eprintln!("Could not convert {}. {}", path, err);
+                ManifestFailure {
+                    source: path.to_string(),
+                    error: err.to_string(),
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut manifest = Manifest {
+        images: vec![],
+        failures: walk_failures,
+    };
+    for result in results {
+        match result {
+            Ok(image) => manifest.images.push(image),
+            Err(failure) => manifest.failures.push(failure),
+        }
+    }
+
+    let manifest_path = opts.output.join("manifest.json");
+    let result = std::fs::create_dir_all(&opts.output)
+        .and_then(|_| std::fs::File::create(&manifest_path))
+        .and_then(|file| {
+            // Dropping a BufWriter ignores errors, so it is flushed explicitly.
+            let mut writer = std::io::BufWriter::new(file);
+            serde_json::to_writer_pretty(&mut writer, &manifest)?;
+            writer.flush()
+        });
+    if let Err(err) = result {
+        eprintln!("Could not write {}. {}", manifest_path.display(), err);
+    }
+
+    println!(
+        "Converted {} images, {} failed.",
+        manifest.images.len(),
+        manifest.failures.len()
+    );
+    if !manifest.failures.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+fn source_format(path: &str) -> Option<SourceFormat> {
+    let (_, ext) = path.rsplit_once('.')?;
+    Some(match ext.to_ascii_lowercase().as_str() {
+        "bmp" => SourceFormat::Bmp,
+        "raw" => SourceFormat::Raw,
+        "pcx" => SourceFormat::Pcx,
+        "jpg" | "jpeg" => SourceFormat::Jpeg,
+        _ => return None,
+    })
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('\\').next().unwrap_or(path)
+}
+
+/// The path of the .png file for each source, relative to the output directory. Sources that would
+/// get the same name, like foo.bmp and foo.jpg, keep their extension, e.g. foo.bmp.png.
+fn output_paths(sources: &[&str]) -> Vec<String> {
+    let stem = |path: &str| {
+        path.rsplit_once('.')
+            .map(|(stem, _)| stem)
+            .unwrap_or(path)
+            .to_ascii_lowercase()
+    };
+
+    let mut counts = HashMap::<String, usize>::new();
+    for path in sources {
+        *counts.entry(stem(path)).or_default() += 1;
+    }
+
+    sources
+        .iter()
+        .map(|path| {
+            let name = if counts[&stem(path)] > 1 {
+                format!("{}.png", path)
+            } else {
+                Path::new(path)
+                    .with_extension("png")
+                    .to_string_lossy()
+                    .to_string()
+            };
+            name.replace('\\', "/")
+        })
+        .collect()
+}
+
+impl Converter {
+    fn convert(
+        &self,
+        path: &str,
+        format: SourceFormat,
+        output: &str,
+    ) -> ImageResult<ManifestImage> {
+        let mut file = self.open(path)?;
+        let archived = matches!(file, File::Archived { .. });
+
+        let (image, alpha) = match format {
+            SourceFormat::Bmp => self.convert_bmp(path, &mut file)?,
+            SourceFormat::Raw => self.convert_raw(path, &mut file)?,
+            SourceFormat::Pcx => match load_pcx_file(&mut file)? {
+                pcx @ PcxImage::Indexed { .. } => (pcx.to_rgba(), Alpha::Palette),
+                pcx @ PcxImage::Rgb(_) => (pcx.to_rgba(), Alpha::Opaque),
+            },
+            SourceFormat::Jpeg => {
+                let data = read_all(&mut file)?;
+                let image = image::load_from_memory_with_format(&data, image::ImageFormat::Jpeg)?;
+                (image.to_rgba8(), Alpha::Opaque)
+            }
+        };
+
+        let png_path = self.output.join(Path::new(output).with_os_separators());
+        if let Some(parent) = png_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        image.save(&png_path)?;
+
+        Ok(ManifestImage {
+            source: path.to_string(),
+            archived,
+            format,
+            output: output.to_string(),
+            width: image.width(),
+            height: image.height(),
+            alpha,
+        })
+    }
+
+    fn convert_bmp(&self, path: &str, file: &mut File) -> ImageResult<(RgbaImage, Alpha)> {
+        let name = file_name(path);
+        let color_key = self
+            .image_defs
+            .as_ref()
+            .and_then(|image_defs| image_defs.texture_color_key(name))
+            .or_else(|| is_color_keyed(name).then_some(ColorKeyRange::BLACK));
+
+        let bmp = load_bmp_file_with_color_key(file, color_key)?;
+
+        if let Some(ColorKeyRange { low, high }) = color_key {
+            return Ok((bmp, Alpha::ColorKey { low, high }));
+        }
+
+        match self.sibling(path, "raw") {
+            Some(raw_path) => {
+                let mut raw_file = self.open(raw_path)?;
+                let raw = load_raw_file(&mut raw_file, bmp.width(), bmp.height())?;
+                Ok((
+                    combine_bmp_and_raw(&bmp, &raw),
+                    Alpha::Raw {
+                        path: raw_path.to_string(),
+                    },
+                ))
+            }
+            None => Ok((bmp, Alpha::Opaque)),
+        }
+    }
+
+    fn convert_raw(&self, path: &str, file: &mut File) -> ImageResult<(RgbaImage, Alpha)> {
+        let data = read_all(file)?;
+        let definitions = self
+            .image_defs
+            .as_ref()
+            .map(|image_defs| image_defs.raw_sizes(&self.data_dir, file_name(path)))
+            .unwrap_or_default();
+
+        let candidate = detect_raw_size(&data, &definitions)
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                ImageError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Could not detect the size of the image",
+                ))
+            })?;
+
+        let raw = load_raw_file(
+            &mut std::io::Cursor::new(data),
+            candidate.width,
+            candidate.height,
+        )?;
+        let detected = candidate.source != RawSizeSource::Definition;
+        Ok((raw.convert(), Alpha::Grayscale { detected }))
+    }
+
+    /// Find a file with the same path as `path`, but with another extension.
+    fn sibling(&self, path: &str, extension: &str) -> Option<&str> {
+        let stem = path.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(path);
+        self.files
+            .get(&format!("{}.{}", stem, extension).to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    fn open(&self, path: &str) -> ImageResult<File> {
+        self.data_dir.open(path).map_err(|err| {
+            ImageError::IoError(std::io::Error::new(std::io::ErrorKind::NotFound, err))
+        })
+    }
+}
+
+/// Archived files can only be read in exact sizes.
+fn read_all(file: &mut File) -> std::io::Result<Vec<u8>> {
+    let mut data = vec![0_u8; file.size()? as usize];
+    std::io::Read::read_exact(file, &mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_output_paths() {
+        let sources = [
+            "textures\\wall.bmp",
+            "textures\\Wall.jpg",
+            "textures\\floor.bmp",
+            "interface\\wall.bmp",
+        ];
+        assert_eq!(
+            output_paths(&sources),
+            [
+                "textures/wall.bmp.png",
+                "textures/Wall.jpg.png",
+                "textures/floor.png",
+                "interface/wall.png",
+            ]
+        );
+    }
+}
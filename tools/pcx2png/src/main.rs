@@ -0,0 +1,86 @@
+use clap::Parser;
+use shadow_company_tools::images::{load_pcx_file, PcxImage};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+struct Opts {
+    /// Path to a .pcx file or a directory containing .pcx files.
+    path: PathBuf,
+    /// Look up the colors in the palette instead of writing the raw palette indices. Only use this
+    /// for images that are meant to be viewed, data maps need the indices.
+    #[arg(short, long)]
+    color: bool,
+    /// Also write the palette of indexed images to a .pal.png file with one pixel per color.
+    #[arg(short, long)]
+    palette: bool,
+}
+
+fn main() {
+    let opts = Opts::parse();
+
+    if !opts.path.exists() {
+        eprintln!("Path does not exist: {}", opts.path.display());
+        std::process::exit(1);
+    }
+
+    let paths = if opts.path.is_dir() {
+        walkdir::WalkDir::new(&opts.path)
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|entry| entry.into_path())
+            .filter(|path| {
+                path.extension()
+                    .map(|ext| ext.eq_ignore_ascii_case("pcx"))
+                    .unwrap_or(false)
+            })
+            .collect()
+    } else {
+        vec![opts.path.clone()]
+    };
+
+    for path in paths {
+        if let Err(err) = convert(&path, &opts) {
+            eprintln!("Could not convert {}. {}", path.display(), err);
+        }
+    }
+}
+
+fn convert(path: &Path, opts: &Opts) -> image::ImageResult<()> {
+    let mut file = std::fs::File::open(path)?;
+    let pcx = load_pcx_file(&mut file)?;
+
+    let (width, height) = pcx.dimensions();
+    let png_path = path.with_extension("png");
+
+    match pcx {
+        PcxImage::Indexed { .. } if opts.color => pcx.to_rgba().save(&png_path)?,
+        PcxImage::Indexed {
+            ref indices,
+            ref palette,
+        } => {
+            indices.save(&png_path)?;
+
+            if opts.palette {
+                let palette_path = path.with_extension("pal.png");
+                image::RgbImage::from_fn(16, 16, |x, y| image::Rgb(palette[(y * 16 + x) as usize]))
+                    .save(&palette_path)?;
+                println!("Generated {}", palette_path.display());
+            }
+        }
+        PcxImage::Rgb(ref rgb) => rgb.save(&png_path)?,
+    }
+
+    println!(
+        "Generated {} ({}x{}, {})",
+        png_path.display(),
+        width,
+        height,
+        match pcx {
+            PcxImage::Indexed { .. } if opts.color => "indexed, colored",
+            PcxImage::Indexed { .. } => "indexed",
+            PcxImage::Rgb(_) => "rgb",
+        }
+    );
+
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use clap::{Parser, ValueEnum};
+use image::ImageResult;
+use shadow_company_tools::images::{
+    color_key_rgba, has_binary_alpha, split_rgba_to_bmp_and_raw, write_bmp_file, write_raw_file,
+};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Mode {
+    /// Write the colors to a .bmp file and the alpha channel to a .raw file. The .raw file is
+    /// skipped if the image is fully opaque, and an old one is removed.
+    Pair,
+    /// Write a single .bmp file where transparent pixels are black. Only works for images where
+    /// every pixel is either fully transparent or fully opaque. An old .raw file is removed.
+    ColorKey,
+    /// Write the image as a grayscale .raw file, like the font sheets.
+    Raw,
+}
+
+#[derive(Parser)]
+struct Opts {
+    /// Path to the .png file to convert.
+    path: PathBuf,
+    /// How to store the image.
+    #[arg(short, long, value_enum, default_value_t = Mode::Pair)]
+    mode: Mode,
+}
+
+fn main() {
+    let opts = Opts::parse();
+
+    if !opts.path.exists() {
+        eprintln!("File does not exist: {}", opts.path.display());
+        std::process::exit(1);
+    }
+
+    if let Err(err) = convert(&opts.path, opts.mode) {
+        eprintln!("Could not convert {}. {}", opts.path.display(), err);
+        std::process::exit(1);
+    }
+}
+
+fn convert(path: &Path, mode: Mode) -> ImageResult<()> {
+    let image = image::open(path)?;
+    let (width, height) = (image.width(), image.height());
+
+    match mode {
+        Mode::Raw => {
+            let raw_path = path.with_extension("raw");
+            write_raw_file(&mut std::fs::File::create(&raw_path)?, &image.to_luma8())?;
+            println!("Generated {} ({}x{})", raw_path.display(), width, height);
+        }
+
+        Mode::ColorKey => {
+            let rgba = image.to_rgba8();
+            let Some(rgb) = color_key_rgba(&rgba) else {
+                eprintln!("The image has partially transparent pixels and can not be color keyed.");
+                eprintln!("Use --mode pair to write the alpha channel to a .raw file instead.");
+                std::process::exit(1);
+            };
+
+            let bmp_path = path.with_extension("bmp");
+            write_bmp_file(&mut std::fs::File::create(&bmp_path)?, &rgb)?;
+            println!(
+                "Generated {} ({}x{}, color keyed)",
+                bmp_path.display(),
+                width,
+                height
+            );
+            remove_stale_raw(path)?;
+        }
+
+        Mode::Pair => {
+            let rgba = image.to_rgba8();
+            let (rgb, alpha) = split_rgba_to_bmp_and_raw(&rgba);
+
+            let bmp_path = path.with_extension("bmp");
+            write_bmp_file(&mut std::fs::File::create(&bmp_path)?, &rgb)?;
+            println!("Generated {} ({}x{})", bmp_path.display(), width, height);
+
+            if alpha.pixels().any(|p| p.0[0] != 255) {
+                let raw_path = path.with_extension("raw");
+                write_raw_file(&mut std::fs::File::create(&raw_path)?, &alpha)?;
+                println!("Generated {} ({}x{})", raw_path.display(), width, height);
+
+                if has_binary_alpha(&rgba) {
+                    println!("The alpha is binary, --mode color-key can store it in the .bmp.");
+                }
+            } else {
+                remove_stale_raw(path)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A .raw file next to a .bmp is used as its alpha channel, so one left over from an earlier
+/// conversion would be paired with the new .bmp.
+fn remove_stale_raw(path: &Path) -> std::io::Result<()> {
+    let raw_path = path.with_extension("raw");
+    if raw_path.exists() {
+        std::fs::remove_file(&raw_path)?;
+        println!("Removed {}", raw_path.display());
+    }
+    Ok(())
+}
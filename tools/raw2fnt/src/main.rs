@@ -0,0 +1,171 @@
+use clap::Parser;
+use image::Rgba;
+use shadow_company_tools::{
+    data_dir::DataDir,
+    images::{detect_raw_size, load_raw_file},
+};
+use shadow_company_tools_configs::{Font, FontGrid, ImageDefs};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+struct Opts {
+    /// Path to the .raw font sheet.
+    path: PathBuf,
+    /// Use the frames of this SPRITE3D definition in config\image_defs.txt as the glyphs.
+    #[arg(short, long, requires = "data_dir", conflicts_with = "grid")]
+    sprite: Option<String>,
+    /// Path to the "<Shadow Company>\Data" directory, used to look up the sprite definition.
+    #[arg(short, long)]
+    data_dir: Option<PathBuf>,
+    /// The size of the cells of a sheet with one glyph per cell, e.g. "16x16".
+    #[arg(short, long, value_parser = parse_size, required_unless_present = "sprite")]
+    grid: Option<(u32, u32)>,
+    /// The number of glyphs in the grid. Defaults to all the cells.
+    #[arg(long, requires = "grid")]
+    count: Option<u32>,
+    /// The size of the sheet, e.g. "256x256". Taken from the sprite definition or detected if not
+    /// specified.
+    #[arg(long, value_parser = parse_size)]
+    size: Option<(u32, u32)>,
+    /// The character of the first glyph.
+    #[arg(short, long, default_value_t = ' ')]
+    first_char: char,
+    /// Extra pixels between glyphs.
+    #[arg(long, default_value_t = 1)]
+    spacing: i32,
+    /// Render this text to a "<name>_sample.png" file.
+    #[arg(long)]
+    sample: Option<String>,
+    /// Directory to write the .fnt and .png files to.
+    #[arg(short, long, default_value = ".")]
+    output: PathBuf,
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("Expected <width>x<height>, got \"{}\"", value))?;
+    let parse = |v: &str| {
+        v.trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|v| *v > 0)
+            .ok_or_else(|| format!("Invalid size: \"{}\"", value))
+    };
+    Ok((parse(width)?, parse(height)?))
+}
+
+fn main() {
+    let opts = Opts::parse();
+
+    let data = match std::fs::read(&opts.path) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("Could not read {}. {}", opts.path.display(), err);
+            std::process::exit(1);
+        }
+    };
+
+    let sprite = opts.sprite.as_ref().map(|name| {
+        let data_dir = DataDir::new(opts.data_dir.as_ref().unwrap());
+        let image_defs = match ImageDefs::load(&data_dir) {
+            Ok(image_defs) => image_defs,
+            Err(err) => {
+                eprintln!("Could not read image_defs.txt: {}", err);
+                std::process::exit(1);
+            }
+        };
+        match image_defs
+            .sprite_3ds
+            .into_iter()
+            .find(|sprite| sprite.name.eq_ignore_ascii_case(name))
+        {
+            Some(sprite) => sprite,
+            None => {
+                eprintln!("SPRITE3D {} not found.", name);
+                std::process::exit(1);
+            }
+        }
+    });
+
+    let definitions = sprite
+        .iter()
+        .filter(|sprite| sprite.texture_width > 0 && sprite.texture_height > 0)
+        .map(|sprite| (sprite.texture_width as u32, sprite.texture_height as u32))
+        .collect::<Vec<_>>();
+    let (width, height) = match opts.size {
+        Some(size) => size,
+        None => match detect_raw_size(&data, &definitions).first() {
+            Some(candidate) => (candidate.width, candidate.height),
+            None => {
+                eprintln!("Could not detect the size of the font sheet, use --size.");
+                std::process::exit(1);
+            }
+        },
+    };
+
+    let sheet = match load_raw_file(&mut std::io::Cursor::new(data), width, height) {
+        Ok(sheet) => sheet,
+        Err(err) => {
+            eprintln!("Could not load {}. {}", opts.path.display(), err);
+            std::process::exit(1);
+        }
+    };
+
+    let font = match (&sprite, opts.grid) {
+        (Some(sprite), _) => Font::from_sprite_3d(sprite, sheet, opts.first_char, opts.spacing),
+        (None, Some((cell_width, cell_height))) => {
+            let name = opts
+                .path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let grid = FontGrid {
+                cell_width,
+                cell_height,
+                first_char: opts.first_char,
+                count: opts.count,
+            };
+            Font::from_grid(name, sheet, &grid, opts.spacing)
+        }
+        (None, None) => unreachable!("clap requires --grid without --sprite"),
+    };
+
+    println!(
+        "{}: {}x{} sheet, {} glyphs, line height {}, base {}",
+        font.name,
+        width,
+        height,
+        font.glyphs.len(),
+        font.line_height,
+        font.base
+    );
+
+    if let Err(err) = std::fs::create_dir_all(&opts.output) {
+        eprintln!("Could not create {}. {}", opts.output.display(), err);
+        std::process::exit(1);
+    }
+
+    let png_name = format!("{}.png", font.name);
+    let png_path = opts.output.join(&png_name);
+    match font.page_image().save(&png_path) {
+        Ok(()) => println!("Generated {}", png_path.display()),
+        Err(err) => eprintln!("Could not write {}. {}", png_path.display(), err),
+    }
+
+    let fnt_path = opts.output.join(format!("{}.fnt", font.name));
+    match std::fs::File::create(&fnt_path).and_then(|mut file| font.write_fnt(&mut file, &png_name))
+    {
+        Ok(()) => println!("Generated {}", fnt_path.display()),
+        Err(err) => eprintln!("Could not write {}. {}", fnt_path.display(), err),
+    }
+
+    if let Some(ref sample) = opts.sample {
+        let sample_path = opts.output.join(format!("{}_sample.png", font.name));
+        let image = font.render_text(&sample.replace("\\n", "\n"), Rgba([255, 255, 255, 255]));
+        match image.save(&sample_path) {
+            Ok(()) => println!("Generated {}", sample_path.display()),
+            Err(err) => eprintln!("Could not write {}. {}", sample_path.display(), err),
+        }
+    }
+}
@@ -1,5 +1,11 @@
 use clap::Parser;
-use image::{buffer::ConvertBuffer, ImageResult, RgbaImage};
+use image::{
+    buffer::ConvertBuffer,
+    error::{ParameterError, ParameterErrorKind},
+    ImageError, ImageResult, RgbaImage,
+};
+use shadow_company_tools::{data_dir::DataDir, images::detect_raw_size};
+use shadow_company_tools_configs::ImageDefs;
 
 #[derive(Parser)]
 struct Opts {
@@ -7,8 +13,12 @@ struct Opts {
     path: std::path::PathBuf,
     /// The width of the .raw image. If the width is not specified, try to detect it.
     width: Option<u32>,
-    /// The height of the .raw image. If the width is not specified, try to detect it.
+    /// The height of the .raw image. If the height is not specified, try to detect it.
     height: Option<u32>,
+    /// Path to the "<Shadow Company>\Data" directory. Used to look up the size of the image in
+    /// config\image_defs.txt when detecting it.
+    #[arg(short, long)]
+    data_dir: Option<std::path::PathBuf>,
 }
 
 fn main() {
@@ -65,15 +75,72 @@ fn convert_to_png(opts: &Opts) -> ImageResult<RgbaImage> {
 
         shadow_company_tools::images::combine_bmp_and_raw(&bmp, &raw)
     } else {
-        let width = match opts.width {
-            Some(value) => value,
-            None => todo!(),
-        };
-        let height = match opts.height {
-            Some(value) => value,
-            None => todo!(),
+        let size = file.metadata()?.len();
+        let (width, height) = match (opts.width, opts.height) {
+            (Some(width), Some(height)) => (width, height),
+            (Some(width), None) => (width, other_side(size, width, "width")?),
+            (None, Some(height)) => (other_side(size, height, "height")?, height),
+            (None, None) => detect_size(opts)?,
         };
+        println!("Size: {}x{}", width, height);
 
         shadow_company_tools::images::load_raw_file(&mut file, width, height)?.convert()
     })
 }
+
+/// The other side of an image of `size` bytes, given one side. Fails if the side does not divide
+/// the size, instead of ignoring the side that was asked for.
+fn other_side(size: u64, side: u32, name: &str) -> ImageResult<u32> {
+    if side == 0 || !size.is_multiple_of(side as u64) {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::Generic(format!(
+                "The file size of {} bytes is not a multiple of the {} {}",
+                size, name, side
+            )),
+        )));
+    }
+    Ok((size / side as u64) as u32)
+}
+
+fn detect_size(opts: &Opts) -> ImageResult<(u32, u32)> {
+    let data = std::fs::read(&opts.path)?;
+
+    let definitions = match opts.data_dir {
+        Some(ref data_dir) => definition_sizes(data_dir, &opts.path),
+        None => vec![],
+    };
+
+    let candidates = detect_raw_size(&data, &definitions);
+    println!("Size candidates:");
+    for candidate in candidates.iter() {
+        println!(
+            "  {:4} x {:4}  {:?} (score: {:.3})",
+            candidate.width, candidate.height, candidate.source, candidate.score
+        );
+    }
+
+    candidates
+        .first()
+        .map(|candidate| (candidate.width, candidate.height))
+        .ok_or_else(|| {
+            ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            ))
+        })
+}
+
+/// Find the sizes declared for the image in config\image_defs.txt.
+fn definition_sizes(data_dir: &std::path::Path, path: &std::path::Path) -> Vec<(u32, u32)> {
+    let data_dir = DataDir::new(data_dir);
+
+    let image_defs = match ImageDefs::load(&data_dir) {
+        Ok(image_defs) => image_defs,
+        Err(err) => {
+            eprintln!("Could not read image_defs.txt: {}", err);
+            return vec![];
+        }
+    };
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    image_defs.raw_sizes(&data_dir, &name)
+}
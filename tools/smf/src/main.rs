@@ -1,37 +1,243 @@
-use clap::Parser;
-use shadow_company_tools::smf;
-use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use shadow_company_tools::{
+    lint::{self, Severity},
+    render::{self, Camera, RenderOptions, TextureCache},
+    smf,
+    texture_resolver::TextureResolver,
+};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Opts {
+    #[command(subcommand)]
+    command: Option<Commands>,
+    /// Without a command, the model trees are printed.
+    #[command(flatten)]
+    print: Option<PrintOpts>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    /// Print the model trees, the default.
+    Print(PrintOpts),
+    /// Render thumbnails of models to .png files.
+    Thumb(ThumbOpts),
+    /// Check models for problems that can crash the game. Exits with an error if any are found.
+    Lint(LintOpts),
+}
+
+#[derive(Debug, Args)]
+struct PrintOpts {
     /// Path to the .smf file you want to operate on.
     path: PathBuf,
     /// Print out mesh vertices, index and faces.
     #[arg(long, short)]
     print_mesh_details: bool,
+    /// Print histograms of the values of the unknown fields, instead of the model trees.
+    #[arg(long = "histograms", short = 'H')]
+    histograms: bool,
+    /// Check that the vertices of the meshes are inside the bounding boxes of their node, instead
+    /// of printing the model trees.
+    #[arg(long, short = 'b')]
+    check_bounding_boxes: bool,
+    /// How far vertices may be outside the bounding boxes, with --check-bounding-boxes.
+    #[arg(long, default_value = "0.001")]
+    tolerance: f32,
+}
+
+#[derive(Debug, Args)]
+struct ThumbOpts {
+    /// Path to a .smf file, or a directory to render all the .smf files in.
+    path: PathBuf,
+    /// Where to write the image. Defaults to the path of the input with a .png extension. For a
+    /// directory without --contact-sheet, the directory to write the images to, which defaults to
+    /// next to the models.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// An extra directory to search for images. The directory of the model and the "Data"
+    /// directory it is in are always searched.
+    #[arg(short, long)]
+    texture_path: Option<PathBuf>,
+    /// The width and height of a view, in pixels.
+    #[arg(short, long, default_value = "256")]
+    size: u32,
+    /// The angle of the camera around the model, in degrees.
+    #[arg(long, default_value = "30", allow_negative_numbers = true)]
+    yaw: f32,
+    /// The angle of the camera above the horizon, in degrees.
+    #[arg(long, default_value = "25", allow_negative_numbers = true)]
+    pitch: f32,
+    /// The vertical field of view, in degrees.
+    #[arg(long, default_value = "30")]
+    fov: f32,
+    /// The number of views around the model, rendered side by side.
+    #[arg(long, default_value = "1")]
+    views: usize,
+    /// Render all the models into a single image, in a grid.
+    #[arg(short, long)]
+    contact_sheet: bool,
+    /// The number of models in a row of the contact sheet.
+    #[arg(long, default_value = "8")]
+    columns: u32,
+    /// Leave the background transparent.
+    #[arg(long)]
+    transparent: bool,
+}
+
+#[derive(Debug, Args)]
+struct LintOpts {
+    /// Path to a .smf file, or a directory to check all the .smf files in.
+    path: PathBuf,
+    /// An extra directory to search for images. The directory of the model and the "Data"
+    /// directory it is in are always searched.
+    #[arg(short, long)]
+    texture_path: Option<PathBuf>,
+    /// Don't check that the textures of the meshes exist.
+    #[arg(long)]
+    skip_textures: bool,
+    /// Only print problems of at least this severity.
+    #[arg(short, long, value_enum, default_value = "warning")]
+    severity: LintSeverity,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum LintSeverity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl From<LintSeverity> for Severity {
+    fn from(value: LintSeverity) -> Self {
+        match value {
+            LintSeverity::Info => Severity::Info,
+            LintSeverity::Warning => Severity::Warning,
+            LintSeverity::Error => Severity::Error,
+        }
+    }
+}
+
+/// Counts how often each value occurs in the fields we don't know the meaning of.
+#[derive(Default)]
+struct Histograms {
+    fields: BTreeMap<&'static str, BTreeMap<String, usize>>,
+}
+
+impl Histograms {
+    /// The number of values printed for each field.
+    const MAX_VALUES: usize = 10;
+
+    fn add(&mut self, field: &'static str, value: impl ToString) {
+        *self
+            .fields
+            .entry(field)
+            .or_default()
+            .entry(value.to_string())
+            .or_default() += 1;
+    }
+
+    fn add_model(&mut self, model: &smf::Model) {
+        self.add("model.version", model.version.as_str());
+        self.add("model.u0", format!("{:?}", model.u0));
+        self.add("model.u1", model.u1);
+
+        for node in model.nodes.iter() {
+            if model.version == smf::SmfVersion::V1_1 {
+                self.add("node.u0", node.u0);
+            }
+
+            for vertex in node.meshes.iter().flat_map(|mesh| mesh.vertices.iter()) {
+                self.add("vertex.u1", format!("0x{:08X}", vertex.u1));
+                self.add("vertex.u2", format!("{:?}", vertex.u2));
+            }
+
+            for bounding_box in node.bounding_boxes.iter() {
+                self.add("bounding_box.u0", format!("{:?}", bounding_box.u0));
+                // Check if the value could be the radius of a bounding sphere.
+                let half_diagonal = (bounding_box.max - bounding_box.min).length() / 2.0;
+                let ratio = if half_diagonal > 0.0 {
+                    format!("{:.2}", bounding_box.u0 / half_diagonal)
+                } else {
+                    String::from("n/a")
+                };
+                self.add("bounding_box.u0 / half diagonal", ratio);
+            }
+        }
+    }
+
+    fn print(&self) {
+        for (field, values) in self.fields.iter() {
+            let total = values.values().sum::<usize>();
+            println!("{} ({} values, {} distinct)", field, total, values.len());
+
+            let mut values = values.iter().collect::<Vec<_>>();
+            values.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+            for (value, count) in values.iter().take(Self::MAX_VALUES) {
+                println!(
+                    "  {:>12}  {:8}  {:5.1}%",
+                    value,
+                    count,
+                    **count as f32 * 100.0 / total as f32
+                );
+            }
+            if values.len() > Self::MAX_VALUES {
+                println!("  ... {} more", values.len() - Self::MAX_VALUES);
+            }
+        }
+    }
 }
 
 fn main() -> std::io::Result<()> {
     let opts = Opts::parse();
 
-    let files = if opts.path.is_dir() {
-        walkdir::WalkDir::new(opts.path)
-            .into_iter()
-            .filter_map(|entry| {
-                let Ok(entry) = entry else {
-                    return None;
-                };
+    match (opts.command, opts.print) {
+        (Some(Commands::Print(opts)), _) | (None, Some(opts)) => print(opts),
+        (Some(Commands::Thumb(opts)), _) => {
+            thumb(opts);
+            Ok(())
+        }
+        (Some(Commands::Lint(opts)), _) => {
+            if !lint(opts) {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        (None, None) => unreachable!("clap requires the path without a command"),
+    }
+}
 
-                if entry.path().extension()? != "smf" {
-                    return None;
-                }
+/// The path itself, or all the .smf files in it if it is a directory.
+fn find_models(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
 
-                Some(entry.into_path())
-            })
-            .collect()
-    } else {
-        vec![opts.path]
-    };
+    let mut files = walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| {
+            let Ok(entry) = entry else {
+                return None;
+            };
+
+            if entry.path().extension()? != "smf" {
+                return None;
+            }
+
+            Some(entry.into_path())
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+fn print(opts: PrintOpts) -> std::io::Result<()> {
+    let files = find_models(&opts.path);
+
+    let mut histograms = Histograms::default();
 
     for file in files {
         let mut reader = std::fs::File::open(&file)?;
@@ -44,20 +250,35 @@ fn main() -> std::io::Result<()> {
             }
         };
 
+        if opts.histograms {
+            histograms.add_model(&model);
+            continue;
+        }
+
+        if opts.check_bounding_boxes {
+            check_bounding_boxes(&file, &model, opts.tolerance);
+            continue;
+        }
+
         let mut tree = ptree::TreeBuilder::new(file.display().to_string());
 
         tree.begin_child(format!(
-            "Model({}) | unknown: {:?}",
-            model.name, model.scale
+            "Model({}) {} | scale: {:?}, unknown: {:?}, {}",
+            model.name,
+            model.version.as_str(),
+            model.scale,
+            model.u0,
+            model.u1
         ));
 
         fn print_nodes(
-            nodes: &[smf::Node],
-            parent_name: &str,
+            model: &smf::Model,
+            indices: impl Iterator<Item = usize>,
             tree: &mut ptree::TreeBuilder,
             print_mesh_details: bool,
         ) {
-            for node in nodes.iter().filter(|node| node.parent_name == parent_name) {
+            for index in indices {
+                let node = &model.nodes[index];
                 let tree_id = if node.tree_id == u32::MAX {
                     String::from("n/a")
                 } else {
@@ -130,18 +351,245 @@ fn main() -> std::io::Result<()> {
                     }
                 }
 
-                print_nodes(nodes, &node.name, tree, print_mesh_details);
+                print_nodes(model, model.children(index), tree, print_mesh_details);
 
                 tree.end_child();
             }
         }
 
-        print_nodes(&model.nodes, "<root>", &mut tree, opts.print_mesh_details);
+        let roots = model
+            .depth_first()
+            .filter(|&(_, depth)| depth == 0)
+            .map(|(index, _)| index);
+        print_nodes(&model, roots, &mut tree, opts.print_mesh_details);
 
         tree.end_child();
 
         ptree::print_tree(&tree.build()).unwrap();
+
+        if let Err(errors) = model.validate_hierarchy() {
+            for error in errors {
+                eprintln!("Warning: {}", error);
+            }
+        }
+    }
+
+    if opts.histograms {
+        histograms.print();
     }
 
     Ok(())
 }
+
+/// Print the nodes with meshes that are not covered by their bounding boxes.
+fn check_bounding_boxes(file: &Path, model: &smf::Model, tolerance: f32) {
+    for node in model.nodes.iter().filter(|node| !node.meshes.is_empty()) {
+        if node.bounding_boxes.is_empty() {
+            println!(
+                "{}: Node({}) has no bounding boxes",
+                file.display(),
+                node.name
+            );
+            continue;
+        }
+
+        for outside in node.check_bounding_boxes(tolerance) {
+            let mesh = &node.meshes[outside.mesh];
+            println!(
+                "{}: Node({}) Mesh({}) {} of {} vertices are outside the bounding boxes, up to {}",
+                file.display(),
+                node.name,
+                mesh.name,
+                outside.vertices_outside,
+                mesh.vertices.len(),
+                outside.max_distance
+            );
+        }
+
+        if let Some(computed) = node.compute_bounding_box() {
+            let stored = node.bounding_boxes.iter().skip(1).fold(
+                (node.bounding_boxes[0].min, node.bounding_boxes[0].max),
+                |(min, max), b| (min.min(b.min), max.max(b.max)),
+            );
+            let slack = (computed.min - stored.0)
+                .abs()
+                .max((stored.1 - computed.max).abs())
+                .max_element();
+            if slack > tolerance {
+                println!(
+                    "{}: Node({}) bounding boxes min: {:?}, max: {:?}, vertices min: {:?}, max: {:?}",
+                    file.display(),
+                    node.name,
+                    stored.0,
+                    stored.1,
+                    computed.min,
+                    computed.max
+                );
+            }
+        }
+    }
+}
+
+/// Render a thumbnail of every model, or a contact sheet with all of them.
+fn thumb(opts: ThumbOpts) {
+    let files = find_models(&opts.path);
+
+    let mut resolver = TextureResolver::default();
+    if let Some(ref texture_path) = opts.texture_path {
+        if let Err(err) = resolver.add_root(texture_path) {
+            eprintln!("Could not read {}. {}", texture_path.display(), err);
+        }
+    }
+    let mut textures = TextureCache::default();
+
+    let options = RenderOptions {
+        width: opts.size,
+        height: opts.size,
+        background: if opts.transparent {
+            image::Rgba([0, 0, 0, 0])
+        } else {
+            RenderOptions::default().background
+        },
+        ..Default::default()
+    };
+    let cameras = Camera {
+        yaw: opts.yaw,
+        pitch: opts.pitch,
+        fov: opts.fov,
+    }
+    .orbit(opts.views.max(1));
+    let (cell_width, cell_height) = (opts.size * cameras.len() as u32, opts.size);
+
+    let columns = opts.columns.clamp(1, files.len().max(1) as u32);
+    let rows = (files.len() as u32).div_ceil(columns);
+    let mut sheet = opts.contact_sheet.then(|| {
+        image::RgbaImage::from_pixel(columns * cell_width, rows * cell_height, options.background)
+    });
+
+    for (index, file) in files.iter().enumerate() {
+        let model = match std::fs::File::open(file)
+            .and_then(|file| smf::Model::read(&mut std::io::BufReader::new(file)))
+        {
+            Ok(model) => model,
+            Err(err) => {
+                eprintln!("Could not read {}. {}", file.display(), err);
+                continue;
+            }
+        };
+
+        // Roots that were added before are not indexed again.
+        if let Err(err) = resolver.add_model_roots(file) {
+            eprintln!("Could not search for textures. {}", err);
+        }
+        for missing in textures.load(&model, &resolver) {
+            match missing.error {
+                Some(err) => eprintln!("Warning: Could not load image: {}. {}", missing.name, err),
+                None => eprintln!("Warning: Could not find image: {}", missing.name),
+            }
+        }
+
+        let mut image = image::RgbaImage::new(cell_width, cell_height);
+        for (i, camera) in cameras.iter().enumerate() {
+            let view = render::render(&model, camera, &textures, &options);
+            image::imageops::replace(&mut image, &view, (i as u32 * opts.size) as i64, 0);
+        }
+
+        if let Some(ref mut sheet) = sheet {
+            let (column, row) = (index as u32 % columns, index as u32 / columns);
+            image::imageops::replace(
+                sheet,
+                &image,
+                (column * cell_width) as i64,
+                (row * cell_height) as i64,
+            );
+            println!("{}, {}: {}", row, column, file.display());
+            continue;
+        }
+
+        let output = match opts.output {
+            Some(ref output) if opts.path.is_dir() => output
+                .join(file.strip_prefix(&opts.path).unwrap_or(file))
+                .with_extension("png"),
+            Some(ref output) => output.clone(),
+            None => file.with_extension("png"),
+        };
+        write_image(&image, &output);
+    }
+
+    if let Some(sheet) = sheet {
+        let output = opts
+            .output
+            .clone()
+            .unwrap_or_else(|| opts.path.with_extension("png"));
+        write_image(&sheet, &output);
+    }
+}
+
+fn write_image(image: &image::RgbaImage, path: &Path) {
+    let result = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => std::fs::create_dir_all(dir),
+        _ => Ok(()),
+    }
+    .map_err(image::ImageError::IoError)
+    .and_then(|()| image.save(path));
+
+    match result {
+        Ok(()) => println!("Generated {}", path.display()),
+        Err(err) => eprintln!("Could not write {}. {}", path.display(), err),
+    }
+}
+
+/// Print the problems of every model. Returns false if any model has errors.
+fn lint(opts: LintOpts) -> bool {
+    let files = find_models(&opts.path);
+    let min_severity = Severity::from(opts.severity);
+
+    let mut resolver = TextureResolver::default();
+    if let Some(ref texture_path) = opts.texture_path {
+        if let Err(err) = resolver.add_root(texture_path) {
+            eprintln!("Could not read {}. {}", texture_path.display(), err);
+        }
+    }
+
+    let mut counts = BTreeMap::<Severity, usize>::new();
+    for file in files.iter() {
+        let model = match std::fs::File::open(file)
+            .and_then(|file| smf::Model::read(&mut std::io::BufReader::new(file)))
+        {
+            Ok(model) => model,
+            Err(err) => {
+                println!("{}: error: Could not read model. {}", file.display(), err);
+                *counts.entry(Severity::Error).or_default() += 1;
+                continue;
+            }
+        };
+
+        let textures = if opts.skip_textures {
+            None
+        } else {
+            // Roots that were added before are not indexed again.
+            if let Err(err) = resolver.add_model_roots(file) {
+                eprintln!("Could not search for textures. {}", err);
+            }
+            Some(&resolver)
+        };
+
+        for diagnostic in lint::lint(&model, textures) {
+            *counts.entry(diagnostic.severity).or_default() += 1;
+            if diagnostic.severity >= min_severity {
+                println!("{}: {}", file.display(), diagnostic);
+            }
+        }
+    }
+
+    let count = |severity| counts.get(&severity).copied().unwrap_or_default();
+    println!(
+        "Checked {} files: {} errors, {} warnings, {} info",
+        files.len(),
+        count(Severity::Error),
+        count(Severity::Warning),
+        count(Severity::Info)
+    );
+
+    count(Severity::Error) == 0
+}
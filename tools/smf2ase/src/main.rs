@@ -0,0 +1,156 @@
+use clap::Parser;
+use shadow_company_tools::{
+    ase::{self, convert_rotation, CONVERT_ASE},
+    smf, Mat4, Vec2, Vec3,
+};
+use std::{io::Write, path::PathBuf};
+
+#[derive(Parser)]
+struct Opts {
+    /// Path to the .smf file to convert.
+    path: PathBuf,
+    /// Where to write the .ase file. Defaults to the path of the input with a .ase extension.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn main() {
+    let opts = Opts::parse();
+
+    let model = match std::fs::File::open(&opts.path)
+        .and_then(|file| smf::Model::read(&mut std::io::BufReader::new(file)))
+    {
+        Ok(model) => model,
+        Err(err) => {
+            eprintln!("Could not read {}. {}", opts.path.display(), err);
+            std::process::exit(1);
+        }
+    };
+
+    let scene = smf_to_ase(&model, &opts);
+
+    let output = opts
+        .output
+        .clone()
+        .unwrap_or_else(|| opts.path.with_extension("ase"));
+    // Dropping a BufWriter ignores errors, so it is flushed explicitly.
+    let result = std::fs::File::create(&output).and_then(|file| {
+        let mut writer = std::io::BufWriter::new(file);
+        scene.write(&mut writer)?;
+        writer.flush()
+    });
+    match result {
+        Ok(()) => println!(
+            "Generated {} ({} objects, {} materials)",
+            output.display(),
+            scene.objects.len(),
+            scene.materials.len()
+        ),
+        Err(err) => {
+            eprintln!("Could not write {}. {}", output.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn smf_to_ase(model: &smf::Model, opts: &Opts) -> ase::Scene {
+    let mut materials = vec![];
+    let objects = model
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| {
+            // The rotations are not applied, like the nodes mode of smf2gltf.
+            let world_position = model.world_position(index);
+            let material_ref = (!node.meshes.is_empty()).then(|| {
+                materials.push(node_material(node));
+                materials.len() - 1
+            });
+
+            let parent = node.parent_name.as_str();
+            ase::Object {
+                name: node.name.to_string(),
+                parent: (parent != smf::ROOT_PARENT).then(|| parent.to_string()),
+                // SMF nodes have no scale and the vertices are not rotated, the rotation is only
+                // stored so it can be read back.
+                transform: Mat4::from_rotation_translation(
+                    convert_rotation(node.rotation),
+                    CONVERT_ASE.transform_point3(world_position),
+                ),
+                mesh: (!node.meshes.is_empty()).then(|| node_mesh(node, world_position)),
+                material_ref,
+            }
+        })
+        .collect();
+
+    ase::Scene {
+        name: model.name.to_string(),
+        comment: Some(format!(
+            "Converted from {}",
+            opts.path
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default()
+        )),
+        materials,
+        objects,
+    }
+}
+
+/// Every mesh of the node becomes a sub material, so the mesh names and textures survive the round
+/// trip through ase2smf.
+fn node_material(node: &smf::Node) -> ase::Material {
+    let mesh_material = |mesh: &smf::Mesh| ase::Material {
+        name: mesh.name.to_string(),
+        bitmap: (!mesh.texture_name.as_str().is_empty()).then(|| mesh.texture_name.to_string()),
+        sub_materials: vec![],
+    };
+
+    match node.meshes.as_slice() {
+        [mesh] => mesh_material(mesh),
+        meshes => ase::Material {
+            name: node.name.to_string(),
+            bitmap: None,
+            sub_materials: meshes.iter().map(mesh_material).collect(),
+        },
+    }
+}
+
+/// Combine the meshes of a node into a single mesh, with the material id of the faces set to the
+/// index of their mesh.
+fn node_mesh(node: &smf::Node, world_position: Vec3) -> ase::Mesh {
+    let mut mesh = ase::Mesh::default();
+
+    for (material_id, smf_mesh) in node.meshes.iter().enumerate() {
+        let base = mesh.vertices.len() as u32;
+
+        for vertex in smf_mesh.vertices.iter() {
+            mesh.vertices
+                .push(CONVERT_ASE.transform_point3(world_position + vertex.position));
+            // 3D Studio MAX has the origin of the texture in the bottom left corner.
+            let Vec2 { x: u, y: v } = vertex.tex_coord;
+            mesh.tex_vertices.push(Vec3::new(u, 1.0 - v, 0.0));
+        }
+
+        for face in smf_mesh.faces.iter() {
+            let [i0, i1, i2] = face.indices;
+            let indices = [i2, i1, i0];
+            let corners = indices.map(|i| (base + i) as usize);
+
+            mesh.faces.push(ase::Face {
+                indices: corners.map(|i| i as u32),
+                material_id: material_id as u32,
+            });
+            mesh.tex_faces.push(corners.map(|i| i as u32));
+            mesh.normals.push(indices.map(|i| {
+                smf_mesh
+                    .vertices
+                    .get(i as usize)
+                    .map(|vertex| -CONVERT_ASE.transform_vector3(vertex.normal))
+                    .unwrap_or(Vec3::ZERO)
+            }));
+        }
+    }
+
+    mesh
+}
@@ -0,0 +1,887 @@
+//! Converts .smf models to glTF. The conversion is in a library so that the tests of other tools
+//! can use it.
+
+use clap::{Parser, ValueEnum};
+use gltf_json::{self as json, scene::UnitQuaternion};
+use image::ImageFormat;
+use json::{
+    material::PbrMetallicRoughness,
+    validation::{Checked::Valid, USize64},
+};
+use shadow_company_tools::{
+    smf::{self, CONVERT, CONVERT_NORMAL},
+    texture_resolver::{TextureAlpha, TextureResolver},
+    Mat4, Quat, Vec3,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+#[derive(Parser)]
+pub struct Opts {
+    /// Path to a .smf file or a directory containing .smf files.
+    pub path: PathBuf,
+    /// An extra directory to search for images. The directory of the model and the "Data"
+    /// directory it is in are always searched.
+    #[arg(short, long)]
+    pub texture_path: Option<PathBuf>,
+    /// Whether to embed images into the .gltf file.
+    #[arg(short, long)]
+    pub embed_images: bool,
+    #[arg(short, long, default_value = "1.0")]
+    pub scale: f32,
+    #[arg(short, long)]
+    pub verbose: bool,
+    /// How to export meshes: attach to nodes or export a skeleton with skinning.
+    #[arg(long, value_enum, default_value_t = NodeMode::Nodes)]
+    pub node_mode: NodeMode,
+    /// Apply SMF rotations to skeleton joints (enable with --skeleton-rotations).
+    #[arg(long, default_value_t = false)]
+    pub skeleton_rotations: bool,
+    /// Add the bounding boxes of the nodes as child nodes, scaled to the size of the box, so they
+    /// can be previewed as box empties. The box is also stored in the extras of the child.
+    #[arg(long)]
+    pub collision: bool,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum NodeMode {
+    Nodes,
+    Skeleton,
+}
+
+/// Convert a .smf file to a .gltf file next to it, with the textures found by `resolver`.
+pub fn convert(
+    path: impl AsRef<Path>,
+    resolver: &TextureResolver,
+    opts: &Opts,
+) -> std::io::Result<()> {
+    let from_path = path.as_ref().to_owned();
+    let to_path = from_path.with_extension("gltf");
+
+    let mut file = std::fs::File::open(&from_path)?;
+    let smf = smf::Model::read(&mut file)?;
+    if let Err(errors) = smf.validate_hierarchy() {
+        let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        return Err(std::io::Error::other(errors.join(" ")));
+    }
+
+    let gltf_json = smf_to_gltf_json(smf, &to_path, resolver, opts);
+
+    let writer = std::fs::File::create(&to_path)?;
+    json::serialize::to_writer_pretty(writer, &gltf_json)?;
+
+    // println!("Wrote to: {}", to_path.display());
+
+    Ok(())
+}
+
+struct VV {
+    position: [f32; 3],
+    _normal: [f32; 3],
+    _uv: [f32; 3],
+}
+
+struct NodeTransform {
+    local_matrix: Mat4,
+}
+
+fn smf_to_gltf_json(
+    scene: smf::Model,
+    to_path: impl AsRef<Path>,
+    resolver: &TextureResolver,
+    opts: &Opts,
+) -> json::Root {
+    let mut root = json::Root::default();
+
+    let mut root_index = None;
+    let mut node_indices = HashMap::new();
+
+    let mut material_indices = HashMap::new();
+
+    let skeleton_mode = matches!(opts.node_mode, NodeMode::Skeleton);
+    let use_joint_rotations = skeleton_mode && opts.skeleton_rotations;
+
+    let convert_position = |v: Vec3| {
+        let m = CONVERT * Mat4::from_scale(Vec3::splat(opts.scale));
+        m.project_point3(v)
+    };
+
+    let convert_rotation = |q: Quat| {
+        let rotation_z_to_y = Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+        let transformed_quaternion = rotation_z_to_y * q;
+        Quat::from_xyzw(
+            -transformed_quaternion.x,
+            transformed_quaternion.y,
+            transformed_quaternion.z,
+            transformed_quaternion.w,
+        )
+    };
+
+    let mut node_translations = Vec::with_capacity(scene.nodes.len());
+    let mut joint_rotations = Vec::new();
+    let mut parent_indices = Vec::with_capacity(scene.nodes.len());
+    for (index, smf_node) in scene.nodes.iter().enumerate() {
+        let translation = convert_position(smf_node.position);
+        node_translations.push(translation);
+
+        if skeleton_mode {
+            let joint_rotation = if use_joint_rotations {
+                convert_rotation(smf_node.rotation)
+            } else {
+                Quat::IDENTITY
+            };
+            joint_rotations.push(joint_rotation);
+        }
+
+        parent_indices.push(scene.parent(index));
+    }
+
+    let mut joint_translations = Vec::new();
+    let mut joint_transforms = Vec::new();
+    let mut mesh_transforms = Vec::new();
+    if skeleton_mode {
+        joint_translations = if use_joint_rotations {
+            compute_joint_translations(&node_translations, &joint_rotations, &parent_indices)
+        } else {
+            node_translations.clone()
+        };
+
+        joint_transforms.reserve(scene.nodes.len());
+        mesh_transforms.reserve(scene.nodes.len());
+        for index in 0..scene.nodes.len() {
+            let joint_rotation = joint_rotations[index];
+            let joint_translation = joint_translations[index];
+            let joint_local_matrix =
+                Mat4::from_rotation_translation(joint_rotation, joint_translation);
+            joint_transforms.push(NodeTransform {
+                local_matrix: joint_local_matrix,
+            });
+
+            let mesh_local_matrix = Mat4::from_translation(node_translations[index]);
+            mesh_transforms.push(NodeTransform {
+                local_matrix: mesh_local_matrix,
+            });
+        }
+    }
+
+    let joint_global_transforms = if skeleton_mode {
+        Some(compute_global_transforms(&joint_transforms, &parent_indices))
+    } else {
+        None
+    };
+
+    let mesh_global_transforms = if skeleton_mode {
+        Some(compute_global_transforms(&mesh_transforms, &parent_indices))
+    } else {
+        None
+    };
+
+    if skeleton_mode && scene.nodes.len() > u16::MAX as usize {
+        panic!("Too many joints for u16 joint indices.");
+    }
+
+    let mut joint_nodes = Vec::with_capacity(scene.nodes.len());
+    for (node_i, smf_node) in scene.nodes.iter().enumerate() {
+        let mut node = json::Node {
+            translation: Some(
+                if skeleton_mode {
+                    joint_translations[node_i]
+                } else {
+                    node_translations[node_i]
+                }
+                .to_array(),
+            ),
+            name: Some(smf_node.name.to_string()),
+            ..Default::default()
+        };
+
+        if skeleton_mode {
+            node.rotation = Some(UnitQuaternion(joint_rotations[node_i].to_array()));
+        }
+
+        // Keep the SMF fields that are not part of the glTF node, so gltf2smf can restore them.
+        node.extras = to_extras(&serde_json::json!({
+            "tree_id": smf_node.tree_id,
+            "rotation": smf_node.rotation.to_array(),
+            "bounding_boxes": smf_node
+                .bounding_boxes
+                .iter()
+                .map(|b| serde_json::json!({
+                    "min": b.min.to_array(),
+                    "max": b.max.to_array(),
+                    "u0": b.u0,
+                }))
+                .collect::<Vec<_>>(),
+        }));
+
+        let node_index = root.push(node);
+        node_indices.insert(smf_node.name.clone(), node_index);
+        joint_nodes.push(node_index);
+        if smf_node.parent_name == "<root>" {
+            root_index = Some(node_index);
+        }
+    }
+
+    for (node_i, parent_i) in parent_indices.iter().enumerate() {
+        let Some(parent_i) = *parent_i else {
+            continue;
+        };
+
+        let parent = &mut root.nodes[joint_nodes[parent_i].value()];
+        parent
+            .children
+            .get_or_insert_with(Vec::new)
+            .push(joint_nodes[node_i]);
+    }
+
+    if opts.collision {
+        for (node_i, smf_node) in scene.nodes.iter().enumerate() {
+            for (box_i, bounding_box) in smf_node.bounding_boxes.iter().enumerate() {
+                let half_size = CONVERT.transform_vector3(bounding_box.max - bounding_box.min)
+                    * opts.scale
+                    / 2.0;
+                let collision_index = root.push(json::Node {
+                    name: Some(format!("{}_collision_{}", smf_node.name, box_i)),
+                    translation: Some(convert_position(bounding_box.center()).to_array()),
+                    scale: Some(half_size.abs().to_array()),
+                    extras: to_extras(&serde_json::json!({
+                        "collision": "box",
+                        "min": bounding_box.min.to_array(),
+                        "max": bounding_box.max.to_array(),
+                        "u0": bounding_box.u0,
+                    })),
+                    ..Default::default()
+                });
+
+                let node = &mut root.nodes[joint_nodes[node_i].value()];
+                node.children
+                    .get_or_insert_with(Vec::new)
+                    .push(collision_index);
+            }
+        }
+    }
+
+    let root_index = root_index.expect("no root node found");
+
+    let skin_index = if skeleton_mode {
+        let joint_global_transforms = joint_global_transforms
+            .as_ref()
+            .expect("missing joint global transforms");
+        let inverse_bind_matrices = joint_global_transforms
+            .iter()
+            .map(|transform| transform.inverse().to_cols_array())
+            .collect::<Vec<_>>();
+        let inverse_bind_count = inverse_bind_matrices.len();
+
+        let buffer = create_buffer(inverse_bind_matrices);
+        let byte_length = buffer.byte_length;
+        let buffer = root.push(buffer);
+
+        let buffer_view = root.push(json::buffer::View {
+            buffer,
+            byte_length,
+            byte_offset: None,
+            byte_stride: None,
+            extensions: Default::default(),
+            extras: Default::default(),
+            name: None,
+            target: None,
+        });
+
+        let inverse_bind_matrices = root.push(json::Accessor {
+            buffer_view: Some(buffer_view),
+            byte_offset: Some(USize64(0)),
+            count: USize64::from(inverse_bind_count),
+            component_type: Valid(json::accessor::GenericComponentType(
+                json::accessor::ComponentType::F32,
+            )),
+            extensions: Default::default(),
+            extras: Default::default(),
+            type_: Valid(json::accessor::Type::Mat4),
+            min: None,
+            max: None,
+            name: None,
+            normalized: false,
+            sparse: None,
+        });
+
+        Some(root.push(json::Skin {
+            inverse_bind_matrices: Some(inverse_bind_matrices),
+            skeleton: Some(root_index),
+            joints: joint_nodes.clone(),
+            name: None,
+            extensions: Default::default(),
+            extras: Default::default(),
+        }))
+    } else {
+        None
+    };
+
+    let mut mesh_node_indices = Vec::new();
+
+    for (node_i, smf_node) in scene.nodes.iter().enumerate() {
+        let node_global_transform = if skeleton_mode {
+            Some(
+                mesh_global_transforms
+                    .as_ref()
+                    .expect("missing mesh global transforms")[node_i],
+            )
+        } else {
+            None
+        };
+        let joint_index = if skeleton_mode {
+            Some(u16::try_from(node_i).expect("Joint index exceeds u16."))
+        } else {
+            None
+        };
+
+        for smf_mesh in smf_node.meshes.iter() {
+            let smf_vertices = smf_mesh
+                .vertices
+                .iter()
+                .map(|v| {
+                    let mut position = convert_position(v.position);
+                    let mut normal = CONVERT_NORMAL.project_point3(-v.normal);
+
+                    if let Some(transform) = node_global_transform {
+                        position = transform.transform_point3(position);
+                        normal = transform.transform_vector3(normal).normalize();
+                    }
+
+                    VV {
+                        position: position.to_array(),
+                        _normal: normal.to_array(),
+                        _uv: [v.tex_coord.x, v.tex_coord.y, 0.0],
+                    }
+                })
+                .collect::<Vec<_>>();
+            let vertex_count = smf_vertices.len();
+
+            let (min, max) = bounding_coords(&smf_vertices);
+
+            let vertices_buffer_view = {
+                let buffer = create_buffer(smf_vertices);
+                let byte_length = buffer.byte_length;
+                let buffer = root.push(buffer);
+
+                root.push(json::buffer::View {
+                    buffer,
+                    byte_length,
+                    byte_offset: None,
+                    byte_stride: Some(json::buffer::Stride(std::mem::size_of::<VV>())),
+                    extensions: Default::default(),
+                    extras: Default::default(),
+                    name: None,
+                    target: Some(Valid(json::buffer::Target::ArrayBuffer)),
+                })
+            };
+
+            let positions = root.push(json::Accessor {
+                buffer_view: Some(vertices_buffer_view),
+                byte_offset: Some(USize64(0)),
+                count: USize64::from(vertex_count),
+                component_type: Valid(json::accessor::GenericComponentType(
+                    json::accessor::ComponentType::F32,
+                )),
+                extensions: Default::default(),
+                extras: Default::default(),
+                type_: Valid(json::accessor::Type::Vec3),
+                min: Some(json::Value::from(Vec::from(min))),
+                max: Some(json::Value::from(Vec::from(max))),
+                name: None,
+                normalized: false,
+                sparse: None,
+            });
+
+            let normals = root.push(json::Accessor {
+                buffer_view: Some(vertices_buffer_view),
+                byte_offset: Some(USize64::from(3 * std::mem::size_of::<f32>())),
+                count: USize64::from(vertex_count),
+                component_type: Valid(json::accessor::GenericComponentType(
+                    json::accessor::ComponentType::F32,
+                )),
+                extensions: Default::default(),
+                extras: Default::default(),
+                type_: Valid(json::accessor::Type::Vec3),
+                min: None,
+                max: None,
+                name: None,
+                normalized: false,
+                sparse: None,
+            });
+
+            let uvs = root.push(json::Accessor {
+                buffer_view: Some(vertices_buffer_view),
+                byte_offset: Some(USize64::from(6 * std::mem::size_of::<f32>())),
+                count: USize64::from(vertex_count),
+                component_type: Valid(json::accessor::GenericComponentType(
+                    json::accessor::ComponentType::F32,
+                )),
+                extensions: Default::default(),
+                extras: Default::default(),
+                type_: Valid(json::accessor::Type::Vec2),
+                min: None,
+                max: None,
+                name: None,
+                normalized: false,
+                sparse: None,
+            });
+
+            let (joints, weights) = if let Some(joint_index) = joint_index {
+                let joints_data = vec![[joint_index, 0, 0, 0]; vertex_count];
+                let joints_buffer = create_buffer(joints_data);
+                let joints_byte_length = joints_buffer.byte_length;
+                let joints_buffer = root.push(joints_buffer);
+                let joints_view = root.push(json::buffer::View {
+                    buffer: joints_buffer,
+                    byte_length: joints_byte_length,
+                    byte_offset: None,
+                    byte_stride: None,
+                    name: None,
+                    target: Some(Valid(json::buffer::Target::ArrayBuffer)),
+                    extensions: Default::default(),
+                    extras: Default::default(),
+                });
+
+                let joints_accessor = root.push(json::Accessor {
+                    buffer_view: Some(joints_view),
+                    byte_offset: Some(USize64(0)),
+                    count: USize64::from(vertex_count),
+                    component_type: Valid(json::accessor::GenericComponentType(
+                        json::accessor::ComponentType::U16,
+                    )),
+                    extensions: Default::default(),
+                    extras: Default::default(),
+                    type_: Valid(json::accessor::Type::Vec4),
+                    min: None,
+                    max: None,
+                    name: None,
+                    normalized: false,
+                    sparse: None,
+                });
+
+                let weights_data = vec![[1.0_f32, 0.0, 0.0, 0.0]; vertex_count];
+                let weights_buffer = create_buffer(weights_data);
+                let weights_byte_length = weights_buffer.byte_length;
+                let weights_buffer = root.push(weights_buffer);
+                let weights_view = root.push(json::buffer::View {
+                    buffer: weights_buffer,
+                    byte_length: weights_byte_length,
+                    byte_offset: None,
+                    byte_stride: None,
+                    name: None,
+                    target: Some(Valid(json::buffer::Target::ArrayBuffer)),
+                    extensions: Default::default(),
+                    extras: Default::default(),
+                });
+
+                let weights_accessor = root.push(json::Accessor {
+                    buffer_view: Some(weights_view),
+                    byte_offset: Some(USize64(0)),
+                    count: USize64::from(vertex_count),
+                    component_type: Valid(json::accessor::GenericComponentType(
+                        json::accessor::ComponentType::F32,
+                    )),
+                    extensions: Default::default(),
+                    extras: Default::default(),
+                    type_: Valid(json::accessor::Type::Vec4),
+                    min: None,
+                    max: None,
+                    name: None,
+                    normalized: false,
+                    sparse: None,
+                });
+
+                (Some(joints_accessor), Some(weights_accessor))
+            } else {
+                (None, None)
+            };
+
+            let indices = {
+                let indices = smf_mesh
+                    .faces
+                    .iter()
+                    .flat_map(|f| [f.indices[2], f.indices[1], f.indices[0]])
+                    .collect::<Vec<_>>();
+                let indices_count = indices.len();
+
+                let buffer = create_buffer(indices);
+                let byte_length = buffer.byte_length;
+                let buffer = root.push(buffer);
+
+                let buffer_view = root.push(json::buffer::View {
+                    buffer,
+                    byte_length,
+                    byte_offset: None,
+                    byte_stride: None, // No byte stride for indices.
+                    name: None,
+                    target: Some(Valid(json::buffer::Target::ElementArrayBuffer)),
+                    extensions: Default::default(),
+                    extras: Default::default(),
+                });
+
+                root.push(json::Accessor {
+                    buffer_view: Some(buffer_view),
+                    byte_offset: Some(USize64::from(0_u64)),
+                    count: USize64::from(indices_count),
+                    component_type: Valid(json::accessor::GenericComponentType(
+                        json::accessor::ComponentType::U32,
+                    )),
+                    extensions: Default::default(),
+                    extras: Default::default(),
+                    type_: Valid(json::accessor::Type::Scalar),
+                    min: None,
+                    max: None,
+                    name: None,
+                    normalized: false,
+                    sparse: None,
+                })
+            };
+
+            let material_i = if let Some(mat) = material_indices.get(&smf_mesh.texture_name) {
+                *mat
+            } else {
+                let (image_path, alpha_mode) = match resolver.resolve(&smf_mesh.texture_name) {
+                    Ok(Some(texture)) => {
+                        let uri = if opts.embed_images {
+                            image_to_buffer(&texture.image)
+                        } else {
+                            write_png_next_to(&texture.image, &texture.path, to_path.as_ref())
+                                .expect("Could not write image.")
+                        };
+                        let alpha_mode = match texture.alpha {
+                            TextureAlpha::Opaque => json::material::AlphaMode::Opaque,
+                            TextureAlpha::Raw(_) => json::material::AlphaMode::Blend,
+                            TextureAlpha::ColorKey => json::material::AlphaMode::Mask,
+                        };
+                        (uri, alpha_mode)
+                    }
+                    Ok(None) => {
+                        eprintln!("Warning: Could not find image: {}", smf_mesh.texture_name);
+                        (
+                            smf_mesh.texture_name.to_string(),
+                            json::material::AlphaMode::Opaque,
+                        )
+                    }
+                    Err(err) => {
+                        eprintln!(
+                            "Warning: Could not load image: {}. {}",
+                            smf_mesh.texture_name, err
+                        );
+                        (
+                            smf_mesh.texture_name.to_string(),
+                            json::material::AlphaMode::Opaque,
+                        )
+                    }
+                };
+
+                let image_i = root.push(json::Image {
+                    buffer_view: None,
+                    mime_type: None,
+                    name: Some(smf_mesh.name.to_string()),
+                    uri: Some(image_path),
+                    extensions: None,
+                    extras: None,
+                });
+
+                let texture_i = root.push(json::Texture {
+                    name: None,
+                    sampler: None,
+                    source: image_i,
+                    extensions: None,
+                    extras: None,
+                });
+
+                let material_i = root.push(json::Material {
+                    alpha_cutoff: None,
+                    alpha_mode: Valid(alpha_mode),
+                    double_sided: true,
+                    name: Some(smf_mesh.texture_name.to_string()),
+                    pbr_metallic_roughness: PbrMetallicRoughness {
+                        base_color_factor: json::material::PbrBaseColorFactor([1.0, 1.0, 1.0, 1.0]),
+                        base_color_texture: Some(json::texture::Info {
+                            index: texture_i,
+                            tex_coord: 0,
+                            extensions: None,
+                            extras: None,
+                        }),
+                        metallic_factor: json::material::StrengthFactor(0.0),
+                        roughness_factor: json::material::StrengthFactor(1.0),
+                        metallic_roughness_texture: None,
+                        extensions: None,
+                        extras: None,
+                    },
+                    normal_texture: None,
+                    occlusion_texture: None,
+                    emissive_texture: None,
+                    emissive_factor: json::material::EmissiveFactor([0.0, 0.0, 0.0]),
+                    extensions: None,
+                    extras: None,
+                });
+
+                material_indices.insert(smf_mesh.texture_name.clone(), material_i);
+
+                material_i
+            };
+
+            let primitive = json::mesh::Primitive {
+                attributes: {
+                    let mut map = std::collections::BTreeMap::new();
+                    map.insert(Valid(json::mesh::Semantic::Positions), positions);
+                    map.insert(Valid(json::mesh::Semantic::Normals), normals);
+                    map.insert(Valid(json::mesh::Semantic::TexCoords(0)), uvs);
+                    if let Some(joints) = joints {
+                        map.insert(Valid(json::mesh::Semantic::Joints(0)), joints);
+                    }
+                    if let Some(weights) = weights {
+                        map.insert(Valid(json::mesh::Semantic::Weights(0)), weights);
+                    }
+                    map
+                },
+                extensions: Default::default(),
+                extras: Default::default(),
+                indices: Some(indices),
+                material: Some(material_i),
+                mode: Valid(json::mesh::Mode::Triangles),
+                targets: None,
+            };
+
+            let mesh_index = root.push(json::Mesh {
+                extensions: Default::default(),
+                extras: Default::default(),
+                name: Some(smf_mesh.name.to_string()),
+                primitives: vec![primitive],
+                weights: None,
+            });
+
+            let node_index = root.push(json::Node {
+                mesh: Some(mesh_index),
+                name: Some(smf_mesh.name.to_string()),
+                skin: skin_index,
+                ..Default::default()
+            });
+
+            if skeleton_mode {
+                mesh_node_indices.push(node_index);
+            } else {
+                let Some(parent_index) = node_indices.get(&smf_node.name) else {
+                    panic!("node index not found");
+                };
+
+                let Some(parent) = root.nodes.get_mut(parent_index.value()) else {
+                    panic!("parent node not found!");
+                };
+
+                if let Some(ref mut children) = parent.children {
+                    children.push(node_index);
+                } else {
+                    parent.children = Some(vec![node_index]);
+                }
+            }
+        }
+    }
+
+    let mut scene_nodes = vec![root_index];
+    if skeleton_mode {
+        scene_nodes.extend(mesh_node_indices);
+    }
+
+    root.push(json::Scene {
+        extensions: Default::default(),
+        extras: to_extras(&serde_json::json!({
+            "smf_version": scene.version.as_str(),
+            "scale": scene.scale.to_array(),
+        })),
+        name: Some(scene.name.to_string()),
+        nodes: scene_nodes,
+    });
+
+    root
+}
+
+/// Calculate bounding coordinates of a list of vertices, used for the clipping distance of the model
+fn bounding_coords(points: &[VV]) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::MAX, f32::MAX, f32::MAX];
+    let mut max = [f32::MIN, f32::MIN, f32::MIN];
+
+    for point in points {
+        let p = point.position;
+        for i in 0..3 {
+            min[i] = f32::min(min[i], p[i]);
+            max[i] = f32::max(max[i], p[i]);
+        }
+    }
+    (min, max)
+}
+
+fn compute_global_transforms(
+    transforms: &[NodeTransform],
+    parent_indices: &[Option<usize>],
+) -> Vec<Mat4> {
+    let mut globals = vec![Mat4::IDENTITY; transforms.len()];
+    let mut visited = vec![false; transforms.len()];
+
+    fn visit(
+        index: usize,
+        transforms: &[NodeTransform],
+        parent_indices: &[Option<usize>],
+        globals: &mut [Mat4],
+        visited: &mut [bool],
+    ) {
+        if visited[index] {
+            return;
+        }
+
+        let global = if let Some(parent_index) = parent_indices[index] {
+            visit(parent_index, transforms, parent_indices, globals, visited);
+            globals[parent_index] * transforms[index].local_matrix
+        } else {
+            transforms[index].local_matrix
+        };
+
+        globals[index] = global;
+        visited[index] = true;
+    }
+
+    for index in 0..transforms.len() {
+        visit(index, transforms, parent_indices, &mut globals, &mut visited);
+    }
+
+    globals
+}
+
+fn compute_joint_translations(
+    translations: &[Vec3],
+    rotations: &[Quat],
+    parent_indices: &[Option<usize>],
+) -> Vec<Vec3> {
+    let mut adjusted = vec![Vec3::ZERO; translations.len()];
+    let mut global_rotations = vec![Quat::IDENTITY; translations.len()];
+    let mut visited = vec![false; translations.len()];
+
+    fn visit(
+        index: usize,
+        translations: &[Vec3],
+        rotations: &[Quat],
+        parent_indices: &[Option<usize>],
+        adjusted: &mut [Vec3],
+        global_rotations: &mut [Quat],
+        visited: &mut [bool],
+    ) {
+        if visited[index] {
+            return;
+        }
+
+        if let Some(parent_index) = parent_indices[index] {
+            visit(
+                parent_index,
+                translations,
+                rotations,
+                parent_indices,
+                adjusted,
+                global_rotations,
+                visited,
+            );
+            let parent_global_rotation = global_rotations[parent_index];
+            adjusted[index] = parent_global_rotation.inverse() * translations[index];
+            global_rotations[index] = parent_global_rotation * rotations[index];
+        } else {
+            adjusted[index] = translations[index];
+            global_rotations[index] = rotations[index];
+        }
+
+        visited[index] = true;
+    }
+
+    for index in 0..translations.len() {
+        visit(
+            index,
+            translations,
+            rotations,
+            parent_indices,
+            &mut adjusted,
+            &mut global_rotations,
+            &mut visited,
+        );
+    }
+
+    adjusted
+}
+
+fn to_padded_byte_vector<T>(vec: Vec<T>) -> Vec<u8> {
+    let byte_length = vec.len() * std::mem::size_of::<T>();
+    let byte_capacity = vec.capacity() * std::mem::size_of::<T>();
+    let alloc = vec.into_boxed_slice();
+    let ptr = Box::<[T]>::into_raw(alloc) as *mut u8;
+    let mut new_vec = unsafe { Vec::from_raw_parts(ptr, byte_length, byte_capacity) };
+    while new_vec.len() % 4 != 0 {
+        new_vec.push(0); // pad to multiple of four bytes
+    }
+    new_vec
+}
+
+fn create_buffer<T>(buffer: Vec<T>) -> json::Buffer {
+    let count = buffer.len();
+    let byte_length = count * std::mem::size_of::<T>();
+    let byte_vector = to_padded_byte_vector(buffer);
+
+    let data_uri = create_data_uri(&byte_vector, "application/octet-stream");
+
+    json::Buffer {
+        byte_length: USize64::from(byte_length),
+        name: None,
+        uri: Some(data_uri),
+        extensions: None,
+        extras: None,
+    }
+}
+
+fn to_extras(value: &serde_json::Value) -> json::Extras {
+    Some(serde_json::value::to_raw_value(value).expect("Could not serialize extras."))
+}
+
+fn create_data_uri(data: &[u8], mime_type: &str) -> String {
+    use base64::Engine;
+
+    let base64 = base64::engine::GeneralPurpose::new(
+        &base64::alphabet::STANDARD,
+        base64::engine::general_purpose::PAD,
+    );
+
+    let encoded_buffer = base64.encode(data);
+
+    format!("data:{mime_type};base64,{encoded_buffer}")
+}
+
+fn image_to_buffer(image: &image::RgbaImage) -> String {
+    let mut buf = Vec::new();
+    let mut writer = std::io::Cursor::new(&mut buf);
+    image
+        .write_to(&mut writer, ImageFormat::Png)
+        .expect("Could not generate image buffer.");
+
+    create_data_uri(&buf, "image/png")
+}
+
+/// glTF only supports .png and .jpg images, so textures are converted to .png and written next to
+/// the .gltf file. Returns the uri of the image, relative to the .gltf file.
+fn write_png_next_to(
+    image: &image::RgbaImage,
+    texture_path: &str,
+    gltf_path: &Path,
+) -> image::ImageResult<String> {
+    let file_name = texture_path
+        .rsplit(['\\', '/'])
+        .next()
+        .unwrap_or(texture_path);
+    let png_name = Path::new(file_name).with_extension("png");
+    let png_path = gltf_path
+        .parent()
+        .expect("Could not get directory parent.")
+        .join(&png_name);
+    image.save(&png_path)?;
+
+    Ok(png_name.to_string_lossy().to_string())
+}
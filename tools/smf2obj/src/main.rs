@@ -0,0 +1,261 @@
+use clap::Parser;
+use shadow_company_tools::{
+    smf::{self, CONVERT, CONVERT_NORMAL},
+    texture_resolver::{TextureAlpha, TextureResolver},
+    Mat4, Vec3,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Parser)]
+struct Opts {
+    /// Path to the .smf file to convert.
+    path: PathBuf,
+    /// Where to write the .obj file. The .mtl file and the textures are written next to it.
+    /// Defaults to the path of the input with a .obj extension.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// An extra directory to search for images. The directory of the model and the "Data"
+    /// directory it is in are always searched.
+    #[arg(short, long)]
+    texture_path: Option<PathBuf>,
+    #[arg(short, long, default_value = "1.0")]
+    scale: f32,
+    /// Keep the left handed z-up coordinate system of SC, instead of converting to the right
+    /// handed y-up system most tools expect.
+    #[arg(short, long)]
+    keep_coordinates: bool,
+}
+
+fn main() {
+    let opts = Opts::parse();
+
+    let model = match std::fs::File::open(&opts.path)
+        .and_then(|file| smf::Model::read(&mut std::io::BufReader::new(file)))
+    {
+        Ok(model) => model,
+        Err(err) => {
+            eprintln!("Could not read {}. {}", opts.path.display(), err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut resolver = TextureResolver::default();
+    if let Some(ref texture_path) = opts.texture_path {
+        if let Err(err) = resolver.add_root(texture_path) {
+            eprintln!("Could not read {}. {}", texture_path.display(), err);
+        }
+    }
+    if let Err(err) = resolver.add_model_roots(&opts.path) {
+        eprintln!("Could not search for textures. {}", err);
+    }
+
+    let output = opts
+        .output
+        .clone()
+        .unwrap_or_else(|| opts.path.with_extension("obj"));
+    match write_obj(&model, &output, &resolver, &opts) {
+        Ok(()) => println!("Generated {}", output.display()),
+        Err(err) => {
+            eprintln!("Could not write {}. {}", output.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn write_obj(
+    model: &smf::Model,
+    obj_path: &Path,
+    resolver: &TextureResolver,
+    opts: &Opts,
+) -> std::io::Result<()> {
+    let mtl_path = obj_path.with_extension("mtl");
+    let mtl_name = mtl_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    // OBJ faces are counter clockwise, so the winding of the faces is reversed, which makes them
+    // face the same way as the SMF normals. Mirroring with CONVERT flips the faces again, so the
+    // normals are negated, like smf2gltf does.
+    let (to_obj, to_obj_normal) = if opts.keep_coordinates {
+        (Mat4::from_scale(Vec3::splat(opts.scale)), Mat4::IDENTITY)
+    } else {
+        (
+            CONVERT * Mat4::from_scale(Vec3::splat(opts.scale)),
+            CONVERT_NORMAL * Mat4::from_scale(Vec3::NEG_ONE),
+        )
+    };
+
+    let mut obj = std::io::BufWriter::new(std::fs::File::create(obj_path)?);
+    writeln!(obj, "# {}", model.name)?;
+    writeln!(obj, "mtllib {}", mtl_name)?;
+
+    // Maps texture names to material names.
+    let mut materials = HashMap::new();
+    let mut material_names = HashSet::new();
+    let mut mtl = std::io::BufWriter::new(std::fs::File::create(&mtl_path)?);
+
+    // OBJ indices are 1 based and shared by the whole file.
+    let mut base = 1;
+    for (node, world) in model.nodes.iter().zip(model.world_transforms()) {
+        let (_, rotation, _) = world.to_scale_rotation_translation();
+
+        for mesh in node.meshes.iter() {
+            let material = match materials.get(&mesh.texture_name) {
+                Some(material) => material,
+                None => {
+                    let material = write_material(
+                        &mut mtl,
+                        &mesh.texture_name,
+                        &mut material_names,
+                        obj_path,
+                        resolver,
+                    )?;
+                    materials
+                        .entry(mesh.texture_name.clone())
+                        .or_insert(material)
+                }
+            };
+
+            writeln!(obj, "g {}_{}", node.name, mesh.name)?;
+            writeln!(obj, "usemtl {}", material)?;
+
+            for vertex in mesh.vertices.iter() {
+                let p = to_obj.transform_point3(world.transform_point3(vertex.position));
+                writeln!(obj, "v {} {} {}", p.x, p.y, p.z)?;
+            }
+            for vertex in mesh.vertices.iter() {
+                // OBJ has the origin of the texture in the bottom left corner.
+                writeln!(
+                    obj,
+                    "vt {} {}",
+                    vertex.tex_coord.x,
+                    1.0 - vertex.tex_coord.y
+                )?;
+            }
+            for vertex in mesh.vertices.iter() {
+                let n = to_obj_normal
+                    .transform_vector3(rotation * vertex.normal)
+                    .normalize_or_zero();
+                writeln!(obj, "vn {} {} {}", n.x, n.y, n.z)?;
+            }
+
+            for face in mesh.faces.iter() {
+                let [i0, i1, i2] = face.indices.map(|i| i + base);
+                writeln!(obj, "f {i2}/{i2}/{i2} {i1}/{i1}/{i1} {i0}/{i0}/{i0}")?;
+            }
+
+            base += mesh.vertices.len() as u32;
+        }
+    }
+
+    obj.flush()?;
+    mtl.flush()
+}
+
+/// Write a material for a texture and convert the texture to a .png file next to the .obj file.
+/// Returns the name of the material.
+fn write_material(
+    mtl: &mut impl Write,
+    texture_name: &str,
+    material_names: &mut HashSet<String>,
+    obj_path: &Path,
+    resolver: &TextureResolver,
+) -> std::io::Result<String> {
+    let material = material_name(texture_name, material_names);
+
+    writeln!(mtl, "newmtl {}", material)?;
+    writeln!(mtl, "Ka 1 1 1")?;
+    writeln!(mtl, "Kd 1 1 1")?;
+    writeln!(mtl, "Ks 0 0 0")?;
+    writeln!(mtl, "illum 1")?;
+
+    if !texture_name.is_empty() {
+        match resolver.resolve(texture_name) {
+            Ok(Some(texture)) => {
+                let png_name = format!("{}.png", material);
+                let png_path = obj_path.with_file_name(&png_name);
+                texture
+                    .image
+                    .save(&png_path)
+                    .map_err(std::io::Error::other)?;
+
+                writeln!(mtl, "map_Kd {}", png_name)?;
+                // The alpha channel of the .png is the transparency.
+                if texture.alpha != TextureAlpha::Opaque {
+                    writeln!(mtl, "map_d {}", png_name)?;
+                }
+            }
+            Ok(None) => {
+                eprintln!("Warning: Could not find image: {}", texture_name);
+                writeln!(mtl, "map_Kd {}", texture_name)?;
+            }
+            Err(err) => {
+                eprintln!("Warning: Could not load image: {}. {}", texture_name, err);
+                writeln!(mtl, "map_Kd {}", texture_name)?;
+            }
+        }
+    }
+    writeln!(mtl)?;
+
+    Ok(material)
+}
+
+/// The name of the material for a texture, also used for its .png file. Textures like "a b.bmp"
+/// and "a_b.jpg" would get the same name, so a number is added to names that are already in
+/// `material_names`. Names are compared without case, because the file system may ignore it.
+fn material_name(texture_name: &str, material_names: &mut HashSet<String>) -> String {
+    let stem = texture_name
+        .rsplit_once('.')
+        .map(|(stem, _)| stem)
+        .unwrap_or(texture_name);
+    let base = if stem.is_empty() {
+        "default".to_string()
+    } else {
+        stem.replace(char::is_whitespace, "_")
+    };
+
+    let mut material = base.clone();
+    let mut number = 2;
+    while !material_names.insert(material.to_ascii_lowercase()) {
+        material = format!("{}_{}", base, number);
+        number += 1;
+    }
+    material
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_material_names() {
+        let mut material_names = HashSet::new();
+        let names = [
+            "wall.bmp",
+            "wall.jpg",
+            "a b.bmp",
+            "a_b.bmp",
+            "Wall_2.bmp",
+            "",
+            "default.bmp",
+        ]
+        .map(|texture_name| material_name(texture_name, &mut material_names));
+        assert_eq!(
+            names,
+            [
+                "wall",
+                "wall_2",
+                "a_b",
+                "a_b_2",
+                "Wall_2_2",
+                "default",
+                "default_2"
+            ]
+        );
+    }
+}
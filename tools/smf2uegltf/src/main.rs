@@ -17,6 +17,7 @@ use json::{
 use shadow_company_tools::{
     bmf,
     smf::{self, CONVERT, CONVERT_NORMAL},
+    texture_resolver::{TextureAlpha, TextureResolver},
     Mat4, Quat, Vec3, Vec4,
 };
 
@@ -39,6 +40,9 @@ struct Opts {
     /// Export mode: full, mesh, anim.
     #[arg(long, value_enum, default_value_t = ExportMode::Full)]
     mode: ExportMode,
+    /// Export the bounding boxes of the nodes as UBX_ collision meshes. Not used with --mode anim.
+    #[arg(long)]
+    collision: bool,
 }
 
 /// BMF data appears to be right-handed z-up; this basis converts to right-handed y-up without an X mirror.
@@ -62,6 +66,12 @@ fn main() {
         &mut std::fs::File::open(&opts.smf_path).expect("Could not open .smf file."),
     )
     .expect("Could not parse .smf file.");
+    if let Err(errors) = smf.validate_hierarchy() {
+        for error in errors {
+            eprintln!("{}", error);
+        }
+        std::process::exit(1);
+    }
 
     let bmf_path = if opts.mode == ExportMode::Mesh {
         opts.bmf_path.clone()
@@ -91,7 +101,14 @@ fn main() {
 
     println!("out_path: {out_path:?}");
 
-    let gltf = build_gltf(&smf, bmf.as_ref(), &out_path, &opts.smf_path, opts.mode);
+    let gltf = build_gltf(
+        &smf,
+        bmf.as_ref(),
+        &out_path,
+        &opts.smf_path,
+        opts.mode,
+        opts.collision,
+    );
 
     let writer = std::fs::File::create(&out_path).expect("Could not create output file.");
     json::serialize::to_writer_pretty(writer, &gltf).expect("Could not write gltf.");
@@ -103,6 +120,7 @@ fn build_gltf(
     out_path: &Path,
     smf_path: &Path,
     mode: ExportMode,
+    collision: bool,
 ) -> Root {
     let mut root = Root::default();
 
@@ -116,13 +134,12 @@ fn build_gltf(
     let invert_anim_rot = true;
     let root_motion = true;
     let root_motion_bone = None;
-    let texture_roots = texture_search_roots(smf_path, out_path);
+    let textures = texture_resolver(smf_path, out_path);
 
-    assert_eq!(scene.nodes[0].parent_name, "<root>");
     let skeleton_root = add_node(
         &mut root,
-        &scene.nodes,
-        0,
+        scene,
+        scene.root().expect("The model has no root node."),
         Mat4::IDENTITY,
         scale,
         &mut bone_lookup,
@@ -155,7 +172,7 @@ fn build_gltf(
             let (mesh_index, skin_index) = build_mesh_and_skin(
                 &mut root,
                 scene,
-                &texture_roots,
+                &textures,
                 scale,
                 &joint_info,
                 &joints,
@@ -180,6 +197,19 @@ fn build_gltf(
         }
     };
 
+    let collision_nodes = if collision && mode != ExportMode::Anim {
+        build_collision_nodes(
+            &mut root,
+            scene,
+            &format!("{}_mesh", scene.name),
+            scale,
+            &joint_info,
+            flip_forward,
+        )
+    } else {
+        vec![]
+    };
+
     if flip_forward {
         apply_scene_rotation(&mut root, skeleton_root);
     }
@@ -208,11 +238,12 @@ fn build_gltf(
     if let Some(mesh_node) = mesh_node {
         scene_nodes.push(mesh_node);
     }
+    scene_nodes.extend(collision_nodes);
 
     root.push(Scene {
         extensions: Default::default(),
         extras: Default::default(),
-        name: Some(scene.name.clone()),
+        name: Some(scene.name.to_string()),
         nodes: scene_nodes,
     });
 
@@ -222,7 +253,7 @@ fn build_gltf(
 #[allow(clippy::too_many_arguments)]
 fn add_node(
     root: &mut Root,
-    nodes: &[smf::Node],
+    model: &smf::Model,
     node_index: usize,
     parent_global: Mat4,
     scale: f32,
@@ -231,21 +262,19 @@ fn add_node(
     joints: &mut Vec<Index<Node>>,
     joint_globals: &mut Vec<Mat4>,
 ) -> Index<Node> {
-    let node = &nodes[node_index];
+    let node = &model.nodes[node_index];
 
     let translation = convert_position(node.position, scale);
     let rotation = convert_rotation(node.rotation);
     let local = Mat4::from_translation(translation) * Mat4::from_quat(rotation);
     let global = parent_global * local;
 
-    let children = nodes
-        .iter()
-        .enumerate()
-        .filter(|(_, n)| n.parent_name == node.name)
-        .map(|(i, _)| {
+    let children = model
+        .children(node_index)
+        .map(|i| {
             add_node(
                 root,
-                nodes,
+                model,
                 i,
                 global,
                 scale,
@@ -268,7 +297,7 @@ fn add_node(
         extras: None,
         matrix: None,
         mesh: None,
-        name: Some(node.name.clone()),
+        name: Some(node.name.to_string()),
         rotation: Some(UnitQuaternion(rotation.to_array())),
         scale: None,
         translation: Some(translation.to_array()),
@@ -316,7 +345,7 @@ fn apply_scene_rotation(root: &mut Root, node_index: Index<Node>) {
 fn build_mesh_and_skin(
     root: &mut Root,
     scene: &smf::Model,
-    texture_roots: &[PathBuf],
+    textures: &TextureResolver,
     scale: f32,
     joint_info: &HashMap<u32, JointInfo>,
     joints: &[Index<Node>],
@@ -371,8 +400,7 @@ fn build_mesh_and_skin(
             let weights_accessor = create_vec4_accessor(root, &weights_0);
             let index_accessor = create_indices_accessor(root, &indices);
 
-            let material_index =
-                resolve_material(root, smf_mesh, texture_roots, &mut material_indices);
+            let material_index = resolve_material(root, smf_mesh, textures, &mut material_indices);
 
             let mut attributes = std::collections::BTreeMap::new();
             attributes.insert(Valid(json::mesh::Semantic::Positions), position_accessor);
@@ -396,7 +424,7 @@ fn build_mesh_and_skin(
     let mesh_index = root.push(Mesh {
         extensions: None,
         extras: None,
-        name: Some(scene.name.clone()),
+        name: Some(scene.name.to_string()),
         primitives,
         weights: None,
     });
@@ -420,6 +448,105 @@ fn build_mesh_and_skin(
     (mesh_index, skin_index)
 }
 
+/// Unreal imports meshes named `UBX_<mesh name>_<number>` as box collision for the mesh. The boxes
+/// are not skinned, so they are placed in the bind pose of their node.
+fn build_collision_nodes(
+    root: &mut Root,
+    scene: &smf::Model,
+    mesh_name: &str,
+    scale: f32,
+    joint_info: &HashMap<u32, JointInfo>,
+    flip_forward: bool,
+) -> Vec<Index<Node>> {
+    // The quads of a box, with the corners indexed like BoundingBox::corners.
+    const QUADS: [[usize; 4]; 6] = [
+        [0, 2, 6, 4],
+        [1, 3, 7, 5],
+        [0, 1, 5, 4],
+        [2, 3, 7, 6],
+        [0, 1, 3, 2],
+        [4, 5, 7, 6],
+    ];
+
+    // The same rotation apply_scene_rotation applies to the skeleton.
+    let flip = if flip_forward {
+        Mat4::from_quat(Quat::from_rotation_y(std::f32::consts::PI))
+    } else {
+        Mat4::IDENTITY
+    };
+
+    let mut nodes = vec![];
+    for smf_node in scene.nodes.iter() {
+        let Some(joint) = joint_info.get(&smf_node.tree_id) else {
+            continue;
+        };
+        let transform = flip * joint.global_bind;
+
+        for bounding_box in smf_node.bounding_boxes.iter() {
+            let corners = bounding_box
+                .corners()
+                .map(|corner| transform.transform_point3(convert_position(corner, scale)));
+            let center = corners.iter().sum::<Vec3>() / 8.0;
+
+            // Wind the triangles so they face away from the center of the box.
+            let mut indices = Vec::with_capacity(36);
+            for [a, b, c, d] in QUADS {
+                for [i0, i1, i2] in [[a, b, c], [a, c, d]] {
+                    let (p0, p1, p2) = (corners[i0], corners[i1], corners[i2]);
+                    let normal = (p1 - p0).cross(p2 - p0);
+                    if normal.dot(p0 - center) < 0.0 {
+                        indices.extend([i0 as u32, i2 as u32, i1 as u32]);
+                    } else {
+                        indices.extend([i0 as u32, i1 as u32, i2 as u32]);
+                    }
+                }
+            }
+
+            let positions = corners.map(|corner| corner.to_array());
+            let (min, max) = bounding_coords(&positions);
+            let position_accessor = create_vec3_accessor(root, &positions, Some(min), Some(max));
+            let index_accessor = create_indices_accessor(root, &indices);
+
+            let mut attributes = std::collections::BTreeMap::new();
+            attributes.insert(Valid(json::mesh::Semantic::Positions), position_accessor);
+
+            let name = format!("UBX_{}_{:02}", mesh_name, nodes.len());
+            let mesh_index = root.push(Mesh {
+                extensions: None,
+                extras: None,
+                name: Some(name.clone()),
+                primitives: vec![json::mesh::Primitive {
+                    attributes,
+                    extensions: None,
+                    extras: None,
+                    indices: Some(index_accessor),
+                    material: None,
+                    mode: Valid(json::mesh::Mode::Triangles),
+                    targets: None,
+                }],
+                weights: None,
+            });
+
+            nodes.push(root.push(Node {
+                camera: None,
+                children: None,
+                extensions: None,
+                extras: None,
+                matrix: None,
+                mesh: Some(mesh_index),
+                name: Some(name),
+                rotation: None,
+                scale: None,
+                skin: None,
+                translation: None,
+                weights: None,
+            }));
+        }
+    }
+
+    nodes
+}
+
 fn build_dummy_mesh_and_skin(
     root: &mut Root,
     scene: &smf::Model,
@@ -520,7 +647,7 @@ fn add_animation(
         extensions: None,
         extras: None,
         channels: vec![],
-        name: Some(motion.name.clone()),
+        name: Some(motion.name.to_string()),
         samplers: vec![],
     };
 
@@ -721,12 +848,10 @@ fn select_root_motion_bone(
         return Some(id);
     }
 
-    let root_name = scene.nodes.first()?.name.as_str();
+    let root_index = scene.root()?;
     let root_children = scene
-        .nodes
-        .iter()
-        .filter(|n| n.parent_name == root_name)
-        .map(|n| n.tree_id)
+        .children(root_index)
+        .map(|i| scene.nodes[i].tree_id)
         .collect::<Vec<_>>();
 
     for child in root_children.iter() {
@@ -735,7 +860,7 @@ fn select_root_motion_bone(
         }
     }
 
-    let root_id = scene.nodes.first().map(|n| n.tree_id)?;
+    let root_id = scene.nodes[root_index].tree_id;
     if motion_has_translation(motion, root_id) {
         return Some(root_id);
     }
@@ -774,30 +899,39 @@ fn convert_rotation_basis(q: Quat, basis: Mat4) -> Quat {
 fn resolve_material(
     root: &mut Root,
     mesh: &smf::Mesh,
-    texture_roots: &[PathBuf],
+    textures: &TextureResolver,
     materials: &mut HashMap<String, Index<json::Material>>,
 ) -> Index<json::Material> {
-    if let Some(existing) = materials.get(&mesh.texture_name) {
+    if let Some(existing) = materials.get(mesh.texture_name.as_str()) {
         return *existing;
     }
 
-    let image_uri = if let Some(image_path) = texture_roots
-        .iter()
-        .find_map(|root| find_image_path(root, &mesh.texture_name))
-    {
-        image_to_buffer(image_path).expect("Could not embed image.")
-    } else {
-        eprintln!("Warning: Could not find image: {}", mesh.texture_name);
-        PathBuf::from(&mesh.texture_name)
-            .to_str()
-            .unwrap()
-            .to_owned()
+    let (image_uri, alpha_mode) = match textures.resolve(&mesh.texture_name) {
+        Ok(Some(texture)) => {
+            let alpha_mode = match texture.alpha {
+                TextureAlpha::Opaque => material::AlphaMode::Opaque,
+                TextureAlpha::Raw(_) => material::AlphaMode::Blend,
+                TextureAlpha::ColorKey => material::AlphaMode::Mask,
+            };
+            (image_to_buffer(&texture.image), alpha_mode)
+        }
+        Ok(None) => {
+            eprintln!("Warning: Could not find image: {}", mesh.texture_name);
+            (mesh.texture_name.to_string(), material::AlphaMode::Opaque)
+        }
+        Err(err) => {
+            eprintln!(
+                "Warning: Could not load image: {}. {}",
+                mesh.texture_name, err
+            );
+            (mesh.texture_name.to_string(), material::AlphaMode::Opaque)
+        }
     };
 
     let image_index = root.push(json::Image {
         buffer_view: None,
         mime_type: None,
-        name: Some(mesh.name.clone()),
+        name: Some(mesh.name.to_string()),
         uri: Some(image_uri),
         extensions: None,
         extras: None,
@@ -813,7 +947,7 @@ fn resolve_material(
 
     let material_index = root.push(json::Material {
         alpha_cutoff: None,
-        alpha_mode: Valid(material::AlphaMode::Opaque),
+        alpha_mode: Valid(alpha_mode),
         double_sided: true,
         name: None,
         pbr_metallic_roughness: material::PbrMetallicRoughness {
@@ -838,7 +972,7 @@ fn resolve_material(
         extras: None,
     });
 
-    materials.insert(mesh.texture_name.clone(), material_index);
+    materials.insert(mesh.texture_name.to_string(), material_index);
 
     material_index
 }
@@ -1084,83 +1218,31 @@ fn create_data_uri(data: &[u8], mime_type: &str) -> String {
     format!("data:{mime_type};base64,{encoded_buffer}")
 }
 
-fn find_image_path(root: impl AsRef<Path>, name: &str) -> Option<PathBuf> {
-    walkdir::WalkDir::new(root.as_ref())
-        .into_iter()
-        .filter_map(Result::ok)
-        .find(|e| e.file_name().eq_ignore_ascii_case(name))
-        .map(|e| e.into_path())
-}
-
-fn texture_search_roots(smf_path: &Path, out_path: &Path) -> Vec<PathBuf> {
-    let mut roots = Vec::new();
-
-    if let Some(textures_root) = find_textures_root(smf_path) {
-        roots.push(textures_root);
+/// Textures are searched for next to the model, in the "Data" directory the model is in and next
+/// to the output file.
+fn texture_resolver(smf_path: &Path, out_path: &Path) -> TextureResolver {
+    let mut resolver = TextureResolver::default();
+    if let Err(err) = resolver.add_model_roots(smf_path) {
+        eprintln!("Warning: Could not index textures. {}", err);
     }
 
-    if let Some(out_parent) = out_path.parent() {
-        if !roots.iter().any(|root| root == out_parent) {
-            roots.push(out_parent.to_path_buf());
+    if let Some(out_parent) = out_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        if let Err(err) = resolver.add_root(out_parent) {
+            eprintln!("Warning: Could not index textures. {}", err);
         }
     }
 
-    roots
+    resolver
 }
 
-fn find_textures_root(smf_path: &Path) -> Option<PathBuf> {
-    let mut current = smf_path.parent();
-    while let Some(dir) = current {
-        let is_data = dir
-            .file_name()
-            .and_then(|name| name.to_str())
-            .map(|name| name.eq_ignore_ascii_case("data"))
-            .unwrap_or(false);
-        if is_data {
-            let textures = dir.join("textures");
-            if textures.is_dir() {
-                return Some(textures);
-            }
-        }
-        current = dir.parent();
-    }
-
-    None
-}
-
-fn image_to_buffer(image_path: impl AsRef<Path>) -> std::io::Result<String> {
-    let file = std::fs::File::open(image_path.as_ref())?;
-    let mut reader = std::io::BufReader::new(file);
-
-    let ext = image_path.as_ref().extension().unwrap();
-    if !ext.eq_ignore_ascii_case("bmp") {
-        panic!("Invalid image format!");
-    }
-
-    let bmp_image = shadow_company_tools::images::load_bmp_file(&mut reader, false)
-        .expect("Could not open .bmp file");
-
-    let raw_path = image_path.as_ref().with_extension("raw");
-    let png = if raw_path.exists() {
-        let raw_image = shadow_company_tools::images::load_raw_file(
-            &mut std::fs::File::open(&raw_path).expect("Could not open .raw file."),
-            bmp_image.width(),
-            bmp_image.height(),
-        )
-        .expect("Could not read .raw file.");
-        shadow_company_tools::images::combine_bmp_and_raw(&bmp_image, &raw_image)
-    } else {
-        use image::buffer::ConvertBuffer;
-        let png: image::RgbaImage = bmp_image.convert();
-        png
-    };
-
+fn image_to_buffer(image: &image::RgbaImage) -> String {
     let mut buf = Vec::new();
     let mut writer = std::io::Cursor::new(&mut buf);
-    png.write_to(&mut writer, ImageFormat::Png)
+    image
+        .write_to(&mut writer, ImageFormat::Png)
         .expect("Could not generate image buffer.");
 
-    Ok(create_data_uri(&buf, "image/png"))
+    create_data_uri(&buf, "image/png")
 }
 
 fn bounding_coords(points: &[[f32; 3]]) -> ([f32; 3], [f32; 3]) {
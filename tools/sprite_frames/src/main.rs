@@ -0,0 +1,161 @@
+use clap::Parser;
+use serde::Serialize;
+use shadow_company_tools::{data_dir::DataDir, texture_resolver::TextureResolver};
+use shadow_company_tools_configs::{ImageDefs, SpriteSheet, SpriteSheetError};
+use std::{collections::BTreeMap, io::Write, path::PathBuf};
+
+#[derive(Parser)]
+struct Opts {
+    /// Path to the "<Shadow Company>\Data" directory.
+    data_dir: PathBuf,
+    /// Directory to write the frames and atlas.json to.
+    #[arg(short, long, default_value = "sprites")]
+    output: PathBuf,
+    /// Only extract the sprites with these names.
+    names: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct AtlasSprite {
+    texture: String,
+    width: u32,
+    height: u32,
+    frames: Vec<AtlasFrame>,
+}
+
+#[derive(Serialize)]
+struct AtlasFrame {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    /// The extracted frame, relative to atlas.json. Not set if the frame is outside of the texture.
+    file: Option<String>,
+}
+
+fn main() {
+    let opts = Opts::parse();
+
+    let data_dir = DataDir::new(&opts.data_dir);
+    let image_defs = match ImageDefs::load(&data_dir) {
+        Ok(image_defs) => image_defs,
+        Err(err) => {
+            eprintln!("Could not read image_defs.txt: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut resolver = TextureResolver::default();
+    if let Err(err) = resolver.add_root(&opts.data_dir) {
+        eprintln!("Could not read {}. {}", opts.data_dir.display(), err);
+        std::process::exit(1);
+    }
+
+    let wanted = |name: &str| {
+        opts.names.is_empty() || opts.names.iter().any(|n| n.eq_ignore_ascii_case(name))
+    };
+
+    let mut sheets: Vec<(&'static str, Result<SpriteSheet, SpriteSheetError>)> = vec![];
+    for sprite in image_defs.sprite_3ds.iter().filter(|s| wanted(&s.name)) {
+        sheets.push(("sprite3d", SpriteSheet::from_sprite_3d(&resolver, sprite)));
+    }
+    for sprite in image_defs.anim_sprites.iter().filter(|s| wanted(&s.name)) {
+        sheets.push((
+            "animsprite",
+            SpriteSheet::from_anim_sprite(&resolver, sprite),
+        ));
+    }
+    for sprite in image_defs
+        .anim_sprite_3ds
+        .iter()
+        .filter(|s| wanted(&s.name))
+    {
+        sheets.push((
+            "animsprite3d",
+            SpriteSheet::from_anim_sprite(&resolver, sprite),
+        ));
+    }
+
+    let mut atlas = BTreeMap::new();
+    for (kind, sheet) in sheets {
+        let sheet = match sheet {
+            Ok(sheet) => sheet,
+            Err(err) => {
+                eprintln!("Could not load sprite texture. {}", err);
+                continue;
+            }
+        };
+
+        match extract_frames(&opts, kind, &sheet) {
+            Ok(sprite) => {
+                println!("{} {}: {} frames", kind, sheet.name, sprite.frames.len());
+                atlas
+                    .entry(kind)
+                    .or_insert_with(BTreeMap::new)
+                    .insert(sheet.name.clone(), sprite);
+            }
+            Err(err) => eprintln!("Could not extract frames for {}. {}", sheet.name, err),
+        }
+    }
+
+    let atlas_path = opts.output.join("atlas.json");
+    let result = std::fs::create_dir_all(&opts.output)
+        .and_then(|_| std::fs::File::create(&atlas_path))
+        .and_then(|file| {
+            // Dropping a BufWriter ignores errors, so it is flushed explicitly.
+            let mut writer = std::io::BufWriter::new(file);
+            serde_json::to_writer_pretty(&mut writer, &atlas)?;
+            writer.flush()
+        });
+    match result {
+        Ok(()) => println!("Generated {}", atlas_path.display()),
+        Err(err) => {
+            eprintln!("Could not write {}. {}", atlas_path.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn extract_frames(
+    opts: &Opts,
+    kind: &'static str,
+    sheet: &SpriteSheet,
+) -> image::ImageResult<AtlasSprite> {
+    // Sprites of different kinds can have the same name.
+    let dir = format!("{}/{}", kind, sheet.name);
+    std::fs::create_dir_all(opts.output.join(&dir))?;
+
+    let mut frames = Vec::with_capacity(sheet.frames.len());
+    for (index, rect) in sheet.frames.iter().enumerate() {
+        let file = match sheet.frame(index) {
+            Some(frame) => {
+                let file = format!("{}/{:03}.png", dir, index);
+                frame.save(opts.output.join(&file))?;
+                Some(file)
+            }
+            None => {
+                eprintln!(
+                    "{}: frame {} ({:?}) is outside of the texture.",
+                    sheet.name, index, rect
+                );
+                None
+            }
+        };
+
+        frames.push(AtlasFrame {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+            file,
+        });
+    }
+
+    let (width, height) = sheet.texture.dimensions();
+    Ok(AtlasSprite {
+        texture: sheet.texture_path.clone(),
+        width,
+        height,
+        frames,
+    })
+}
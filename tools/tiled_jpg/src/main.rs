@@ -0,0 +1,192 @@
+use clap::{Parser, Subcommand};
+use shadow_company_tools::{
+    config::{Config, ConfigReader},
+    data_dir::DataDir,
+    images::{write_jpg_file, TiledImage},
+};
+use shadow_company_tools_configs::WindowBases;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+struct Opts {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Stitch the chunks of every GEOMETRY_TILED background in a window definition into one image.
+    Stitch {
+        /// Path to the "<Shadow Company>\Data" directory.
+        data_dir: PathBuf,
+        /// The window definition, relative to the data directory, e.g. "config\main_menu.txt".
+        config: String,
+        /// The directory inside the data directory the chunks are stored in.
+        #[arg(short, long, default_value = "textures")]
+        dir: String,
+        /// How the chunks are named, e.g. "{name}_{index:2}". Detected if not specified.
+        #[arg(short, long)]
+        pattern: Option<String>,
+        /// Directory to write the stitched images to.
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+    /// Split an image into chunks that can replace the original chunks.
+    Split {
+        /// The image to split.
+        image: PathBuf,
+        /// The size of the chunks, e.g. "256x256".
+        #[arg(short, long, value_parser = parse_size)]
+        chunk: (u32, u32),
+        /// The name used for the chunks. Defaults to the stem of the image.
+        #[arg(short, long)]
+        name: Option<String>,
+        /// How the chunks are named. See the stitch command.
+        #[arg(short, long, default_value = "{name}_{index}")]
+        pattern: String,
+        /// The JPEG quality, from 1 to 100.
+        #[arg(short, long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+        quality: u8,
+        /// Directory to write the chunks to.
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("Expected <width>x<height>, got \"{}\"", value))?;
+    let parse = |v: &str| {
+        v.trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|v| *v > 0)
+            .ok_or_else(|| format!("Invalid size: \"{}\"", value))
+    };
+    Ok((parse(width)?, parse(height)?))
+}
+
+fn stitch(data_dir: &Path, config: &str, dir: &str, pattern: Option<&str>, output: &Path) {
+    let data_dir = DataDir::new(data_dir);
+    let window_bases = match data_dir
+        .open(config)
+        .map_err(|err| err.to_string())
+        .and_then(|file| ConfigReader::new(file).map_err(|err| err.to_string()))
+        .and_then(|mut reader| WindowBases::from_config(&mut reader).map_err(|err| err.to_string()))
+    {
+        Ok(window_bases) => window_bases,
+        Err(err) => {
+            eprintln!("Could not read {}: {}", config, err);
+            std::process::exit(1);
+        }
+    };
+
+    for tiled in window_bases
+        .window_bases
+        .iter()
+        .flat_map(|window_base| window_base.geometry_tiled.iter())
+    {
+        let tiled = tiled.tiled_image();
+        let image = match tiled.load(&data_dir, dir, pattern) {
+            Ok(image) => image,
+            Err(err) => {
+                eprintln!("Could not stitch {}. {}", tiled.name, err);
+                continue;
+            }
+        };
+
+        let png_path = output.join(format!("{}.png", tiled.name));
+        match image.save(&png_path) {
+            Ok(()) => println!(
+                "Generated {} ({}x{} from {}x{} chunks of {}x{})",
+                png_path.display(),
+                tiled.width,
+                tiled.height,
+                tiled.columns(),
+                tiled.rows(),
+                tiled.chunk_width,
+                tiled.chunk_height,
+            ),
+            Err(err) => eprintln!("Could not write {}. {}", png_path.display(), err),
+        }
+    }
+}
+
+fn split(
+    path: &Path,
+    (chunk_width, chunk_height): (u32, u32),
+    name: Option<String>,
+    pattern: &str,
+    quality: u8,
+    output: &Path,
+) {
+    let image = match image::open(path) {
+        Ok(image) => image.to_rgba8(),
+        Err(err) => {
+            eprintln!("Could not open {}. {}", path.display(), err);
+            std::process::exit(1);
+        }
+    };
+
+    let name = name.unwrap_or_else(|| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    });
+    let tiled = TiledImage {
+        name,
+        width: image.width(),
+        height: image.height(),
+        chunk_width,
+        chunk_height,
+    };
+
+    if let Err(err) = std::fs::create_dir_all(output) {
+        eprintln!("Could not create {}. {}", output.display(), err);
+        std::process::exit(1);
+    }
+
+    for (chunk_name, chunk) in tiled.split(&image, pattern) {
+        let chunk_path = output.join(&chunk_name);
+        let rgb = image::DynamicImage::ImageRgba8(chunk).to_rgb8();
+        let result = if chunk_name.to_ascii_lowercase().ends_with(".jpg") {
+            std::fs::File::create(&chunk_path)
+                .map_err(image::ImageError::IoError)
+                .and_then(|mut file| write_jpg_file(&mut file, &rgb, quality))
+        } else {
+            rgb.save(&chunk_path)
+        };
+        match result {
+            Ok(()) => println!("Generated {}", chunk_path.display()),
+            Err(err) => eprintln!("Could not write {}. {}", chunk_path.display(), err),
+        }
+    }
+
+    println!(
+        "GEOMETRY_JPG_DIMENSIONS {} {}\nGEOMETRY_CHUNK_DIMENSIONS {} {}",
+        tiled.width, tiled.height, tiled.chunk_width, tiled.chunk_height
+    );
+}
+
+fn main() {
+    let opts = Opts::parse();
+
+    match opts.command {
+        Commands::Stitch {
+            data_dir,
+            config,
+            dir,
+            pattern,
+            output,
+        } => stitch(&data_dir, &config, &dir, pattern.as_deref(), &output),
+        Commands::Split {
+            image,
+            chunk,
+            name,
+            pattern,
+            quality,
+            output,
+        } => split(&image, chunk, name, &pattern, quality, &output),
+    }
+}
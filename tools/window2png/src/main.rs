@@ -0,0 +1,108 @@
+use clap::Parser;
+use shadow_company_tools::{
+    config::{Config, ConfigReader},
+    data_dir::DataDir,
+    texture_resolver::TextureResolver,
+};
+use shadow_company_tools_configs::{
+    render_window_base, BlendMode, WindowBases, WindowRenderOptions,
+};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+struct Opts {
+    /// Path to the "<Shadow Company>\Data" directory.
+    data_dir: PathBuf,
+    /// The window definition to render, relative to the data directory, e.g.
+    /// "config\main_menu.txt".
+    config: String,
+    /// Directory to write the images to.
+    #[arg(short, long, default_value = ".")]
+    output: PathBuf,
+    /// Draw the DEFINE_BUTTON_ADVICE rectangles on top of the window.
+    #[arg(short, long)]
+    button_advice: bool,
+    /// Draw geometry with this GEOMETRY_BLEND_MODE value using one of the blend modes opaque,
+    /// alpha, additive or multiply, e.g. "--blend-mode glow=additive". Can be repeated. Geometry
+    /// with any other value is alpha blended.
+    #[arg(long = "blend-mode", value_name = "VALUE=MODE", value_parser = parse_blend_mode)]
+    blend_modes: Vec<(String, BlendMode)>,
+}
+
+fn parse_blend_mode(arg: &str) -> Result<(String, BlendMode), String> {
+    let (value, name) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Expected VALUE=MODE, got \"{}\".", arg))?;
+    let blend_mode =
+        BlendMode::from_name(name).ok_or_else(|| format!("Unknown blend mode \"{}\".", name))?;
+    Ok((value.to_string(), blend_mode))
+}
+
+fn main() {
+    let opts = Opts::parse();
+
+    let data_dir = DataDir::new(&opts.data_dir);
+    let window_bases = match data_dir
+        .open(&opts.config)
+        .map_err(|err| err.to_string())
+        .and_then(|file| ConfigReader::new(file).map_err(|err| err.to_string()))
+        .and_then(|mut reader| WindowBases::from_config(&mut reader).map_err(|err| err.to_string()))
+    {
+        Ok(window_bases) => window_bases,
+        Err(err) => {
+            eprintln!("Could not read {}: {}", opts.config, err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut textures = TextureResolver::default();
+    if let Err(err) = textures.add_root(&opts.data_dir) {
+        eprintln!("Could not index textures. {}", err);
+        std::process::exit(1);
+    }
+
+    let options = WindowRenderOptions {
+        button_advice: opts.button_advice,
+        blend_modes: opts.blend_modes,
+    };
+
+    for window_base in window_bases.window_bases.iter() {
+        let render = match render_window_base(window_base, &textures, &options) {
+            Ok(render) => render,
+            Err(err) => {
+                eprintln!("Could not render {}. {}", window_base.name, err);
+                continue;
+            }
+        };
+
+        for name in render.missing_textures.iter() {
+            eprintln!("{}: texture not found: {}", window_base.name, name);
+        }
+        for value in render.unknown_blend_modes.iter() {
+            eprintln!(
+                "{}: unknown blend mode {}, drawn with alpha blending.",
+                window_base.name, value
+            );
+        }
+
+        let png_path = opts.output.join(format!("{}.png", window_base.name));
+        match render.image.save(&png_path) {
+            Ok(()) => println!(
+                "Generated {} ({}x{})",
+                png_path.display(),
+                render.image.width(),
+                render.image.height()
+            ),
+            Err(err) => eprintln!("Could not write {}. {}", png_path.display(), err),
+        }
+
+        if opts.button_advice {
+            for advice in window_base.button_advices.iter() {
+                println!(
+                    "  {:<24} {:4} {:4} {:4} {:4}",
+                    advice.name, advice.x, advice.y, advice.dx, advice.dy
+                );
+            }
+        }
+    }
+}